@@ -4,6 +4,9 @@
 
 use std::collections::HashMap;
 
+use rocket::{
+    http::Status, response::{self, Responder, Response}, Request,
+};
 use rocket_contrib::Json;
 
 #[cfg(test)]
@@ -14,6 +17,11 @@ pub fn bad_request() -> Json<ApplicationError> {
     Json(ApplicationError::new(400, "Bad Request"))
 }
 
+#[error(401)]
+pub fn unauthorized() -> Json<ApplicationError> {
+    Json(ApplicationError::new(401, "Unauthorized"))
+}
+
 #[error(404)]
 pub fn not_found() -> Json<ApplicationError> {
     Json(ApplicationError::new(404, "Not Found"))
@@ -39,6 +47,11 @@ pub fn internal_server_error() -> Json<ApplicationError> {
     Json(ApplicationError::new(500, "Internal Server Error"))
 }
 
+#[error(503)]
+pub fn service_unavailable() -> Json<ApplicationError> {
+    Json(ApplicationError::new(503, "Service Unavailable"))
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct ApplicationError {
     pub status: u16,
@@ -64,3 +77,45 @@ impl ApplicationError {
         }
     }
 }
+
+/// An error response for request handlers.
+/// Without `retry_after` it defers to the matching error catcher,
+/// exactly like `Failure` does.
+/// With it, the response is built here instead,
+/// so that the `Retry-After` header can be included.
+#[derive(Debug, PartialEq)]
+pub struct HttpError {
+    pub status: Status,
+    pub retry_after: Option<u64>,
+}
+
+impl HttpError {
+    pub fn new(status: Status) -> HttpError {
+        HttpError {
+            status,
+            retry_after: None,
+        }
+    }
+
+    pub fn with_retry_after(status: Status, retry_after: u64) -> HttpError {
+        HttpError {
+            status,
+            retry_after: Some(retry_after),
+        }
+    }
+}
+
+impl<'r> Responder<'r> for HttpError {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self.retry_after {
+            Some(retry_after) => {
+                let body = Json(ApplicationError::new(self.status.code, self.status.reason));
+                Response::build_from(body.respond_to(request)?)
+                    .status(self.status)
+                    .raw_header("Retry-After", retry_after.to_string())
+                    .ok()
+            }
+            None => Err(self.status),
+        }
+    }
+}
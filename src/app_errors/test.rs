@@ -12,6 +12,14 @@ fn bad_request() {
     );
 }
 
+#[test]
+fn unauthorized() {
+    assert_eq!(
+        super::unauthorized().into_inner(),
+        ApplicationError::new(401, "Unauthorized")
+    );
+}
+
 #[test]
 fn not_found() {
     assert_eq!(
@@ -51,3 +59,11 @@ fn internal_server_error() {
         ApplicationError::new(500, "Internal Server Error")
     );
 }
+
+#[test]
+fn service_unavailable() {
+    assert_eq!(
+        super::service_unavailable().into_inner(),
+        ApplicationError::new(503, "Service Unavailable")
+    );
+}
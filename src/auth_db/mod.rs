@@ -16,7 +16,7 @@ use serde::{
 use settings::Settings;
 
 #[cfg(test)]
-mod test;
+pub mod test;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum BounceType {
@@ -41,7 +41,7 @@ impl<'d> Deserialize<'d> for BounceType {
             2 => Ok(BounceType::Soft),
             3 => Ok(BounceType::Complaint),
             _ => Err(D::Error::invalid_value(
-                Unexpected::Unsigned(value as u64),
+                Unexpected::Unsigned(u64::from(value)),
                 &"bounce type",
             )),
         }
@@ -107,7 +107,7 @@ impl<'d> Deserialize<'d> for BounceSubtype {
             13 => Ok(BounceSubtype::Other),
             14 => Ok(BounceSubtype::Virus),
             _ => Err(D::Error::invalid_value(
-                Unexpected::Unsigned(value as u64),
+                Unexpected::Unsigned(u64::from(value)),
                 &"bounce subtype",
             )),
         }
@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::time::SystemTime;
+use std::{sync::Mutex, time::SystemTime};
 
 use serde_json;
 
@@ -202,3 +202,54 @@ fn now_as_milliseconds() -> u64 {
         .expect("system time error");
     now.as_secs() * 1000
 }
+
+/// Records the bounces that are created,
+/// for testing code that writes to the auth db.
+pub struct DbMock {
+    pub bounces: Mutex<Vec<(String, BounceType, BounceSubtype)>>,
+}
+
+impl DbMock {
+    pub fn new() -> DbMock {
+        DbMock {
+            bounces: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Db for DbMock {
+    fn get_bounces(&self, _address: &str) -> Result<Vec<BounceRecord>, DbError> {
+        Ok(Vec::new())
+    }
+
+    fn create_bounce(
+        &self,
+        address: &str,
+        bounce_type: BounceType,
+        bounce_subtype: BounceSubtype,
+    ) -> Result<(), DbError> {
+        self.bounces
+            .lock()
+            .expect("lock error")
+            .push((address.to_string(), bounce_type, bounce_subtype));
+        Ok(())
+    }
+}
+
+/// Fails every request, as if the auth db was unavailable.
+pub struct DbMockError;
+
+impl Db for DbMockError {
+    fn get_bounces(&self, _address: &str) -> Result<Vec<BounceRecord>, DbError> {
+        Err(DbError::new(String::from("wibble")))
+    }
+
+    fn create_bounce(
+        &self,
+        _address: &str,
+        _bounce_type: BounceType,
+        _bounce_subtype: BounceSubtype,
+    ) -> Result<(), DbError> {
+        Err(DbError::new(String::from("wibble")))
+    }
+}
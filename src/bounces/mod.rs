@@ -6,8 +6,9 @@ use std::{
     collections::HashMap, error::Error, fmt::{self, Display, Formatter}, time::SystemTime,
 };
 
-use rocket::{http::Status, response::Failure};
+use rocket::http::Status;
 
+use app_errors::HttpError;
 use auth_db::{BounceRecord, BounceType, Db, DbError};
 use settings::{BounceLimit, BounceLimits, Settings};
 
@@ -62,11 +63,11 @@ impl From<DbError> for BounceError {
     }
 }
 
-impl From<BounceError> for Failure {
-    fn from(_error: BounceError) -> Failure {
+impl From<BounceError> for HttpError {
+    fn from(_error: BounceError) -> HttpError {
         // Eventually we should be able to do something richer than this,
         // as per https://github.com/SergioBenitez/Rocket/issues/586.
-        Failure(Status::TooManyRequests)
+        HttpError::new(Status::TooManyRequests)
     }
 }
 
@@ -113,7 +114,7 @@ impl<'a> Bounces<'a> {
 
 unsafe impl<'a> Sync for Bounces<'a> {}
 
-fn is_bounce_violation(count: u8, created_at: u64, now: u64, limits: &Vec<BounceLimit>) -> bool {
+fn is_bounce_violation(count: u8, created_at: u64, now: u64, limits: &[BounceLimit]) -> bool {
     for limit in limits.iter() {
         if count > limit.limit && created_at >= now - limit.period {
             return true;
@@ -227,7 +227,7 @@ fn check_db_error() {
         Err(error) => {
             assert_eq!(error.description(), "database error: wibble blee");
             assert_eq!(error.address, "");
-            if let Some(_) = error.bounce {
+            if error.bounce.is_some() {
                 assert!(false, "Error::bounce should not be set");
             }
         }
@@ -4,11 +4,20 @@
 
 use std::convert::TryFrom;
 
+use base64;
+use chrono::DateTime;
 use serde::de::{Deserialize, Deserializer, Error, Unexpected};
 
 use duration::Duration;
 use validate;
 
+pub fn aws_endpoint<'d, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize_optional(deserializer, validate::aws_endpoint, "AWS endpoint")
+}
+
 pub fn aws_region<'d, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'d>,
@@ -30,6 +39,14 @@ where
     deserialize(deserializer, validate::aws_secret, "AWS secret key")
 }
 
+pub fn base64<'d, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    let value: String = Deserialize::deserialize(deserializer)?;
+    base64::decode(&value).map_err(|_| D::Error::invalid_value(Unexpected::Str(&value), &"base64"))
+}
+
 pub fn base_uri<'d, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'d>,
@@ -37,6 +54,24 @@ where
     deserialize(deserializer, validate::base_uri, "base URI")
 }
 
+pub fn dkim_algorithm<'d, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize(
+        deserializer,
+        validate::dkim_algorithm,
+        "'rsa-sha256' or 'ed25519-sha256'",
+    )
+}
+
+pub fn dkim_selector<'d, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize(deserializer, validate::dkim_selector, "DKIM selector")
+}
+
 pub fn email_address<'d, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'d>,
@@ -61,11 +96,97 @@ where
         .map_err(|_| D::Error::invalid_value(Unexpected::Str(&value), &"duration"))
 }
 
+pub fn mailgun_api_key<'d, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize(deserializer, validate::mailgun_api_key, "Mailgun API key")
+}
+
+/// Accepts either a single string or an array of strings.
+pub fn one_or_many<'d, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    match Deserialize::deserialize(deserializer)? {
+        OneOrMany::One(value) => Ok(vec![value]),
+        OneOrMany::Many(values) => Ok(values),
+    }
+}
+
+pub fn percentage<'d, D>(deserializer: D) -> Result<u8, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    let value: u8 = Deserialize::deserialize(deserializer)?;
+    if value > 100 {
+        return Err(D::Error::invalid_value(
+            Unexpected::Unsigned(u64::from(value)),
+            &"percentage",
+        ));
+    }
+    Ok(value)
+}
+
 pub fn provider<'d, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'d>,
 {
-    deserialize(deserializer, validate::provider, "'ses' or 'smtp'")
+    let mut providers: Vec<String> = validate::PROVIDERS
+        .iter()
+        .map(|provider| format!("'{}'", provider))
+        .collect();
+    let last = providers.pop().unwrap_or_default();
+    let expected = format!("{} or {}", providers.join(", "), last);
+    deserialize(deserializer, validate::provider, &expected)
+}
+
+pub fn providers<'d, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    let values: Vec<String> = Deserialize::deserialize(deserializer)?;
+    for value in &values {
+        if !validate::provider(value) {
+            return Err(D::Error::invalid_value(
+                Unexpected::Str(value),
+                &"list of providers",
+            ));
+        }
+    }
+    Ok(values)
+}
+
+pub fn provider_error_kind<'d, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize(
+        deserializer,
+        validate::provider_error_kind,
+        "'transient', 'permanent' or 'configuration'",
+    )
+}
+
+pub fn queue<'d, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize(deserializer, validate::queue, "queue type")
+}
+
+pub fn regex<'d, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize(deserializer, validate::regex, "regular expression")
 }
 
 pub fn sender_name<'d, D>(deserializer: D) -> Result<String, D::Error>
@@ -82,6 +203,64 @@ where
     deserialize(deserializer, validate::sendgrid_api_key, "Sendgrid API key")
 }
 
+pub fn sendgrid_webhook_key<'d, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize_optional(
+        deserializer,
+        validate::sendgrid_webhook_key,
+        "Sendgrid webhook verification key",
+    )
+}
+
+pub fn ses_configuration_set<'d, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize_optional(
+        deserializer,
+        validate::ses_configuration_set,
+        "SES configuration set name",
+    )
+}
+
+pub fn smtp_security<'d, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize_optional(
+        deserializer,
+        validate::smtp_security,
+        "'none', 'opportunistic', 'required' or 'tls'",
+    )
+}
+
+pub fn sqs_url<'d, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    deserialize(deserializer, validate::sqs_url, "SQS queue URL")
+}
+
+pub fn timestamp<'d, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    let value: String = Deserialize::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&value)
+        .ok()
+        .and_then(|timestamp| {
+            let milliseconds = timestamp.timestamp_millis();
+            if milliseconds >= 0 {
+                Some(milliseconds as u64)
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| D::Error::invalid_value(Unexpected::Str(&value), &"RFC 3339 timestamp"))
+}
+
 fn deserialize<'d, D>(
     deserializer: D,
     validator: fn(&str) -> bool,
@@ -97,3 +276,22 @@ where
         Err(D::Error::invalid_value(Unexpected::Str(&value), &expected))
     }
 }
+
+fn deserialize_optional<'d, D>(
+    deserializer: D,
+    validator: fn(&str) -> bool,
+    expected: &str,
+) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'d>,
+{
+    let value: Option<String> = Deserialize::deserialize(deserializer)?;
+    match value {
+        Some(value) => if validator(&value) {
+            Ok(Some(value))
+        } else {
+            Err(D::Error::invalid_value(Unexpected::Str(&value), &expected))
+        },
+        None => Ok(None),
+    }
+}
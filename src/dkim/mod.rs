@@ -0,0 +1,266 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    error::Error, fmt::{self, Display, Formatter}, sync::Arc,
+};
+
+use base64;
+use chrono::Utc;
+use ring::{
+    digest::{self, SHA256}, error::Unspecified, rand::SystemRandom,
+    signature::{Ed25519KeyPair, RSAKeyPair, RSASigningState, RSA_PKCS1_SHA256},
+};
+use untrusted::Input;
+
+use settings::Dkim as DkimSettings;
+
+#[cfg(test)]
+mod test;
+
+/// Headers that are signed if they're present, in this order.
+pub const SIGNED_HEADERS: &[&str] = &[
+    "from",
+    "to",
+    "cc",
+    "reply-to",
+    "subject",
+    "date",
+    "message-id",
+    "mime-version",
+    "content-type",
+];
+
+/// The headers to sign for messages sent through SES,
+/// which replaces Date and Message-ID when it sends a raw message.
+pub const SES_SIGNED_HEADERS: &[&str] = &[
+    "from",
+    "to",
+    "cc",
+    "reply-to",
+    "subject",
+    "mime-version",
+    "content-type",
+];
+
+#[derive(Debug)]
+pub struct DkimError {
+    description: String,
+}
+
+impl DkimError {
+    pub fn new(description: String) -> DkimError {
+        DkimError { description }
+    }
+}
+
+impl Error for DkimError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Display for DkimError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description)
+    }
+}
+
+impl From<Unspecified> for DkimError {
+    fn from(_error: Unspecified) -> DkimError {
+        DkimError::new(String::from("DKIM signing failed"))
+    }
+}
+
+enum Key {
+    Rsa(Arc<RSAKeyPair>),
+    Ed25519(Ed25519KeyPair),
+}
+
+impl Key {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, DkimError> {
+        match *self {
+            Key::Rsa(ref key_pair) => {
+                let mut signing_state = RSASigningState::new(key_pair.clone())?;
+                let mut signature = vec![0; key_pair.public_modulus_len()];
+                signing_state.sign(&RSA_PKCS1_SHA256, &SystemRandom::new(), data, &mut signature)?;
+                Ok(signature)
+            }
+            // RFC 8463 signs the hash rather than the data itself
+            Key::Ed25519(ref key_pair) => {
+                let hash = digest::digest(&SHA256, data);
+                Ok(key_pair.sign(hash.as_ref()).as_ref().to_vec())
+            }
+        }
+    }
+}
+
+/// Adds DKIM signatures to composed messages,
+/// so that they pass DMARC alignment for the signing domain.
+pub struct Signer {
+    algorithm: String,
+    domain: String,
+    selector: String,
+    key: Key,
+    signed_headers: &'static [&'static str],
+}
+
+impl Signer {
+    /// Create a signer that signs `signed_headers`,
+    /// which should only include headers that arrive unchanged.
+    pub fn new(
+        settings: &DkimSettings,
+        signed_headers: &'static [&'static str],
+    ) -> Result<Signer, DkimError> {
+        let private_key = Input::from(settings.privatekey.as_slice());
+        let key = match settings.algorithm.as_str() {
+            "rsa-sha256" => {
+                RSAKeyPair::from_pkcs8(private_key).map(|key_pair| Key::Rsa(Arc::new(key_pair)))
+            }
+            // OpenSSL generates v1 PKCS#8 documents, which omit the public key
+            "ed25519-sha256" => {
+                Ed25519KeyPair::from_pkcs8_maybe_unchecked(private_key).map(Key::Ed25519)
+            }
+            algorithm => {
+                return Err(DkimError::new(format!(
+                    "Unsupported DKIM algorithm `{}`",
+                    algorithm
+                )))
+            }
+        }.map_err(|_| DkimError::new(String::from("Invalid DKIM private key")))?;
+
+        Ok(Signer {
+            algorithm: settings.algorithm.to_string(),
+            domain: settings.domain.to_string(),
+            selector: settings.selector.to_string(),
+            key,
+            signed_headers,
+        })
+    }
+
+    /// Sign a message, returning it with a DKIM-Signature header prepended.
+    /// Headers and body both use relaxed canonicalization,
+    /// which tolerates the whitespace changes that can happen in transit.
+    pub fn sign(&self, message: &str) -> Result<String, DkimError> {
+        let (headers, body) = split_message(message);
+        let body_hash = digest::digest(&SHA256, canonicalize_body(body).as_bytes());
+
+        let fields = parse_headers(headers);
+        let signed_fields: Vec<(&str, &str)> = self.signed_headers
+            .iter()
+            .filter_map(|signed_name| {
+                fields
+                    .iter()
+                    .rev()
+                    .find(|&&(name, _)| name.trim().eq_ignore_ascii_case(signed_name))
+                    .cloned()
+            })
+            .collect();
+
+        let mut signature = format!(
+            "v=1; a={}; c=relaxed/relaxed; d={}; s={}; t={}; h={}; bh={}; b=",
+            self.algorithm,
+            self.domain,
+            self.selector,
+            Utc::now().timestamp(),
+            signed_fields
+                .iter()
+                .map(|&(name, _)| name.trim().to_lowercase())
+                .collect::<Vec<String>>()
+                .join(":"),
+            base64::encode(body_hash.as_ref())
+        );
+
+        // The signature header itself is signed with an empty b= tag
+        // and without a trailing CRLF, as per RFC 6376 section 3.7.
+        let mut data = String::new();
+        for &(name, value) in &signed_fields {
+            data.push_str(&canonicalize_header(name, value));
+            data.push_str("\r\n");
+        }
+        data.push_str(&canonicalize_header("DKIM-Signature", &signature));
+
+        signature.push_str(&base64::encode(&self.key.sign(data.as_bytes())?));
+
+        Ok(format!("DKIM-Signature: {}\r\n{}", signature, message))
+    }
+}
+
+fn split_message(message: &str) -> (&str, &str) {
+    match message.find("\r\n\r\n") {
+        Some(index) => (&message[..index], &message[index + 4..]),
+        None => (message, ""),
+    }
+}
+
+// Returns the name and raw value of each header field,
+// with any folded lines still attached to the value.
+fn parse_headers(headers: &str) -> Vec<(&str, &str)> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+
+    for (index, _) in headers.match_indices("\r\n") {
+        let next = &headers[index + 2..];
+        if !next.starts_with(' ') && !next.starts_with('\t') {
+            push_field(&mut fields, &headers[start..index]);
+            start = index + 2;
+        }
+    }
+    push_field(&mut fields, &headers[start..]);
+
+    fields
+}
+
+fn push_field<'m>(fields: &mut Vec<(&'m str, &'m str)>, field: &'m str) {
+    if let Some(index) = field.find(':') {
+        fields.push((&field[..index], &field[index + 1..]));
+    }
+}
+
+// Relaxed header canonicalization, from RFC 6376 section 3.4.2.
+fn canonicalize_header(name: &str, value: &str) -> String {
+    format!(
+        "{}:{}",
+        name.trim().to_lowercase(),
+        collapse_whitespace(&value.replace("\r\n", "")).trim()
+    )
+}
+
+// Relaxed body canonicalization, from RFC 6376 section 3.4.4.
+fn canonicalize_body(body: &str) -> String {
+    let mut lines: Vec<String> = body.split("\r\n")
+        .map(|line| collapse_whitespace(line).trim_right().to_string())
+        .collect();
+
+    while lines.last().map_or(false, |line| line.is_empty()) {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        return String::new();
+    }
+
+    let mut canonicalized = lines.join("\r\n");
+    canonicalized.push_str("\r\n");
+    canonicalized
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    let mut collapsed = String::with_capacity(value.len());
+    let mut in_whitespace = false;
+
+    for character in value.chars() {
+        if character == ' ' || character == '\t' {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(character);
+            in_whitespace = false;
+        }
+    }
+
+    collapsed
+}
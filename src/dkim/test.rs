@@ -0,0 +1,367 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+use ring::signature::{self, ED25519, RSA_PKCS1_2048_8192_SHA256};
+
+use super::*;
+use mime;
+use providers::Message;
+use settings::Sender;
+
+// Throwaway keys, generated with:
+//   openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048 | openssl pkcs8 -topk8 -nocrypt -outform DER
+//   openssl genpkey -algorithm ED25519 -outform DER
+// The public keys are in the form that ring verifies against,
+// which for RSA is the bare PKCS#1 RSAPublicKey
+// rather than the SubjectPublicKeyInfo that DNS `p=` tags hold:
+//   openssl rsa -inform DER -RSAPublicKey_out -outform DER
+// For Ed25519 it's the raw key, which is what `p=` holds too.
+const RSA_PRIVATE_KEY: &str = "MIIEvQIBADANBgkqhkiG9w0BAQEFAASCBKcwggSjAgEAAoIBAQCcj8M1Tfq/E1j2ntlwKpAmabSM\
+     Mx3jkdYUPf3Vuvn6pRDoV+XUH26rjs9KD1EyNuY1IqcbHIYN0TH3+ajG5mV8vsVLm4mHaubiolU7\
+     2Dk8PjXQRAKTSpNhiv5T+QpN9hFOO+c3y0rVUT5UuHS52cCIIqqp668fyY9VsZfdQcezTSVs9f79\
+     XSPG8nDR6OCfZR84gMqSzCy1vNszAx9iz3orgdGMh5kLwp+2IV1aChT/T8bgC+1QtHEKKzL/n32p\
+     2+sTvlxyQqkQMJtxJiTL4z2u0RDwwCFQYdx5E0l2X+YaPKF4VGq/2hoJ8ssGLm1yrHEqdq71Gc3k\
+     yC9YPiNxnp1TAgMBAAECggEACHcpj1Sjw03bg0em6uQSSVFxPBzOIpj8d2RmuPYplX5JbMFxTEgA\
+     JvH2OW3x7ld+Q89CM+VrRjdSjrMG3O0Q/cQe95iga3HCAZfU01iSDQoxb2J4tS1/d0hvD2v+56Tv\
+     iJ2/7IoR23j6ItXv0dYkDU26kVeMxO4+0YZkd3KaxrzGfKmNasGRwixx9eCV2b7MJis0OAnvfJkA\
+     2WL/a34DaiVrozKmuHu9egjEMj9undEio+1kQ0sNE0szniFbsTlkMgkiOBFV1WuJbbHGd+Vh0lZb\
+     MPN89gsG6Rc5vA8/J5TFrBTBH5sTJKvArXRZsL5WD1BlCfie55UkLq/sKP6TOQKBgQDQZPfvPHot\
+     Th01PrGyNCD7I88C08LDtPxNvyAAAgmNpqwhgKkRPLzukW6ghCtB7ydl15miA/58AxCtf86i68Jh\
+     TZFRqiMlEB2UtRIrqRgQbA3BZYnUs5TIbRJER5R4BZTY0F6QSj9uOR+EIsD1oFdyGV599ezHJtZH\
+     bRJK/FruFwKBgQDAU5QNqQYVSiBIMayRxoI0PcY+qZHieYdaZdv8EI3AMv831wQHnkxSsRgZl7KA\
+     hWx4RCPHJPq0WL2Z5UTQooWCTWmWEq88O+M8x1toiDUgnV11yRkeuY3YoboRTHy+VFRf6pvHBkP/\
+     2dzunkpZIuEiljPuRG6VHYqRvzFSpgvsJQKBgH0oJr/H0pjjESepP4+HP0eVef54r8BaMi1/W/il\
+     QJeSQk7ctq1jjRS6w3Yj6tbuivrfQaXMQEq6jImm+LNgZJ2LuHsTc4gO5bUqCn+uToWOX7SHBwB3\
+     2iOFXjMjykdLEtokSqbd8LqCFCuHs9vRMLOWCOodiNcSvB6poxLl/LaBAoGAf5p0TqPoT1PBG2Up\
+     1AZa+QJLd+PV0OdE8KtIS3NIOU8cmhXkrJFQnkS1crV9T1w23YyIGGbml6WRFnxRLgPGhO+LsHBx\
+     J4usdvTmJvLIHP2LtsxYCFSgUizrYPLYiQc2e/USqff0QdpwhTkspvtHWEVD60AoBnN1LsIlnWph\
+     2qkCgYEAhWdCvqgEpTPwrIdemVX0lxew6gx6QJdOlW9KylhXU0U5t870Ptq0g4K1YW6R0pAWQg0o\
+     aAOuac0XQS1PKBIJDoy1er1wbGkbqU3zA4gEtHLmqPNM2Uo7/bjTnqycMm6f4+wWnC1iBiUW1pta\
+     e42xlLfQ6njC5t4S7fwD54j97hw=";
+
+const RSA_PUBLIC_KEY: &str = "MIIBCgKCAQEAnI/DNU36vxNY9p7ZcCqQJmm0jDMd45HWFD391br5+qUQ6Ffl1B9uq47PSg9RMjbm\
+     NSKnGxyGDdEx9/moxuZlfL7FS5uJh2rm4qJVO9g5PD410EQCk0qTYYr+U/kKTfYRTjvnN8tK1VE+\
+     VLh0udnAiCKqqeuvH8mPVbGX3UHHs00lbPX+/V0jxvJw0ejgn2UfOIDKkswstbzbMwMfYs96K4HR\
+     jIeZC8KftiFdWgoU/0/G4AvtULRxCisy/599qdvrE75cckKpEDCbcSYky+M9rtEQ8MAhUGHceRNJ\
+     dl/mGjyheFRqv9oaCfLLBi5tcqxxKnau9RnN5MgvWD4jcZ6dUwIDAQAB";
+
+const ED25519_PRIVATE_KEY: &str = "MC4CAQAwBQYDK2VwBCIEIBXE6Lzs9gUHhittjDE9kkxj7yk7rLTfUmjJvAc1jA4R";
+
+const ED25519_PUBLIC_KEY: &str = "pCEioQqE5suMCfBPwmkqa+uJVwA5q3FGxKTdkE76ww8=";
+
+// The Ed25519 example from RFC 8463 appendix A,
+// which checks the verifier below independently of the signer
+const RFC_8463_PUBLIC_KEY: &str = "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+
+const RFC_8463_MESSAGE: &str = "DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed;\r
+ d=football.example.com; i=@football.example.com;\r
+ q=dns/txt; s=brisbane; t=1528637909; h=from : to :\r
+ subject : date : message-id : from : subject : date;\r
+ bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;\r
+ b=/gCrinpcQOoIfuHNQIbq4pgh9kyIK3AQUdt9OdqQehSwhEIug4D11Bus\r
+ Fa3bT3FY5OsU7ZbnKELq+eXdp1Q1Dw==\r
+From: Joe SixPack <joe@football.example.com>\r
+To: Suzie Q <suzie@shopping.example.net>\r
+Subject: Is dinner ready?\r
+Date: Fri, 11 Jul 2003 21:00:37 -0700 (PDT)\r
+Message-ID: <20030712040037.46341.5F8J@football.example.com>\r
+\r
+Hi.\r
+\r
+We lost the game.  Are you hungry yet?\r
+\r
+Joe.\r
+";
+
+#[test]
+fn verify_rfc_8463_example() {
+    assert_eq!(verify(RFC_8463_MESSAGE, RFC_8463_PUBLIC_KEY), Ok(()));
+    assert_eq!(
+        verify(
+            &RFC_8463_MESSAGE.replace("Is dinner ready?", "Is lunch ready?"),
+            RFC_8463_PUBLIC_KEY
+        ),
+        Err("signature mismatch")
+    );
+}
+
+#[test]
+fn sign_rsa_sha256() {
+    let signer = create_signer("rsa-sha256", RSA_PRIVATE_KEY);
+    let signed = signer.sign(&create_message()).expect("sign error");
+
+    assert!(signed.starts_with(
+        "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d=firefox.com; s=fxa; t="
+    ));
+    assert!(signed.contains(
+        "; h=from:to:subject:date:message-id:mime-version:content-type; bh="
+    ));
+    assert_eq!(verify(&signed, RSA_PUBLIC_KEY), Ok(()));
+}
+
+#[test]
+fn sign_ed25519_sha256() {
+    let signer = create_signer("ed25519-sha256", ED25519_PRIVATE_KEY);
+    let signed = signer.sign(&create_message()).expect("sign error");
+
+    assert!(signed.starts_with(
+        "DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed; d=firefox.com; s=fxa; t="
+    ));
+    assert_eq!(verify(&signed, ED25519_PUBLIC_KEY), Ok(()));
+}
+
+#[test]
+fn verify_detects_tampering() {
+    let signer = create_signer("rsa-sha256", RSA_PRIVATE_KEY);
+    let signed = signer.sign(&create_message()).expect("sign error");
+
+    let tampered_body = signed.replace("\r\n\r\nblee\r\n", "\r\n\r\nbleh\r\n");
+    assert_ne!(tampered_body, signed);
+    assert_eq!(
+        verify(&tampered_body, RSA_PUBLIC_KEY),
+        Err("body hash mismatch")
+    );
+
+    let tampered_header = signed.replace("Subject: wibble", "Subject: wobble");
+    assert_ne!(tampered_header, signed);
+    assert_eq!(
+        verify(&tampered_header, RSA_PUBLIC_KEY),
+        Err("signature mismatch")
+    );
+
+    let signer = create_signer("ed25519-sha256", ED25519_PRIVATE_KEY);
+    let signed = signer.sign(&create_message()).expect("sign error");
+    let tampered_header = signed.replace("Subject: wibble", "Subject: wobble");
+    assert_eq!(
+        verify(&tampered_header, ED25519_PUBLIC_KEY),
+        Err("signature mismatch")
+    );
+}
+
+#[test]
+fn verify_tolerates_whitespace_changes() {
+    let signer = create_signer("ed25519-sha256", ED25519_PRIVATE_KEY);
+    let signed = signer.sign(&create_message()).expect("sign error");
+
+    let refolded = signed
+        .replace("Subject: wibble", "Subject:\r\n\t wibble ")
+        .replace("\r\n\r\nblee\r\n", "\r\n\r\nblee \t\r\n\r\n\r\n");
+    assert_ne!(refolded, signed);
+    assert_eq!(verify(&refolded, ED25519_PUBLIC_KEY), Ok(()));
+}
+
+#[test]
+fn ses_headers_survive_rewriting() {
+    let settings = create_settings("rsa-sha256", RSA_PRIVATE_KEY);
+    let signer = Signer::new(&settings, SES_SIGNED_HEADERS).expect("signer error");
+    let signed = signer.sign(&create_message()).expect("sign error");
+    assert!(signed.contains("; h=from:to:subject:mime-version:content-type; bh="));
+
+    let rewritten = rewrite_like_ses(&signed);
+    assert_ne!(rewritten, signed);
+    assert_eq!(verify(&rewritten, RSA_PUBLIC_KEY), Ok(()));
+
+    // Signing the full set of headers doesn't survive it
+    let signer = create_signer("rsa-sha256", RSA_PRIVATE_KEY);
+    let signed = signer.sign(&create_message()).expect("sign error");
+    assert_eq!(
+        verify(&rewrite_like_ses(&signed), RSA_PUBLIC_KEY),
+        Err("signature mismatch")
+    );
+}
+
+#[test]
+fn invalid_private_key() {
+    let mut settings = create_settings("rsa-sha256", RSA_PRIVATE_KEY);
+    settings.privatekey = b"wibble".to_vec();
+    assert!(Signer::new(&settings, SIGNED_HEADERS).is_err());
+
+    let settings = create_settings("ed25519-sha256", RSA_PRIVATE_KEY);
+    assert!(Signer::new(&settings, SIGNED_HEADERS).is_err());
+
+    let settings = create_settings("rsa-sha1", RSA_PRIVATE_KEY);
+    assert!(Signer::new(&settings, SIGNED_HEADERS).is_err());
+}
+
+// The examples from RFC 6376 section 3.4.5
+#[test]
+fn relaxed_canonicalization() {
+    assert_eq!(canonicalize_header("A", " X"), "a:X");
+    assert_eq!(canonicalize_header("B ", " Y\t\r\n\tZ  "), "b:Y Z");
+    assert_eq!(
+        canonicalize_body(" C \r\nD \t E\r\n\r\n\r\n"),
+        " C\r\nD E\r\n"
+    );
+    assert_eq!(canonicalize_body("\r\n\r\n"), "");
+}
+
+#[test]
+fn parse_folded_headers() {
+    let fields = parse_headers("A: X\r\nB : Y\t\r\n\tZ  \r\nC:");
+    assert_eq!(fields, vec![("A", " X"), ("B ", " Y\t\r\n\tZ  "), ("C", "")]);
+}
+
+// A minimal verifier, which checks a signature against a public key
+// as a receiving mail server would after looking it up in DNS.
+// It deliberately shares no parsing or canonicalization with the signer,
+// so that a bug there can't cancel itself out.
+fn verify(message: &str, public_key: &str) -> Result<(), &'static str> {
+    let separator = message.find("\r\n\r\n").ok_or("missing body")?;
+    let headers = &message[..separator];
+    let body = &message[separator + 4..];
+
+    // Each field is a line plus any continuation lines after it
+    let mut fields: Vec<String> = Vec::new();
+    for line in headers.split("\r\n") {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            let field = fields.last_mut().ok_or("unexpected continuation")?;
+            field.push_str("\r\n");
+            field.push_str(line);
+        } else {
+            fields.push(line.to_string());
+        }
+    }
+
+    let signature_field = fields
+        .iter()
+        .find(|field| field.to_lowercase().starts_with("dkim-signature:"))
+        .ok_or("missing signature")?
+        .clone();
+    let whitespace = Regex::new("[ \t\r\n]").unwrap();
+    let tags: HashMap<String, String> = signature_field["dkim-signature:".len()..]
+        .split(';')
+        .filter_map(|tag| {
+            let tag = whitespace.replace_all(tag, "");
+            let mut parts = tag.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => Some((name.to_string(), value.to_string())),
+                _ => None,
+            }
+        })
+        .collect();
+
+    let body_hash = digest::digest(&SHA256, relaxed_body(body).as_bytes());
+    if tags.get("bh") != Some(&base64::encode(body_hash.as_ref())) {
+        return Err("body hash mismatch");
+    }
+
+    // Fields are signed from the bottom up, and names that are listed
+    // more times than they appear contribute nothing
+    let mut data = String::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for signed_name in tags.get("h").ok_or("missing h= tag")?.split(':') {
+        let signed_name = signed_name.to_lowercase();
+        let count = seen.entry(signed_name.clone()).or_insert(0);
+        let field = fields
+            .iter()
+            .rev()
+            .filter(|field| field_name(field) == signed_name)
+            .nth(*count);
+        *count += 1;
+        if let Some(field) = field {
+            data.push_str(&relaxed_header(field));
+            data.push_str("\r\n");
+        }
+    }
+    let empty_b_tag = Regex::new("(^|;)([ \t\r\n]*b[ \t\r\n]*=)[^;]*").unwrap();
+    data.push_str(&relaxed_header(&empty_b_tag.replace(&signature_field, "$1$2")));
+
+    let public_key = base64::decode(public_key).map_err(|_| "invalid public key")?;
+    let signature_bytes =
+        base64::decode(tags.get("b").ok_or("missing b= tag")?).map_err(|_| "invalid signature")?;
+    let result = match tags.get("a").map(|algorithm| algorithm.as_str()) {
+        Some("rsa-sha256") => signature::verify(
+            &RSA_PKCS1_2048_8192_SHA256,
+            Input::from(public_key.as_slice()),
+            Input::from(data.as_bytes()),
+            Input::from(signature_bytes.as_slice()),
+        ),
+        Some("ed25519-sha256") => signature::verify(
+            &ED25519,
+            Input::from(public_key.as_slice()),
+            Input::from(digest::digest(&SHA256, data.as_bytes()).as_ref()),
+            Input::from(signature_bytes.as_slice()),
+        ),
+        _ => return Err("unsupported algorithm"),
+    };
+
+    result.map_err(|_| "signature mismatch")
+}
+
+fn field_name(field: &str) -> String {
+    field.split(':').next().unwrap_or("").trim().to_lowercase()
+}
+
+// RFC 6376 section 3.4.2
+fn relaxed_header(field: &str) -> String {
+    let whitespace = Regex::new("[ \t]+").unwrap();
+    let value = field.splitn(2, ':').nth(1).unwrap_or("").replace("\r\n", "");
+    format!(
+        "{}:{}",
+        field_name(field),
+        whitespace.replace_all(&value, " ").trim()
+    )
+}
+
+// RFC 6376 section 3.4.4
+fn relaxed_body(body: &str) -> String {
+    let whitespace = Regex::new("[ \t]+").unwrap();
+    let mut lines: Vec<String> = body
+        .split("\r\n")
+        .map(|line| whitespace.replace_all(line, " ").trim_right().to_string())
+        .collect();
+    while lines.last().map_or(false, |line| line.is_empty()) {
+        lines.pop();
+    }
+
+    lines
+        .iter()
+        .map(|line| format!("{}\r\n", line))
+        .collect()
+}
+
+// SES sets its own Date and Message-ID when it sends a raw message
+fn rewrite_like_ses(message: &str) -> String {
+    let date = Regex::new("\r\nDate: [^\r]+").unwrap();
+    let message_id = Regex::new("\r\nMessage-ID: [^\r]+").unwrap();
+    let message = date.replace(message, "\r\nDate: Mon, 2 Jul 2018 09:00:00 +0000");
+    message_id
+        .replace(
+            &message,
+            "\r\nMessage-ID: <0102016459e1c9d8-deadbeef-000000@eu-west-1.amazonses.com>",
+        )
+        .into_owned()
+}
+
+fn create_message() -> String {
+    mime::compose(
+        &Message {
+            from: Sender {
+                address: String::from("accounts@firefox.com"),
+                name: String::from("Firefox Accounts"),
+            },
+            to: vec![String::from("foo@example.com")],
+            subject: String::from("wibble"),
+            body_text: String::from("blee"),
+            ..Message::default()
+        },
+        "deadbeef@firefox.com",
+    )
+}
+
+fn create_settings(algorithm: &str, private_key: &str) -> DkimSettings {
+    DkimSettings {
+        algorithm: algorithm.to_string(),
+        domain: String::from("firefox.com"),
+        selector: String::from("fxa"),
+        privatekey: base64::decode(private_key).expect("base64 error"),
+    }
+}
+
+fn create_signer(algorithm: &str, private_key: &str) -> Signer {
+    Signer::new(&create_settings(algorithm, private_key), SIGNED_HEADERS).expect("signer error")
+}
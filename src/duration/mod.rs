@@ -13,7 +13,8 @@ mod test;
 
 // Durations are measured in milliseconds, to play nicely with
 // the rest of the FxA ecosystem
-const SECOND: u64 = 1000;
+const MILLISECOND: u64 = 1;
+const SECOND: u64 = MILLISECOND * 1000;
 const MINUTE: u64 = SECOND * 60;
 const HOUR: u64 = MINUTE * 60;
 const DAY: u64 = HOUR * 24;
@@ -23,7 +24,7 @@ const YEAR: u64 = DAY * 365;
 
 lazy_static! {
     static ref DURATION_FORMAT: Regex =
-        Regex::new("^(?:([0-9]+) )?(second|minute|hour|day|week|month|year)s?$").unwrap();
+        Regex::new("^(?:([0-9]+) )?(millisecond|second|minute|hour|day|week|month|year)s?$").unwrap();
 }
 
 #[derive(Debug)]
@@ -65,6 +66,7 @@ impl<'v> TryFrom<&'v str> for Duration {
         if let Some(matches) = DURATION_FORMAT.captures(value) {
             if let Ok(multiplier) = matches.get(1).map_or(Ok(1), |m| m.as_str().parse::<u64>()) {
                 return match matches.get(2).map_or("", |m| m.as_str()) {
+                    "millisecond" => Ok(Duration(multiplier * MILLISECOND)),
                     "second" => Ok(Duration(multiplier * SECOND)),
                     "minute" => Ok(Duration(multiplier * MINUTE)),
                     "hour" => Ok(Duration(multiplier * HOUR)),
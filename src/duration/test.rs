@@ -6,6 +6,11 @@ use duration::*;
 
 #[test]
 fn without_multipliers() {
+    match Duration::try_from("millisecond") {
+        Ok(duration) => assert_eq!(duration.into(): u64, 1),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
     match Duration::try_from("second") {
         Ok(duration) => assert_eq!(duration.into(): u64, 1000),
         Err(error) => assert!(false, error.description().to_string()),
@@ -17,32 +22,32 @@ fn without_multipliers() {
     }
 
     match Duration::try_from("minute") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 60000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 60_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("hour") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 3600000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 3_600_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("day") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 86400000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 86_400_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("week") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 604800000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 604_800_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("month") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 2592000000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 2_592_000_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("year") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 31536000000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 31_536_000_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 }
@@ -59,6 +64,11 @@ fn with_multipliers() {
         Err(error) => assert!(false, error.description().to_string()),
     }
 
+    match Duration::try_from("250 milliseconds") {
+        Ok(duration) => assert_eq!(duration.into(): u64, 250),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
     match Duration::try_from("3 seconds") {
         Ok(duration) => assert_eq!(duration.into(): u64, 3000),
         Err(error) => assert!(false, error.description().to_string()),
@@ -70,32 +80,32 @@ fn with_multipliers() {
     }
 
     match Duration::try_from("2 minutes") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 120000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 120_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("2 hours") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 7200000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 7_200_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("2 days") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 172800000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 172_800_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("2 weeks") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 1209600000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 1_209_600_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("2 months") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 5184000000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 5_184_000_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 
     match Duration::try_from("2 years") {
-        Ok(duration) => assert_eq!(duration.into(): u64, 63072000000),
+        Ok(duration) => assert_eq!(duration.into(): u64, 63_072_000_000),
         Err(error) => assert!(false, error.description().to_string()),
     }
 }
@@ -0,0 +1,140 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Receives events that providers post back to us,
+//! which is how Sendgrid reports bounces and complaints.
+//! They're recorded in the auth db like SES notifications are,
+//! and update the status of their message
+//! if `status.directory` is set.
+
+use std::{error::Error, fmt::{self, Display, Formatter}, io::Read};
+
+use chrono::Utc;
+use rocket::{data::{self, FromData}, http::Status, Data, Outcome, Request};
+use serde_json;
+
+use self::sendgrid::{Event, Events};
+use app_errors::HttpError;
+use auth_db::{DbClient, DbError};
+use settings::Settings;
+use status::{StatusError, StatusStore};
+
+mod sendgrid;
+
+#[cfg(test)]
+mod test;
+
+// Sendgrid posts batches of up to 768 kilobytes
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+const SENDGRID_SIGNATURE_HEADER: &str = "X-Twilio-Email-Event-Webhook-Signature";
+const SENDGRID_TIMESTAMP_HEADER: &str = "X-Twilio-Email-Event-Webhook-Timestamp";
+
+lazy_static! {
+    static ref SETTINGS: Settings = Settings::new().expect("config error");
+    static ref DB: DbClient = DbClient::new(&SETTINGS);
+    static ref STATUS: Option<StatusStore> = SETTINGS.status.as_ref().map(StatusStore::new);
+    static ref EVENTS: Events<'static> = Events::new(Box::new(&*DB), STATUS.as_ref());
+}
+
+#[derive(Debug)]
+pub struct EventError {
+    description: String,
+}
+
+impl EventError {
+    pub fn new(description: String) -> EventError {
+        EventError { description }
+    }
+}
+
+impl Error for EventError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Display for EventError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description)
+    }
+}
+
+impl From<DbError> for EventError {
+    fn from(error: DbError) -> EventError {
+        EventError::new(format!("database error: {}", error.description()))
+    }
+}
+
+impl From<StatusError> for EventError {
+    fn from(error: StatusError) -> EventError {
+        EventError::new(format!("status error: {}", error.description()))
+    }
+}
+
+impl From<EventError> for HttpError {
+    fn from(_error: EventError) -> HttpError {
+        // Anything other than a 2xx makes Sendgrid retry later
+        HttpError::new(Status::InternalServerError)
+    }
+}
+
+/// A batch of Sendgrid events whose signature has been verified.
+#[derive(Debug)]
+struct SendgridEvents(Vec<Event>);
+
+impl FromData for SendgridEvents {
+    type Error = ();
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Self, Self::Error> {
+        // Without a key there's nothing to verify against,
+        // so the route behaves as if it wasn't there
+        let key = match SETTINGS
+            .sendgrid
+            .as_ref()
+            .and_then(|sendgrid| sendgrid.webhookkey.as_ref())
+        {
+            Some(key) => key,
+            None => return Outcome::Failure((Status::NotFound, ())),
+        };
+
+        let headers = request.headers();
+        let (signature, timestamp) = match (
+            headers.get_one(SENDGRID_SIGNATURE_HEADER),
+            headers.get_one(SENDGRID_TIMESTAMP_HEADER),
+        ) {
+            (Some(signature), Some(timestamp)) => (signature, timestamp),
+            _ => return Outcome::Failure((Status::Unauthorized, ())),
+        };
+
+        // The signature covers the exact bytes that were sent,
+        // so the body has to be read before it's parsed
+        let mut body = Vec::new();
+        if data.open().take(MAX_BODY_SIZE).read_to_end(&mut body).is_err() {
+            return Outcome::Failure((Status::BadRequest, ()));
+        }
+
+        if !sendgrid::verify(key, timestamp, &body, signature, Utc::now().timestamp()) {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        }
+
+        match serde_json::from_slice(&body) {
+            Ok(events) => Outcome::Success(SendgridEvents(events)),
+            Err(_error) => Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
+
+/// Sendgrid's Event Webhook.
+/// Statuses are recorded under the id that `/send` returned,
+/// which is `sendgrid:` followed by the start of `sg_message_id`.
+#[post("/events/sendgrid", format = "application/json", data = "<events>")]
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn sendgrid(events: SendgridEvents) -> Result<(), HttpError> {
+    EVENTS.record(&events.0).map_err(|error| {
+        // TODO: replace this with proper logging when we have it
+        println!("{}", error);
+        From::from(error)
+    })
+}
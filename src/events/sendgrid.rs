@@ -0,0 +1,207 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::boxed::Box;
+
+use base64;
+use chrono::{TimeZone, Utc};
+use ring::signature::{self, ECDSA_P256_SHA256_ASN1};
+use untrusted::Input;
+
+use super::EventError;
+use auth_db::{BounceSubtype, BounceType, Db};
+use status::{self, MessageStatus, StatusStore};
+
+// Sendgrid's verification key is a DER-encoded SubjectPublicKeyInfo,
+// but ring wants the bare point that follows this much of it
+const KEY_HEADER_LENGTH: usize = 26;
+
+// How far a signed timestamp can be from our clock, in seconds,
+// before the request is treated as a replay
+const MAX_TIMESTAMP_SKEW: i64 = 300;
+
+/// An event from Sendgrid's Event Webhook.
+/// Each type of event has its own extra fields,
+/// of which only the ones we use are deserialized.
+#[derive(Debug, Deserialize)]
+pub struct Event {
+    pub email: String,
+    pub timestamp: i64,
+    pub event: String,
+    pub sg_message_id: Option<String>,
+    /// `bounce` or `blocked`, for bounce events.
+    #[serde(rename = "type")]
+    pub bounce_type: Option<String>,
+    /// The enhanced SMTP status code, for bounce events.
+    pub status: Option<String>,
+    /// Why the message was dropped, for dropped events.
+    pub reason: Option<String>,
+    /// The receiving server's response, for delivered events.
+    pub response: Option<String>,
+}
+
+impl Event {
+    /// The id that Sendgrid returned in `X-Message-Id`
+    /// when the message was sent,
+    /// without the prefix that `/send` adds to it.
+    /// `sg_message_id` is that id followed by a `.`
+    /// and some details of Sendgrid's internal routing.
+    pub fn message_id(&self) -> Option<&str> {
+        self.sg_message_id
+            .as_ref()
+            .map(|sg_message_id| match sg_message_id.find('.') {
+                Some(index) => &sg_message_id[..index],
+                None => sg_message_id.as_str(),
+            })
+    }
+
+    /// When the event happened,
+    /// in the same format as the other message statuses.
+    pub fn time(&self) -> Option<String> {
+        Utc.timestamp_opt(self.timestamp, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+    }
+
+    /// How the event should be recorded in the auth db, if at all.
+    pub fn bounce(&self) -> Option<(BounceType, BounceSubtype)> {
+        match self.event.as_ref() {
+            "bounce" => Some(self.classify_bounce()),
+            "dropped" => self.classify_drop(),
+            "spamreport" => Some((BounceType::Complaint, BounceSubtype::Abuse)),
+            _ => None,
+        }
+    }
+
+    // Only drops that say something new about the address are recorded.
+    // Drops for spam reports were recorded when the report was made
+    // and the rest, e.g. unsubscribes or invalid SMTPAPI headers,
+    // aren't a reason to stop sending to the address.
+    fn classify_drop(&self) -> Option<(BounceType, BounceSubtype)> {
+        match self.reason.as_ref().map(|reason| reason.as_str()) {
+            Some("Bounced Address") => Some((BounceType::Hard, BounceSubtype::Suppressed)),
+            Some("Invalid") => Some((BounceType::Hard, BounceSubtype::NoEmail)),
+            _ => None,
+        }
+    }
+
+    // Blocks are rejections that may not happen again,
+    // e.g. because of the sending IP's reputation,
+    // so only bounces with a permanent status code are hard.
+    fn classify_bounce(&self) -> (BounceType, BounceSubtype) {
+        if self
+            .bounce_type
+            .as_ref()
+            .map_or(false, |bounce_type| bounce_type == "blocked")
+        {
+            (BounceType::Soft, BounceSubtype::Undetermined)
+        } else if self
+            .status
+            .as_ref()
+            .map_or(true, |status| status.starts_with('5'))
+        {
+            (BounceType::Hard, BounceSubtype::General)
+        } else {
+            (BounceType::Soft, BounceSubtype::General)
+        }
+    }
+}
+
+/// Checks the signature that Sendgrid sends with each request,
+/// which covers the timestamp header followed by the raw body.
+/// Requests whose timestamp is too far from `now`, in seconds since the epoch,
+/// are rejected so that captured requests can't be replayed later.
+pub fn verify(key: &str, timestamp: &str, body: &[u8], signature: &str, now: i64) -> bool {
+    let fresh = timestamp
+        .parse::<i64>()
+        .map(|timestamp| (now - timestamp).abs() <= MAX_TIMESTAMP_SKEW)
+        .unwrap_or(false);
+    if !fresh {
+        return false;
+    }
+
+    match (base64::decode(key), base64::decode(signature)) {
+        (Ok(ref key), Ok(ref signature)) if key.len() > KEY_HEADER_LENGTH => {
+            let mut message = timestamp.as_bytes().to_vec();
+            message.extend_from_slice(body);
+            signature::verify(
+                &ECDSA_P256_SHA256_ASN1,
+                Input::from(&key[KEY_HEADER_LENGTH..]),
+                Input::from(message.as_slice()),
+                Input::from(signature.as_slice()),
+            ).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// Records Sendgrid events in the auth db,
+/// so that `Bounces::check` can see them,
+/// and in the status store if there is one.
+pub struct Events<'a> {
+    db: Box<&'a Db>,
+    status: Option<&'a StatusStore>,
+}
+
+impl<'a> Events<'a> {
+    pub fn new(db: Box<&'a Db>, status: Option<&'a StatusStore>) -> Events<'a> {
+        Events { db, status }
+    }
+
+    /// Sendgrid retries the whole batch if any of it fails,
+    /// so events before the failure may be recorded twice.
+    pub fn record(&self, events: &[Event]) -> Result<(), EventError> {
+        for event in events.iter() {
+            self.record_event(event)?;
+        }
+
+        Ok(())
+    }
+
+    // As with SES notifications, the status is updated first
+    // so that it's not left behind if the auth db fails.
+    // Events that don't affect either of them, e.g. opens, are ignored.
+    fn record_event(&self, event: &Event) -> Result<(), EventError> {
+        match event.event.as_ref() {
+            "bounce" | "dropped" => {
+                self.record_status(event, |status| status.bounced_at = event.time())?;
+                self.record_bounce(event)
+            }
+            "spamreport" => {
+                self.record_status(event, |status| status.complained_at = event.time())?;
+                self.record_bounce(event)
+            }
+            "delivered" => self.record_status(event, |status| {
+                status.delivered_at = event.time();
+                status.smtp_response = event.response.clone();
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_status<F>(&self, event: &Event, update: F) -> Result<(), EventError>
+    where
+        F: FnOnce(&mut MessageStatus),
+    {
+        if let (Some(store), Some(message_id)) = (self.status, event.message_id()) {
+            let message_id = status::message_id("sendgrid", message_id);
+            let status = store.update(&message_id, update)?;
+            // TODO: replace this with proper logging when we have it
+            println!("message {} is {:?}", message_id, status.state());
+        }
+
+        Ok(())
+    }
+
+    fn record_bounce(&self, event: &Event) -> Result<(), EventError> {
+        if let Some((bounce_type, bounce_subtype)) = event.bounce() {
+            self.db
+                .create_bounce(&event.email, bounce_type, bounce_subtype)?;
+        }
+
+        Ok(())
+    }
+}
+
+unsafe impl<'a> Sync for Events<'a> {}
@@ -0,0 +1,289 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::error::Error;
+
+use rocket::{self, http::{ContentType, Header, Status}, local::Client};
+use serde_json;
+
+use super::sendgrid::{verify, Event, Events};
+use app_errors::{self, ApplicationError};
+use auth_db::{
+    test::{DbMock, DbMockError}, BounceSubtype, BounceType,
+};
+use settings::test::TemporaryDirectory;
+use status::{test::create_store, State};
+
+const KEY: &str = "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEfuU+70dabBAP2ZU/jQODTgI9QKe2uV8LRuVnrgAWeOZ8PLqWS/SxczSlNqgn6DSwzmKDjXBg2w1mhAlRQrQngg==";
+
+const TIMESTAMP: &str = "1527811200";
+
+const NOW: i64 = 1_527_811_260;
+
+const BODY: &str = r#"[{"email":"foo@example.com","timestamp":1527811200,"event":"bounce","sg_event_id":"ZGVhZGJlZWY","sg_message_id":"deadbeef.filter0001.1234.5B108A8C1.0","type":"bounce","status":"5.0.0","reason":"550 5.1.1 unknown user"}]"#;
+
+// Signed with the private half of KEY, over TIMESTAMP followed by BODY
+const SIGNATURE: &str = "MEUCIQCG/RyfOORS8xDxJrEqe2d2X3q048zsh4JI4HqUVokidwIgb3SogLryV6R37UUlB1wjcGXlPksieKidI9/b7qgln4I=";
+
+#[test]
+fn verify_signature() {
+    assert!(verify(KEY, TIMESTAMP, BODY.as_bytes(), SIGNATURE, NOW));
+}
+
+#[test]
+fn invalid_signature() {
+    assert!(!verify(KEY, "1527811201", BODY.as_bytes(), SIGNATURE, NOW));
+    assert!(!verify(
+        KEY,
+        TIMESTAMP,
+        BODY.replace("5.0.0", "4.0.0").as_bytes(),
+        SIGNATURE,
+        NOW
+    ));
+    assert!(!verify(KEY, TIMESTAMP, BODY.as_bytes(), "wibble", NOW));
+    assert!(!verify(KEY, TIMESTAMP, BODY.as_bytes(), "", NOW));
+    assert!(!verify("", TIMESTAMP, BODY.as_bytes(), SIGNATURE, NOW));
+}
+
+#[test]
+fn stale_signature() {
+    assert!(verify(KEY, TIMESTAMP, BODY.as_bytes(), SIGNATURE, 1_527_811_500));
+    assert!(verify(KEY, TIMESTAMP, BODY.as_bytes(), SIGNATURE, 1_527_810_900));
+    assert!(!verify(KEY, TIMESTAMP, BODY.as_bytes(), SIGNATURE, 1_527_811_501));
+    assert!(!verify(KEY, TIMESTAMP, BODY.as_bytes(), SIGNATURE, 1_527_810_899));
+    assert!(!verify(KEY, "wibble", BODY.as_bytes(), SIGNATURE, NOW));
+}
+
+#[test]
+fn parse_event() {
+    let events: Vec<Event> = serde_json::from_str(BODY).expect("JSON error");
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.email, "foo@example.com");
+    assert_eq!(event.event, "bounce");
+    assert_eq!(event.message_id(), Some("deadbeef"));
+    assert_eq!(event.time(), Some(String::from("2018-06-01T00:00:00.000Z")));
+    assert_eq!(event.status, Some(String::from("5.0.0")));
+}
+
+#[test]
+fn message_id() {
+    let mut event = create_event("delivered");
+    assert_eq!(event.message_id(), Some("deadbeef"));
+    event.sg_message_id = Some(String::from("deadbeef"));
+    assert_eq!(event.message_id(), Some("deadbeef"));
+    event.sg_message_id = None;
+    assert_eq!(event.message_id(), None);
+}
+
+#[test]
+fn map_events() {
+    let mut event = create_event("bounce");
+    assert_eq!(
+        event.bounce(),
+        Some((BounceType::Hard, BounceSubtype::General))
+    );
+    event.status = Some(String::from("4.0.0"));
+    assert_eq!(
+        event.bounce(),
+        Some((BounceType::Soft, BounceSubtype::General))
+    );
+    event.bounce_type = Some(String::from("blocked"));
+    assert_eq!(
+        event.bounce(),
+        Some((BounceType::Soft, BounceSubtype::Undetermined))
+    );
+    let mut event = create_event("dropped");
+    assert_eq!(
+        event.bounce(),
+        Some((BounceType::Hard, BounceSubtype::Suppressed))
+    );
+    event.reason = Some(String::from("Invalid"));
+    assert_eq!(
+        event.bounce(),
+        Some((BounceType::Hard, BounceSubtype::NoEmail))
+    );
+    event.reason = Some(String::from("Unsubscribed Address"));
+    assert_eq!(event.bounce(), None);
+    event.reason = Some(String::from("Spam Reporting Address"));
+    assert_eq!(event.bounce(), None);
+    event.reason = Some(String::from("Invalid SMTPAPI header"));
+    assert_eq!(event.bounce(), None);
+    event.reason = None;
+    assert_eq!(event.bounce(), None);
+    assert_eq!(
+        create_event("spamreport").bounce(),
+        Some((BounceType::Complaint, BounceSubtype::Abuse))
+    );
+    assert_eq!(create_event("delivered").bounce(), None);
+    assert_eq!(create_event("open").bounce(), None);
+}
+
+#[test]
+fn record_events() {
+    let db = DbMock::new();
+    let directory = TemporaryDirectory::new("events");
+    let store = create_store(&directory);
+    let events = Events::new(Box::new(&db), Some(&store));
+
+    let mut delivered = create_event("delivered");
+    delivered.response = Some(String::from("250 ok"));
+    let mut unsubscribed = create_event("dropped");
+    unsubscribed.reason = Some(String::from("Unsubscribed Address"));
+    let mut spamreport = create_event("spamreport");
+    spamreport.timestamp += 1;
+    spamreport.sg_message_id = Some(String::from("baadf00d.filter0002.5678.5B108A8C2.0"));
+
+    events
+        .record(&[
+            delivered,
+            create_event("bounce"),
+            create_event("dropped"),
+            unsubscribed,
+            create_event("open"),
+            spamreport,
+        ])
+        .expect("record error");
+
+    assert_eq!(
+        *db.bounces.lock().expect("lock error"),
+        vec![
+            (
+                String::from("foo@example.com"),
+                BounceType::Hard,
+                BounceSubtype::General,
+            ),
+            (
+                String::from("foo@example.com"),
+                BounceType::Hard,
+                BounceSubtype::Suppressed,
+            ),
+            (
+                String::from("foo@example.com"),
+                BounceType::Complaint,
+                BounceSubtype::Abuse,
+            ),
+        ]
+    );
+
+    let status = store
+        .get("sendgrid:deadbeef")
+        .expect("get error")
+        .expect("status should be present");
+    assert_eq!(status.state(), State::Bounced);
+    assert_eq!(
+        status.delivered_at,
+        Some(String::from("2018-06-01T00:00:00.000Z"))
+    );
+    assert_eq!(status.smtp_response, Some(String::from("250 ok")));
+    assert_eq!(
+        status.bounced_at,
+        Some(String::from("2018-06-01T00:00:00.000Z"))
+    );
+
+    let status = store
+        .get("sendgrid:baadf00d")
+        .expect("get error")
+        .expect("status should be present");
+    assert_eq!(status.state(), State::Complained);
+    assert_eq!(
+        status.complained_at,
+        Some(String::from("2018-06-01T00:00:01.000Z"))
+    );
+}
+
+#[test]
+fn record_events_without_status() {
+    let db = DbMock::new();
+    let events = Events::new(Box::new(&db), None);
+
+    let mut event = create_event("bounce");
+    event.sg_message_id = None;
+    events.record(&[event]).expect("record error");
+
+    assert_eq!(db.bounces.lock().expect("lock error").len(), 1);
+}
+
+#[test]
+fn record_events_db_error() {
+    let db = DbMockError;
+    let directory = TemporaryDirectory::new("events");
+    let store = create_store(&directory);
+    let events = Events::new(Box::new(&db), Some(&store));
+
+    match events.record(&[create_event("bounce")]) {
+        Ok(_) => assert!(false, "Events::record should have failed"),
+        Err(error) => assert_eq!(error.description(), "database error: wibble"),
+    }
+
+    // The status was updated before the auth db failed
+    let status = store
+        .get("sendgrid:deadbeef")
+        .expect("get error")
+        .expect("status should be present");
+    assert_eq!(status.state(), State::Bounced);
+}
+
+#[test]
+fn unconfigured_sendgrid_events() {
+    let client = setup();
+
+    let mut response = client
+        .post("/events/sendgrid")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "X-Twilio-Email-Event-Webhook-Signature",
+            SIGNATURE,
+        ))
+        .header(Header::new(
+            "X-Twilio-Email-Event-Webhook-Timestamp",
+            TIMESTAMP,
+        ))
+        .body(BODY)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(404, "Not Found"));
+}
+
+fn setup() -> Client {
+    let server = rocket::ignite()
+        .mount("/", routes![super::sendgrid])
+        .catch(errors![
+            app_errors::bad_request,
+            app_errors::unauthorized,
+            app_errors::not_found,
+            app_errors::internal_server_error
+        ]);
+
+    Client::new(server).unwrap()
+}
+
+fn create_event(event: &str) -> Event {
+    Event {
+        email: String::from("foo@example.com"),
+        timestamp: 1_527_811_200,
+        event: event.to_string(),
+        sg_message_id: Some(String::from("deadbeef.filter0001.1234.5B108A8C1.0")),
+        bounce_type: if event == "bounce" {
+            Some(String::from("bounce"))
+        } else {
+            None
+        },
+        status: if event == "bounce" {
+            Some(String::from("5.0.0"))
+        } else {
+            None
+        },
+        reason: if event == "dropped" {
+            Some(String::from("Bounced Address"))
+        } else {
+            None
+        },
+        response: None,
+    }
+}
@@ -0,0 +1,265 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use base64;
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+
+use providers::{Attachment, Disposition, Message};
+
+#[cfg(test)]
+mod test;
+
+// Header lines should be at most 78 characters and must be at most 998,
+// not counting the CRLF, as per RFC 5322 section 2.1.1
+const LINE_LENGTH: usize = 78;
+const MAX_LINE_LENGTH: usize = 998;
+
+// Encoded-words can be at most 75 characters, as per RFC 2047 section 2,
+// which leaves room for 45 bytes of base64-encoded text in each one
+const ENCODED_WORD_BYTES: usize = 45;
+
+/// Compose a complete message, ready to be handed to a provider.
+///
+/// The body is a single text/plain part if there is no HTML,
+/// otherwise a multipart/alternative with the HTML last.
+/// Inline attachments are wrapped with the HTML in a multipart/related,
+/// and any other attachments go in an outer multipart/mixed.
+/// Bcc recipients are deliberately absent,
+/// they must be passed to the provider as envelope recipients.
+pub fn compose(email: &Message, message_id: &str) -> String {
+    let mut message = String::new();
+    push_header(
+        &mut message,
+        "From",
+        &format_address(&email.from.name, &email.from.address),
+    );
+    push_header(&mut message, "To", &email.to.join(", "));
+    if !email.cc.is_empty() {
+        push_header(&mut message, "Cc", &email.cc.join(", "));
+    }
+    if let Some(ref reply_to) = email.reply_to {
+        push_header(&mut message, "Reply-To", reply_to);
+    }
+    push_header(&mut message, "Subject", &encode_header_value(&email.subject));
+    push_header(&mut message, "Date", &Utc::now().to_rfc2822());
+    push_header(&mut message, "Message-ID", &format!("<{}>", message_id));
+    for (name, value) in &email.headers {
+        push_header(&mut message, name, &encode_header_value(value));
+    }
+    push_header(&mut message, "MIME-Version", "1.0");
+
+    let (inline, mut attached): (Vec<&Attachment>, Vec<&Attachment>) = email
+        .attachments
+        .iter()
+        .partition(|attachment| attachment.disposition == Disposition::Inline);
+
+    let mut body = text_part("text/plain", &email.body_text);
+    if let Some(ref html) = email.body_html {
+        let mut html = text_part("text/html", html);
+        if !inline.is_empty() {
+            let mut parts = vec![html];
+            parts.extend(inline.iter().map(|attachment| attachment_part(attachment)));
+            html = multipart("related", &parts);
+        }
+        body = multipart("alternative", &[body, html]);
+    } else {
+        // Without HTML there's nothing to refer to inline attachments
+        attached.extend(inline);
+    }
+
+    if !attached.is_empty() {
+        let mut parts = vec![body];
+        parts.extend(attached.iter().map(|attachment| attachment_part(attachment)));
+        body = multipart("mixed", &parts);
+    }
+
+    message.push_str(&body);
+    message.push_str("\r\n");
+
+    message
+}
+
+/// Format a mailbox with its display name,
+/// quoting or encoding the name as necessary.
+pub fn format_address(name: &str, address: &str) -> String {
+    if name.is_ascii() {
+        format!(
+            "\"{}\" <{}>",
+            name.replace('\\', "\\\\").replace('"', "\\\""),
+            address
+        )
+    } else {
+        format!("{} <{}>", encode_word(name), address)
+    }
+}
+
+/// Encode a header value as RFC 2047 encoded-words,
+/// unless it's plain ASCII already.
+/// Values containing line breaks are always encoded,
+/// so that they can't start new headers or the body.
+/// So are values containing a word too long to fold onto a line of its own.
+pub fn encode_header_value(value: &str) -> String {
+    if value.is_ascii()
+        && !value.contains(|c| c == '\r' || c == '\n')
+        && value.split(' ').all(|word| word.len() < MAX_LINE_LENGTH)
+    {
+        value.to_string()
+    } else {
+        encode_word(value)
+    }
+}
+
+/// Split a value into as many encoded-words as it needs,
+/// separated by spaces so that the header can be folded between them.
+/// Characters are never split across words.
+fn encode_word(value: &str) -> String {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for (index, character) in value.char_indices() {
+        if index + character.len_utf8() - start > ENCODED_WORD_BYTES {
+            chunks.push(&value[start..index]);
+            start = index;
+        }
+    }
+    chunks.push(&value[start..]);
+
+    chunks
+        .iter()
+        .map(|chunk| format!("=?UTF-8?B?{}?=", base64::encode(chunk)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Encode text as quoted-printable, normalising line endings to CRLF.
+pub fn quoted_printable(content: &str) -> String {
+    let mut encoded = String::new();
+    let normalised = content.replace("\r\n", "\n");
+
+    for (index, line) in normalised.split('\n').enumerate() {
+        if index > 0 {
+            encoded.push_str("\r\n");
+        }
+
+        let bytes = line.as_bytes();
+        let mut line_length = 0;
+        for (position, byte) in bytes.iter().enumerate() {
+            let is_last = position == bytes.len() - 1;
+            let token = match *byte {
+                // Trailing whitespace would be stripped in transit
+                b' ' | b'\t' if !is_last => (*byte as char).to_string(),
+                33...60 | 62...126 => (*byte as char).to_string(),
+                _ => format!("={:02X}", byte),
+            };
+
+            // Lines may be 76 characters long, including the soft break
+            if line_length + token.len() > 75 {
+                encoded.push_str("=\r\n");
+                line_length = 0;
+            }
+
+            encoded.push_str(&token);
+            line_length += token.len();
+        }
+    }
+
+    encoded
+}
+
+/// Append a header, folding it before spaces to keep lines short.
+/// Lines are only left longer than 78 characters
+/// when they contain a single word that can't be split,
+/// which `encode_header_value` keeps within the 998-character limit.
+fn push_header(message: &mut String, name: &str, value: &str) {
+    message.push_str(name);
+    message.push(':');
+    let mut line_length = name.len() + 1;
+
+    for word in value.split(' ') {
+        if !word.is_empty() && line_length > 0 && line_length + 1 + word.len() > LINE_LENGTH {
+            message.push_str("\r\n");
+            line_length = 0;
+        }
+
+        message.push(' ');
+        message.push_str(word);
+        line_length += 1 + word.len();
+    }
+
+    message.push_str("\r\n");
+}
+
+fn text_part(content_type: &str, content: &str) -> String {
+    format!(
+        "Content-Type: {}; charset=UTF-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n{}",
+        content_type,
+        quoted_printable(content)
+    )
+}
+
+fn attachment_part(attachment: &Attachment) -> String {
+    let mut part = format!(
+        "Content-Type: {}; {}\r\n",
+        attachment.content_type,
+        format_parameter("name", &attachment.filename)
+    );
+    part.push_str(&format!(
+        "Content-Disposition: {}; {}\r\n",
+        attachment.disposition,
+        format_parameter("filename", &attachment.filename)
+    ));
+    if let Some(ref content_id) = attachment.content_id {
+        part.push_str(&format!("Content-ID: <{}>\r\n", content_id));
+    }
+    part.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+    part.push_str(&base64::encode_config(&attachment.content, base64::MIME));
+    part
+}
+
+// Non-ASCII parameter values are percent-encoded, as per RFC 2231
+fn format_parameter(name: &str, value: &str) -> String {
+    if value.is_ascii() {
+        format!("{}=\"{}\"", name, value)
+    } else {
+        let encoded: String = value
+            .bytes()
+            .map(|byte| match byte {
+                b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{:02X}", byte),
+            })
+            .collect();
+        format!("{}*=UTF-8''{}", name, encoded)
+    }
+}
+
+fn multipart(subtype: &str, parts: &[String]) -> String {
+    let boundary = generate_boundary();
+    let mut multipart = format!(
+        "Content-Type: multipart/{}; boundary=\"{}\"\r\n\r\n",
+        subtype, boundary
+    );
+    for part in parts {
+        multipart.push_str(&format!("--{}\r\n{}\r\n", boundary, part));
+    }
+    multipart.push_str(&format!("--{}--", boundary));
+    multipart
+}
+
+fn generate_boundary() -> String {
+    let mut rng = thread_rng();
+    format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>())
+}
+
+/// Generate a unique id for the Message-ID header,
+/// without the enclosing angle brackets.
+pub fn generate_message_id(sender_address: &str) -> String {
+    let domain = sender_address
+        .rsplit('@')
+        .next()
+        .unwrap_or("localhost");
+    let mut rng = thread_rng();
+    format!("{:016x}{:016x}@{}", rng.gen::<u64>(), rng.gen::<u64>(), domain)
+}
@@ -0,0 +1,324 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+
+use super::*;
+use settings::Sender;
+
+#[test]
+fn compose_text_only() {
+    let mut email = create_message();
+    email.to.push(String::from("bar@example.com"));
+    email.cc.push(String::from("baz@example.com"));
+    email.reply_to = Some(String::from("qux@example.com"));
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    assert!(message.starts_with("From: \"Firefox Accounts\" <accounts@firefox.com>\r\n"));
+    assert!(message.contains("\r\nTo: foo@example.com, bar@example.com\r\n"));
+    assert!(message.contains("\r\nCc: baz@example.com\r\n"));
+    assert!(message.contains("\r\nReply-To: qux@example.com\r\n"));
+    assert!(message.contains("\r\nSubject: wibble\r\n"));
+    assert!(message.contains("\r\nDate: "));
+    assert!(message.contains("\r\nMessage-ID: <deadbeef@firefox.com>\r\n"));
+    assert!(message.contains(
+        "\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=UTF-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\nblee\r\n"
+    ));
+    assert!(!message.contains("multipart"));
+}
+
+#[test]
+fn compose_with_html() {
+    let mut email = create_message();
+    email.body_html = Some(String::from("<a>blee</a>"));
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    assert!(!message.contains("\r\nCc:"));
+    assert!(!message.contains("\r\nReply-To:"));
+    assert!(message.contains("\r\nContent-Type: multipart/alternative; boundary=\""));
+    assert!(!message.contains("multipart/mixed"));
+
+    let text = message.find("Content-Type: text/plain").expect("missing text part");
+    let html = message.find("Content-Type: text/html").expect("missing HTML part");
+    assert!(text < html);
+    assert!(message.contains("\r\n\r\n<a>blee</a>\r\n--"));
+}
+
+#[test]
+fn compose_with_attachments() {
+    let mut email = create_message();
+    email.body_html = Some(String::from("<img src=\"cid:logo\">"));
+    email.attachments = vec![
+        Attachment {
+            filename: String::from("foo.txt"),
+            content_type: String::from("text/plain"),
+            content: b"attached".to_vec(),
+            disposition: Disposition::Attachment,
+            content_id: None,
+        },
+        Attachment {
+            filename: String::from("logo.png"),
+            content_type: String::from("image/png"),
+            content: vec![0, 1, 2, 3],
+            disposition: Disposition::Inline,
+            content_id: Some(String::from("logo")),
+        },
+    ];
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    let mixed = message.find("multipart/mixed").expect("missing mixed part");
+    let alternative = message
+        .find("multipart/alternative")
+        .expect("missing alternative part");
+    let related = message.find("multipart/related").expect("missing related part");
+    assert!(mixed < alternative);
+    assert!(alternative < related);
+
+    assert!(message.contains(
+        "Content-Type: text/plain; name=\"foo.txt\"\r\nContent-Disposition: attachment; filename=\"foo.txt\"\r\nContent-Transfer-Encoding: base64\r\n\r\nYXR0YWNoZWQ=\r\n"
+    ));
+    assert!(message.contains(
+        "Content-Type: image/png; name=\"logo.png\"\r\nContent-Disposition: inline; filename=\"logo.png\"\r\nContent-ID: <logo>\r\nContent-Transfer-Encoding: base64\r\n\r\nAAECAw==\r\n"
+    ));
+
+    // The inline attachment is related to the HTML, not the outer message
+    let inline = message.find("Content-ID: <logo>").unwrap();
+    let attachment = message.find("filename=\"foo.txt\"").unwrap();
+    assert!(related < inline);
+    assert!(inline < attachment);
+}
+
+#[test]
+fn compose_inline_attachment_without_html() {
+    let mut email = create_message();
+    email.attachments = vec![Attachment {
+        filename: String::from("logo.png"),
+        content_type: String::from("image/png"),
+        content: vec![0, 1, 2, 3],
+        disposition: Disposition::Inline,
+        content_id: Some(String::from("logo")),
+    }];
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    assert!(message.contains("multipart/mixed"));
+    assert!(!message.contains("multipart/related"));
+    assert!(message.contains("Content-ID: <logo>"));
+}
+
+#[test]
+fn compose_encodes_non_ascii() {
+    let mut email = create_message();
+    email.from.name = String::from("Firefox Kontø");
+    email
+        .headers
+        .insert(String::from("X-Qux"), String::from("quüx"));
+    email.subject = String::from("wibblé");
+    email.body_text = String::from("blée");
+    email.attachments = vec![Attachment {
+        filename: String::from("föo.txt"),
+        content_type: String::from("text/plain"),
+        content: b"attached".to_vec(),
+        disposition: Disposition::Attachment,
+        content_id: None,
+    }];
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    assert!(message.starts_with("From: =?UTF-8?B?RmlyZWZveCBLb250w7g=?= <accounts@firefox.com>\r\n"));
+    assert!(message.contains("\r\nSubject: =?UTF-8?B?d2liYmzDqQ==?=\r\n"));
+    assert!(message.contains("\r\nX-Qux: =?UTF-8?B?cXXDvHg=?=\r\n"));
+    assert!(message.contains("\r\n\r\nbl=C3=A9e\r\n"));
+    assert!(message.contains("name*=UTF-8''f%C3%B6o.txt"));
+    assert!(message.contains("filename*=UTF-8''f%C3%B6o.txt"));
+}
+
+#[test]
+fn compose_encodes_line_breaks() {
+    let mut email = create_message();
+    email.subject = String::from("wibble\r\nBcc: bar@example.com");
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    assert!(message.contains("\r\nSubject: =?UTF-8?B?d2liYmxlDQpCY2M6IGJhckBleGFtcGxlLmNvbQ==?=\r\n"));
+    assert!(!message.contains("\r\nBcc:"));
+}
+
+#[test]
+fn compose_splits_long_encoded_words() {
+    let mut email = create_message();
+    email.subject = "wibblé ".repeat(20);
+    email.from.name = "ø".repeat(40);
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    let subject = unfold_header(&message, "Subject");
+    let words: Vec<&str> = subject.split(' ').collect();
+    assert!(words.len() > 1);
+    for word in &words {
+        assert!(word.len() <= 75);
+        assert!(word.starts_with("=?UTF-8?B?"));
+        assert!(word.ends_with("?="));
+        // Characters are never split between words
+        decode_words(word);
+    }
+    assert_eq!(decode_words(&subject), email.subject);
+
+    let from = unfold_header(&message, "From");
+    assert!(from.ends_with(" <accounts@firefox.com>"));
+    assert_eq!(
+        decode_words(from.trim_right_matches(" <accounts@firefox.com>")),
+        email.from.name
+    );
+
+    assert_line_lengths(&message, 78);
+}
+
+#[test]
+fn compose_folds_long_headers() {
+    let mut email = create_message();
+    email.to = (0..50).map(|i| format!("foo{}@example.com", i)).collect();
+    email
+        .headers
+        .insert(String::from("X-Qux"), "wibble blee ".repeat(10));
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    assert_eq!(unfold_header(&message, "To"), email.to.join(", "));
+    assert_eq!(unfold_header(&message, "X-Qux"), "wibble blee ".repeat(10));
+    assert_line_lengths(&message, 78);
+}
+
+#[test]
+fn compose_encodes_unfoldable_words() {
+    let mut email = create_message();
+    email.subject = "x".repeat(MAX_LINE_LENGTH);
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    let subject = unfold_header(&message, "Subject");
+    assert!(subject.starts_with("=?UTF-8?B?"));
+    assert_eq!(decode_words(&subject), email.subject);
+    assert_line_lengths(&message, 78);
+}
+
+#[test]
+fn compose_leaves_long_ascii_words() {
+    let mut email = create_message();
+    email
+        .headers
+        .insert(String::from("X-Qux"), format!("wibble {}", "x".repeat(100)));
+
+    let message = compose(&email, "deadbeef@firefox.com");
+
+    assert!(message.contains(&format!("\r\nX-Qux: wibble\r\n {}\r\n", "x".repeat(100))));
+}
+
+#[test]
+fn format_address_quotes_special_characters() {
+    assert_eq!(
+        format_address("Foo \"Bar\" \\ Baz", "foo@example.com"),
+        "\"Foo \\\"Bar\\\" \\\\ Baz\" <foo@example.com>"
+    );
+}
+
+#[test]
+fn quoted_printable_encoding() {
+    assert_eq!(quoted_printable("foo bar"), "foo bar");
+    assert_eq!(quoted_printable("foo=bar"), "foo=3Dbar");
+    assert_eq!(quoted_printable("foo \nbar\t"), "foo=20\r\nbar=09");
+    assert_eq!(quoted_printable("foo\r\nbar\n"), "foo\r\nbar\r\n");
+    assert_eq!(quoted_printable("€"), "=E2=82=AC");
+}
+
+#[test]
+fn quoted_printable_soft_line_breaks() {
+    let line = "a".repeat(100);
+    assert_eq!(
+        quoted_printable(&line),
+        format!("{}=\r\n{}", "a".repeat(75), "a".repeat(25))
+    );
+
+    // Escape sequences are never split across lines
+    let line = format!("{}€", "a".repeat(70));
+    assert_eq!(
+        quoted_printable(&line),
+        format!("{}=E2=\r\n=82=AC", "a".repeat(70))
+    );
+}
+
+#[test]
+fn generate_message_id_uses_sender_domain() {
+    let message_id = generate_message_id("accounts@firefox.com");
+    assert!(message_id.ends_with("@firefox.com"));
+    assert!(!message_id.starts_with('<'));
+    assert_ne!(message_id, generate_message_id("accounts@firefox.com"));
+}
+
+fn create_message() -> Message {
+    Message {
+        from: Sender {
+            address: String::from("accounts@firefox.com"),
+            name: String::from("Firefox Accounts"),
+        },
+        to: vec![String::from("foo@example.com")],
+        cc: Vec::new(),
+        bcc: Vec::new(),
+        reply_to: None,
+        headers: HashMap::new(),
+        subject: String::from("wibble"),
+        body_text: String::from("blee"),
+        body_html: None,
+        attachments: Vec::new(),
+        tags: HashMap::new(),
+        categories: Vec::new(),
+        metadata: HashMap::new(),
+    }
+}
+
+fn header_lines(message: &str) -> Vec<&str> {
+    message
+        .split("\r\n\r\n")
+        .next()
+        .expect("missing headers")
+        .split("\r\n")
+        .collect()
+}
+
+fn unfold_header(message: &str, name: &str) -> String {
+    let prefix = format!("{}:", name);
+    let mut value: Option<String> = None;
+    for line in header_lines(message) {
+        if line.starts_with(' ') {
+            if let Some(ref mut value) = value {
+                value.push_str(line);
+            }
+        } else if value.is_some() {
+            break;
+        } else if line.starts_with(&prefix) {
+            value = Some(line[prefix.len()..].to_string());
+        }
+    }
+    value.expect("missing header")[1..].to_string()
+}
+
+fn decode_words(value: &str) -> String {
+    let bytes = value.split(' ').fold(Vec::new(), |mut bytes, word| {
+        let encoded = word
+            .trim_left_matches("=?UTF-8?B?")
+            .trim_right_matches("?=");
+        bytes.extend(base64::decode(encoded).expect("base64 error"));
+        bytes
+    });
+    String::from_utf8(bytes).expect("UTF-8 error")
+}
+
+fn assert_line_lengths(message: &str, max_length: usize) {
+    for line in header_lines(message) {
+        assert!(line.len() <= max_length, "line too long: {}", line);
+    }
+}
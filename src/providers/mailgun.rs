@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::Cursor;
+
+use reqwest::{
+    mime::Mime, multipart::{Form, Part}, Client, StatusCode,
+};
+
+use super::{classify_status, Disposition, Message, Provider, ProviderError, ProviderErrorKind};
+use settings::Mailgun as MailgunSettings;
+
+#[derive(Debug, Deserialize)]
+struct MailgunResponse {
+    id: String,
+}
+
+pub struct MailgunProvider<'s> {
+    client: Client,
+    key: &'s str,
+    url: String,
+}
+
+impl<'s> MailgunProvider<'s> {
+    pub fn new(mailgun_settings: &'s MailgunSettings) -> MailgunProvider<'s> {
+        MailgunProvider {
+            client: Client::new(),
+            key: &mailgun_settings.key,
+            url: format!(
+                "{}{}/messages",
+                mailgun_settings.baseuri, mailgun_settings.domain
+            ),
+        }
+    }
+}
+
+impl<'s> Provider for MailgunProvider<'s> {
+    fn send(&self, message: &Message) -> Result<String, ProviderError> {
+        let mut form = Form::new()
+            .text(
+                "from",
+                format!("{} <{}>", message.from.name, message.from.address),
+            )
+            .text("subject", message.subject.to_string())
+            .text("text", message.body_text.to_string());
+
+        if let Some(ref html) = message.body_html {
+            form = form.text("html", html.to_string());
+        }
+
+        for address in &message.to {
+            form = form.text("to", address.to_string());
+        }
+
+        for address in &message.cc {
+            form = form.text("cc", address.to_string());
+        }
+
+        for address in &message.bcc {
+            form = form.text("bcc", address.to_string());
+        }
+
+        if let Some(ref reply_to) = message.reply_to {
+            form = form.text("h:Reply-To", reply_to.to_string());
+        }
+
+        for (name, value) in &message.headers {
+            form = form.text(format!("h:{}", name), value.to_string());
+        }
+
+        for attachment in &message.attachments {
+            // Mailgun uses the filename of inline attachments as their content id
+            let (field, filename) = match attachment.disposition {
+                Disposition::Attachment => ("attachment", attachment.filename.clone()),
+                Disposition::Inline => (
+                    "inline",
+                    attachment
+                        .content_id
+                        .clone()
+                        .unwrap_or_else(|| attachment.filename.clone()),
+                ),
+            };
+            let content_type = attachment.content_type.parse::<Mime>().map_err(|error| {
+                ProviderError::new(
+                    ProviderErrorKind::Permanent,
+                    format!("Invalid attachment content type: {:?}", error),
+                )
+            })?;
+            let length = attachment.content.len() as u64;
+            let part = Part::reader_with_length(Cursor::new(attachment.content.clone()), length)
+                .file_name(filename)
+                .mime(content_type);
+            form = form.part(field, part);
+        }
+
+        let mut response = self
+            .client
+            .post(self.url.as_str())
+            .basic_auth("api", Some(self.key))
+            .multipart(form)
+            .send()?;
+
+        let status = response.status();
+        if status == StatusCode::Ok {
+            response
+                .json::<MailgunResponse>()
+                .map(|body| body.id.trim_matches(|c| c == '<' || c == '>').to_string())
+                .map_err(|error| {
+                    ProviderError::new(
+                        ProviderErrorKind::Configuration,
+                        format!("Invalid Mailgun response: {:?}", error),
+                    )
+                })
+        } else {
+            Err(ProviderError::new(
+                classify_status(status),
+                format!("Mailgun response: {}", status),
+            ))
+        }
+    }
+}
@@ -2,19 +2,142 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
-use super::{Provider, ProviderError};
+use std::{cmp, thread, time::Duration};
 
-pub struct MockProvider;
+use rand::{thread_rng, Rng};
+use regex::Regex;
+
+use super::{Message, Provider, ProviderError, ProviderErrorKind};
+use settings::Mock as MockSettings;
+
+// Like the SES mailbox simulator, recipients at this domain
+// trigger faults based on their local part, e.g. `permanent@mock.invalid`,
+// `transient-1@mock.invalid` or `delay-500@mock.invalid`,
+// if `mock.magicaddresses` is set.
+const MAGIC_DOMAIN: &str = "mock.invalid";
+
+pub struct MockProvider {
+    latency: u64,
+    failure_rate: u8,
+    faults: Vec<(Regex, ProviderErrorKind)>,
+    magic_addresses: bool,
+    max_magic_delay: u64,
+}
+
+impl MockProvider {
+    /// Magic delays are capped at `mock.maxdelay`,
+    /// so that recipients can't tie up a thread indefinitely.
+    pub fn new(settings: &MockSettings) -> MockProvider {
+        MockProvider {
+            latency: settings.latency,
+            failure_rate: settings.failurerate,
+            faults: settings
+                .faults
+                .iter()
+                .map(|fault| {
+                    (
+                        Regex::new(&fault.pattern).expect("invalid mock fault pattern"),
+                        error_kind(&fault.kind).expect("invalid mock fault kind"),
+                    )
+                })
+                .collect(),
+            magic_addresses: settings.magicaddresses,
+            max_magic_delay: settings.maxdelay,
+        }
+    }
+
+    fn fault(&self, recipient: &str) -> Option<ProviderErrorKind> {
+        self.magic(magic_kind(recipient)).or_else(|| {
+            self.faults
+                .iter()
+                .find(|&&(ref pattern, _)| pattern.is_match(recipient))
+                .map(|&(_, kind)| kind)
+        })
+    }
+
+    fn delay(&self, recipient: &str) -> Option<u64> {
+        self.magic(magic_delay(recipient))
+            .map(|delay| cmp::min(delay, self.max_magic_delay))
+    }
+
+    fn magic<T>(&self, value: Option<T>) -> Option<T> {
+        if self.magic_addresses {
+            value
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for MockProvider {
+    fn default() -> MockProvider {
+        MockProvider::new(&MockSettings::default())
+    }
+}
 
 impl Provider for MockProvider {
-    fn send(
-        &self,
-        _to: &str,
-        _cc: &[&str],
-        _subject: &str,
-        _body_text: &str,
-        _body_html: Option<&str>,
-    ) -> Result<String, ProviderError> {
+    fn send(&self, message: &Message) -> Result<String, ProviderError> {
+        let recipients = message.recipients();
+
+        let latency = recipients
+            .iter()
+            .filter_map(|recipient| self.delay(recipient))
+            .fold(self.latency, cmp::max);
+        if latency > 0 {
+            thread::sleep(Duration::from_millis(latency));
+        }
+
+        for recipient in &recipients {
+            if let Some(kind) = self.fault(recipient) {
+                return Err(ProviderError::new(
+                    kind,
+                    format!("mock {:?} failure for {}", kind, recipient),
+                ));
+            }
+        }
+
+        if self.failure_rate > 0 && thread_rng().gen_range(0, 100) < self.failure_rate {
+            return Err(ProviderError::new(
+                ProviderErrorKind::Transient,
+                String::from("mock random failure"),
+            ));
+        }
+
         Ok(String::from("deadbeef"))
     }
 }
+
+fn error_kind(value: &str) -> Option<ProviderErrorKind> {
+    match value {
+        "configuration" => Some(ProviderErrorKind::Configuration),
+        "permanent" => Some(ProviderErrorKind::Permanent),
+        "transient" => Some(ProviderErrorKind::Transient),
+        _ => None,
+    }
+}
+
+// Returns the local part of a magic address split on hyphens,
+// so that tests can make them unique with a suffix.
+fn magic_parts(recipient: &str) -> Option<Vec<&str>> {
+    let mut parts = recipient.rsplitn(2, '@');
+    match (parts.next(), parts.next()) {
+        (Some(domain), Some(local_part)) if domain.eq_ignore_ascii_case(MAGIC_DOMAIN) => {
+            Some(local_part.split('-').collect())
+        }
+        _ => None,
+    }
+}
+
+fn magic_kind(recipient: &str) -> Option<ProviderErrorKind> {
+    magic_parts(recipient).and_then(|parts| error_kind(parts[0]))
+}
+
+fn magic_delay(recipient: &str) -> Option<u64> {
+    magic_parts(recipient).and_then(|parts| {
+        if parts[0] == "delay" && parts.len() > 1 {
+            parts[1].parse().ok()
+        } else {
+            None
+        }
+    })
+}
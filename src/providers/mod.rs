@@ -3,37 +3,144 @@
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::{
-    boxed::Box, collections::HashMap, error::Error, fmt::{self, Display, Formatter},
+    boxed::Box, collections::{HashMap, HashSet}, error::Error, fmt::{self, Display, Formatter},
+    sync::{Arc, Mutex}, thread, time::{Duration, SystemTime},
 };
 
+use rand::{thread_rng, Rng};
+use reqwest::{Error as RequestError, StatusCode};
+use ring::digest::{self, SHA256};
+use rocket::http::Status;
+
+pub use self::recorder::{Recorder, RecorderError, Recording};
 use self::{
-    mock::MockProvider as Mock, sendgrid::SendgridProvider as Sendgrid, ses::SesProvider as Ses,
+    mailgun::MailgunProvider as Mailgun, mock::MockProvider as Mock,
+    recorder::RecorderProvider, sendgrid::SendgridProvider as Sendgrid, ses::SesProvider as Ses,
+    smtp::SmtpProvider as Smtp,
 };
-use settings::Settings;
+use app_errors::HttpError;
+use deserialize;
+use dkim::DkimError;
+use serialize;
+use settings::{CircuitBreaker, ProviderWeight, Retry, Routing, Sender, Settings};
+use spool::{self, Entry, Spool, SpoolError};
+use status;
 
+mod mailgun;
 mod mock;
+mod recorder;
 mod sendgrid;
 mod ses;
+mod smtp;
+#[cfg(test)]
+mod test;
 
-trait Provider {
-    fn send(
-        &self,
-        to: &str,
-        cc: &[&str],
-        subject: &str,
-        body_text: &str,
-        body_html: Option<&str>,
-    ) -> Result<String, ProviderError>;
+// How long clients should wait before retrying after a transient error, in seconds
+const RETRY_AFTER: u64 = 60;
+
+/// Something that can deliver a message,
+/// returning its own id for the message on success.
+/// Providers are shared between the threads that send messages.
+pub trait Provider: Send + Sync {
+    fn send(&self, message: &Message) -> Result<String, ProviderError>;
+}
+
+/// A message to send, as it flows from the `/send` handler
+/// through `Providers` to each provider.
+/// Providers ignore any fields that they don't support.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub from: Sender,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    pub headers: HashMap<String, String>,
+    pub subject: String,
+    pub body_text: String,
+    pub body_html: Option<String>,
+    pub attachments: Vec<Attachment>,
+    pub tags: HashMap<String, String>,
+    pub categories: Vec<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl Message {
+    /// Every envelope recipient, including bcc.
+    pub fn recipients(&self) -> Vec<&str> {
+        self.to
+            .iter()
+            .chain(self.cc.iter())
+            .chain(self.bcc.iter())
+            .map(|address| address.as_str())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Attachment {
+    pub filename: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    #[serde(deserialize_with = "deserialize::base64", serialize_with = "serialize::base64")]
+    pub content: Vec<u8>,
+    #[serde(default)]
+    pub disposition: Disposition,
+    /// Inline attachments are referenced from the HTML body
+    /// by their content id, e.g. `<img src="cid:logo">`.
+    #[serde(rename = "contentId")]
+    pub content_id: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    Attachment,
+    Inline,
+}
+
+impl Default for Disposition {
+    fn default() -> Disposition {
+        Disposition::Attachment
+    }
+}
+
+impl Display for Disposition {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}",
+            match self {
+                Disposition::Attachment => "attachment",
+                Disposition::Inline => "inline",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProviderErrorKind {
+    /// Throttling, timeouts and server errors,
+    /// which may succeed if the message is retried later.
+    Transient,
+    /// The provider rejected the message or one of its recipients,
+    /// so retrying won't help.
+    Permanent,
+    /// Bad credentials, unverified senders and the like,
+    /// which need fixing on our side.
+    Configuration,
 }
 
 #[derive(Debug)]
 pub struct ProviderError {
+    pub kind: ProviderErrorKind,
     description: String,
 }
 
 impl ProviderError {
-    pub fn new(description: String) -> ProviderError {
-        ProviderError { description }
+    pub fn new(kind: ProviderErrorKind, description: String) -> ProviderError {
+        ProviderError { kind, description }
     }
 }
 
@@ -49,51 +156,613 @@ impl Display for ProviderError {
     }
 }
 
+impl From<ProviderError> for HttpError {
+    fn from(error: ProviderError) -> HttpError {
+        match error.kind {
+            ProviderErrorKind::Transient => {
+                HttpError::with_retry_after(Status::ServiceUnavailable, RETRY_AFTER)
+            }
+            ProviderErrorKind::Permanent => HttpError::new(Status::UnprocessableEntity),
+            ProviderErrorKind::Configuration => HttpError::new(Status::InternalServerError),
+        }
+    }
+}
+
+impl From<RequestError> for ProviderError {
+    fn from(error: RequestError) -> ProviderError {
+        // Requests only fail outright on connection problems and timeouts
+        ProviderError {
+            kind: ProviderErrorKind::Transient,
+            description: format!("request error: {:?}", error),
+        }
+    }
+}
+
+impl From<DkimError> for ProviderError {
+    fn from(error: DkimError) -> ProviderError {
+        ProviderError {
+            kind: ProviderErrorKind::Configuration,
+            description: format!("DKIM error: {}", error),
+        }
+    }
+}
+
+impl From<SpoolError> for ProviderError {
+    fn from(error: SpoolError) -> ProviderError {
+        ProviderError {
+            kind: ProviderErrorKind::Configuration,
+            description: format!("failed to spool message: {}", error),
+        }
+    }
+}
+
+// Shared by the providers that talk to an HTTP API
+fn classify_status(status: StatusCode) -> ProviderErrorKind {
+    match status {
+        StatusCode::Unauthorized | StatusCode::Forbidden => ProviderErrorKind::Configuration,
+        StatusCode::TooManyRequests => ProviderErrorKind::Transient,
+        status if status.is_server_error() => ProviderErrorKind::Transient,
+        status if status.is_client_error() => ProviderErrorKind::Permanent,
+        _ => ProviderErrorKind::Configuration,
+    }
+}
+
+/// A message that was successfully handed to a provider,
+/// or left in the spool to be sent later.
+#[derive(Debug)]
+pub struct Delivery {
+    pub message_id: String,
+    /// How many times providers were called,
+    /// counting both retries and failover.
+    pub attempts: u32,
+    /// Whether the message was left in the spool,
+    /// in which case `message_id` is its spool id.
+    pub spooled: bool,
+}
+
+/// The state of a provider's circuit breaker.
+/// Requests are only sent to providers with an open circuit
+/// once the cooldown has elapsed, as a probe.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    opened_at: Option<u64>,
+    probe_started_at: Option<u64>,
+}
+
 pub struct Providers<'s> {
+    circuit_breaker: &'s CircuitBreaker,
     default_provider: &'s str,
+    failover: &'s [String],
+    health: Mutex<HashMap<String, Health>>,
+    in_flight: Mutex<HashSet<String>>,
     providers: HashMap<String, Box<Provider + 's>>,
+    recorder: Option<Arc<Recorder>>,
+    retry: &'s Retry,
+    routing: &'s Routing,
+    spool: Option<Spool>,
 }
 
 impl<'s> Providers<'s> {
     pub fn new(settings: &'s Settings) -> Providers {
-        let mut providers: HashMap<String, Box<Provider + 's>> = HashMap::new();
+        let mut providers = Providers {
+            circuit_breaker: &settings.circuitbreaker,
+            default_provider: &settings.provider,
+            failover: &settings.failover,
+            health: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashSet::new()),
+            providers: HashMap::new(),
+            recorder: None,
+            retry: &settings.retry,
+            routing: &settings.routing,
+            spool: settings.spool.as_ref().map(Spool::new),
+        };
 
-        providers.insert(String::from("mock"), Box::new(Mock));
-        providers.insert(String::from("ses"), Box::new(Ses::new(settings)));
+        providers.add("mock", Box::new(Mock::new(&settings.mock)));
+        providers.add("ses", Box::new(Ses::new(settings)));
+        providers.add("smtp", Box::new(Smtp::new(settings)));
+
+        if let Some(ref mailgun) = settings.mailgun {
+            providers.add("mailgun", Box::new(Mailgun::new(mailgun)));
+        }
 
         if let Some(ref sendgrid) = settings.sendgrid {
-            providers.insert(
-                String::from("sendgrid"),
-                Box::new(Sendgrid::new(sendgrid, settings)),
-            );
+            providers.add("sendgrid", Box::new(Sendgrid::new(sendgrid)));
         }
 
-        Providers {
-            default_provider: &settings.provider,
-            providers,
+        if settings.recorder.enabled {
+            let recorder = Arc::new(Recorder::new(&settings.recorder));
+            providers.add("recorder", Box::new(RecorderProvider::new(recorder.clone())));
+            providers.recorder = Some(recorder);
         }
+
+        providers
     }
 
+    /// Register a provider under `id`,
+    /// replacing any provider that's already registered with it.
+    /// The id is what requests, routing weights and failover refer to
+    /// and it prefixes the message ids that the provider returns.
+    pub fn add(&mut self, id: &str, provider: Box<Provider + 's>) {
+        self.providers.insert(id.to_string(), provider);
+    }
+
+    /// Send a message, retrying transient failures
+    /// and failing over between providers as necessary.
+    /// If the spool is enabled, the message is persisted first
+    /// and if every provider fails transiently,
+    /// it's left there to be replayed later.
+    /// The service spools and sends in separate steps instead,
+    /// so that the worker pool can accept jobs in between.
+    #[cfg(test)]
     pub fn send(
         &self,
-        to: &str,
-        cc: &[&str],
-        subject: &str,
-        body_text: &str,
-        body_html: Option<&str>,
+        message: &Message,
+        provider_id: Option<&str>,
+    ) -> Result<Delivery, ProviderError> {
+        let spool_id = self.spool(message, provider_id)?;
+        self.send_spooled(spool_id, message, provider_id)
+    }
+
+    /// Persist a message in the spool, if it's enabled,
+    /// returning the id of its entry.
+    /// The entry is in flight until it's passed to `send_spooled`
+    /// or `unspool`, so `replay` leaves it alone in the meantime.
+    pub fn spool(
+        &self,
+        message: &Message,
+        provider_id: Option<&str>,
+    ) -> Result<Option<String>, ProviderError> {
+        let spool = match self.spool {
+            Some(ref spool) => spool,
+            None => return Ok(None),
+        };
+
+        // The id is claimed before the entry is written,
+        // so that a replay can never pick it up in between
+        let id = spool::generate_id();
+        self.in_flight
+            .lock()
+            .expect("in-flight lock error")
+            .insert(id.clone());
+
+        let entry = Entry {
+            message: message.clone(),
+            provider: provider_id.map(|provider_id| provider_id.to_string()),
+        };
+        if let Err(error) = spool.write(&id, &entry) {
+            self.release(&id);
+            return Err(From::from(error));
+        }
+
+        Ok(Some(id))
+    }
+
+    /// Send a message that was persisted by `spool`,
+    /// settling its entry once the outcome is known.
+    pub fn send_spooled(
+        &self,
+        spool_id: Option<String>,
+        message: &Message,
         provider_id: Option<&str>,
-    ) -> Result<String, ProviderError> {
-        let resolved_provider_id = provider_id.unwrap_or(self.default_provider);
+    ) -> Result<Delivery, ProviderError> {
+        let (result, attempts) = self.try_send(message, provider_id);
+
+        if let (Some(spool), Some(id)) = (self.spool.as_ref(), spool_id) {
+            let settled = settle(spool, &id, &result);
+            self.release(&id);
+            if !settled {
+                return Ok(Delivery {
+                    message_id: spool_message_id(&id),
+                    attempts,
+                    spooled: true,
+                });
+            }
+        }
+
+        result.map(|message_id| Delivery {
+            message_id,
+            attempts,
+            spooled: false,
+        })
+    }
+
+    /// Remove a message that was persisted by `spool`
+    /// but is not going to be sent after all.
+    pub fn unspool(&self, spool_id: Option<String>) {
+        if let (Some(spool), Some(id)) = (self.spool.as_ref(), spool_id) {
+            if let Err(error) = spool.remove(&id) {
+                // TODO: replace this with proper logging when we have it
+                println!("failed to remove spool entry {}: {}", id, error);
+            }
+            self.release(&id);
+        }
+    }
+
+    /// Every message waiting in the spool.
+    pub fn spooled(&self) -> Vec<(String, Entry)> {
+        match self.spool {
+            Some(ref spool) => spool.pending().unwrap_or_else(|error| {
+                // TODO: replace this with proper logging when we have it
+                println!("failed to read spool: {}", error);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        }
+    }
+
+    /// Try again to send messages from the spool,
+    /// removing the ones that don't fail transiently,
+    /// and return how many are still waiting.
+    /// `sent` is called with the id that `send_spooled` returned
+    /// and the provider's id for each message that's sent.
+    /// Entries that are in flight elsewhere are skipped.
+    pub fn replay<F>(&self, mut sent: F) -> usize
+    where
+        F: FnMut(&str, &str),
+    {
+        let spool = match self.spool {
+            Some(ref spool) => spool,
+            None => return 0,
+        };
+
+        let mut remaining = 0;
+        for (id, entry) in self.spooled() {
+            if !self.claim(spool, &id) {
+                continue;
+            }
+
+            let (result, attempts) = self.try_send(
+                &entry.message,
+                entry.provider.as_ref().map(|provider| provider.as_ref()),
+            );
 
+            // TODO: replace this with proper logging when we have it
+            match result {
+                Ok(ref message_id) => {
+                    println!(
+                        "spool entry {} sent as {} after {} attempts",
+                        id, message_id, attempts
+                    );
+                    sent(&spool_message_id(&id), message_id);
+                }
+                Err(ref error) => println!(
+                    "spool entry {} failed after {} attempts: {}",
+                    id, attempts, error
+                ),
+            }
+
+            if !settle(spool, &id, &result) {
+                remaining += 1;
+            }
+            self.release(&id);
+        }
+
+        remaining
+    }
+
+    // Mark a spool entry as in flight, unless it already is
+    // or it was settled after the spool was read.
+    fn claim(&self, spool: &Spool, id: &str) -> bool {
+        let mut in_flight = self.in_flight.lock().expect("in-flight lock error");
+        if in_flight.contains(id) || !spool.contains(id) {
+            return false;
+        }
+
+        in_flight.insert(id.to_string());
+        true
+    }
+
+    fn release(&self, id: &str) {
+        self.in_flight
+            .lock()
+            .expect("in-flight lock error")
+            .remove(id);
+    }
+
+    fn try_send(
+        &self,
+        message: &Message,
+        provider_id: Option<&str>,
+    ) -> (Result<String, ProviderError>, u32) {
+        let resolved_provider_id = provider_id.unwrap_or_else(|| {
+            self.route(message.to.first().map_or("", |address| address.as_str()))
+        });
+
+        // The resolved provider is always tried first, then any failover
+        // providers in the order they're configured.
+        let mut provider_ids = vec![resolved_provider_id];
+        provider_ids.extend(
+            self.failover
+                .iter()
+                .map(|id| id.as_str())
+                .filter(|id| *id != resolved_provider_id),
+        );
+
+        let started = now();
+        let mut attempts = 0;
+        let mut descriptions = Vec::new();
+        let mut kind = ProviderErrorKind::Configuration;
+        for id in provider_ids {
+            let result = self
+                .providers
+                .get(id)
+                .ok_or_else(|| {
+                    ProviderError::new(
+                        ProviderErrorKind::Configuration,
+                        format!("Invalid provider `{}`", id),
+                    )
+                })
+                .and_then(|provider| {
+                    let mut retries = 0;
+                    loop {
+                        self.check_circuit(id, now())?;
+                        attempts += 1;
+                        let result = provider.send(message);
+                        self.record_result(id, &result, now());
+
+                        if let Err(ref error) = result {
+                            if error.kind == ProviderErrorKind::Transient {
+                                if let Some(delay) = self.backoff(retries, started) {
+                                    // TODO: replace this with proper logging when we have it
+                                    println!(
+                                        "provider `{}` failed, retrying in {}ms: {}",
+                                        id, delay, error
+                                    );
+                                    thread::sleep(Duration::from_millis(delay));
+                                    retries += 1;
+                                    continue;
+                                }
+                            }
+                        }
+
+                        return result;
+                    }
+                })
+                .map(|message_id| status::message_id(id, &message_id));
+
+            match result {
+                Ok(message_id) => return (Ok(message_id), attempts),
+                Err(error) => {
+                    // TODO: replace this with proper logging when we have it
+                    println!(
+                        "provider `{}` failed after {} attempts: {}",
+                        id, attempts, error
+                    );
+
+                    // A rejected message will be rejected everywhere,
+                    // so there's no point failing over.
+                    if error.kind == ProviderErrorKind::Permanent {
+                        return (Err(error), attempts);
+                    }
+
+                    // If any provider failed transiently,
+                    // the whole send may succeed when it's retried.
+                    if error.kind == ProviderErrorKind::Transient {
+                        kind = ProviderErrorKind::Transient;
+                    }
+
+                    descriptions.push(error.description);
+                }
+            }
+        }
+
+        (
+            Err(ProviderError::new(kind, descriptions.join("; "))),
+            attempts,
+        )
+    }
+
+    /// How long to wait before retrying a transient failure,
+    /// or `None` if the retry policy is exhausted.
+    /// The delay doubles with each retry, plus some random jitter,
+    /// and retries stop altogether once the deadline would be passed.
+    fn backoff(&self, retries: u32, started: u64) -> Option<u64> {
+        if retries + 1 >= self.retry.attempts {
+            return None;
+        }
+
+        let jitter = if self.retry.jitter > 0 {
+            thread_rng().gen_range(0, self.retry.jitter + 1)
+        } else {
+            0
+        };
+        let delay = self.retry
+            .basedelay
+            .saturating_mul(1u64.checked_shl(retries).unwrap_or(u64::max_value()))
+            .saturating_add(jitter);
+
+        if now().saturating_add(delay) > started.saturating_add(self.retry.deadline) {
+            return None;
+        }
+
+        Some(delay)
+    }
+
+    /// The store behind the `recorder` provider, if it's enabled.
+    pub fn recorder(&self) -> Option<Arc<Recorder>> {
+        self.recorder.clone()
+    }
+
+    /// The circuit state of every configured provider.
+    pub fn health(&self) -> HashMap<String, CircuitState> {
+        let now = now();
         self.providers
-            .get(resolved_provider_id)
-            .ok_or(ProviderError::new(format!(
-                "Invalid provider `{}`",
-                resolved_provider_id
-            )))
-            .and_then(|provider| provider.send(to, cc, subject, body_text, body_html))
-            .map(|message_id| format!("{}:{}", resolved_provider_id, message_id))
+            .keys()
+            .map(|id| (id.to_string(), self.circuit_state(id, now)))
+            .collect()
+    }
+
+    fn circuit_state(&self, id: &str, now: u64) -> CircuitState {
+        let health = self.health.lock().expect("health lock error");
+        match health.get(id).and_then(|health| health.opened_at) {
+            Some(opened_at) => if now >= opened_at + self.circuit_breaker.cooldown {
+                CircuitState::HalfOpen
+            } else {
+                CircuitState::Open
+            },
+            None => CircuitState::Closed,
+        }
+    }
+
+    /// Fail fast if a provider's circuit is open.
+    /// Once the cooldown has elapsed, a single request is let through
+    /// to probe whether the provider has recovered.
+    fn check_circuit(&self, id: &str, now: u64) -> Result<(), ProviderError> {
+        let mut health = self.health.lock().expect("health lock error");
+        let health = health.entry(id.to_string()).or_insert_with(Health::default);
+
+        if let Some(opened_at) = health.opened_at {
+            let cooldown = self.circuit_breaker.cooldown;
+            let can_probe = now >= opened_at + cooldown && health
+                .probe_started_at
+                .map_or(true, |probe_started_at| now >= probe_started_at + cooldown);
+
+            if !can_probe {
+                return Err(ProviderError::new(
+                    ProviderErrorKind::Transient,
+                    format!("Circuit open for provider `{}`", id),
+                ));
+            }
+
+            health.probe_started_at = Some(now);
+        }
+
+        Ok(())
+    }
+
+    fn record_result(&self, id: &str, result: &Result<String, ProviderError>, now: u64) {
+        let threshold = self.circuit_breaker.threshold;
+        if threshold == 0 {
+            return;
+        }
+
+        let mut health = self.health.lock().expect("health lock error");
+        let health = health.entry(id.to_string()).or_insert_with(Health::default);
+
+        match result {
+            // A rejected message means the provider itself is working
+            Ok(_) => *health = Health::default(),
+            Err(ref error) if error.kind == ProviderErrorKind::Permanent => {
+                *health = Health::default()
+            }
+            Err(_) => {
+                health.failures += 1;
+                if health.probe_started_at.is_some() || health.failures >= threshold {
+                    if health.opened_at.is_none() {
+                        // TODO: replace this with proper logging when we have it
+                        println!("opening circuit for provider `{}`", id);
+                    }
+                    health.opened_at = Some(now);
+                    health.probe_started_at = None;
+                }
+            }
+        }
+    }
+
+    /// Pick a provider according to the configured weights,
+    /// falling back to the default provider if there are none.
+    /// Sticky routing hashes the recipient address with SHA-256,
+    /// so that recipients stay with the same provider
+    /// for as long as the weights don't change,
+    /// across restarts and releases.
+    /// Providers with an open circuit are left out.
+    fn route(&self, to: &str) -> &'s str {
+        let now = now();
+
+        let weights: Vec<(&'s str, u64)> = self
+            .routing
+            .weights
+            .iter()
+            .filter(|weight| self.circuit_state(&weight.provider, now) != CircuitState::Open)
+            .map(|weight| (weight.provider.as_str(), current_weight(weight, now)))
+            .collect();
+
+        let total: u64 = weights.iter().map(|&(_, weight)| weight).sum();
+        if total == 0 {
+            return self.default_provider;
+        }
+
+        let mut point = if self.routing.sticky {
+            let hash = digest::digest(&SHA256, to.to_lowercase().as_bytes());
+            hash.as_ref()[..8]
+                .iter()
+                .fold(0, |point, &byte| (point << 8) | u64::from(byte)) % total
+        } else {
+            thread_rng().gen_range(0, total)
+        };
+
+        for (provider, weight) in weights {
+            if point < weight {
+                return provider;
+            }
+            point -= weight;
+        }
+
+        self.default_provider
+    }
+}
+
+// The id that `/send` returns for a message left in the spool
+fn spool_message_id(id: &str) -> String {
+    format!("spool:{}", id)
+}
+
+// Remove a spool entry unless its send failed transiently,
+// returning whether it was removed.
+fn settle(spool: &Spool, id: &str, result: &Result<String, ProviderError>) -> bool {
+    if let Err(ref error) = *result {
+        if error.kind == ProviderErrorKind::Transient {
+            return false;
+        }
+    }
+
+    if let Err(error) = spool.remove(id) {
+        // TODO: replace this with proper logging when we have it
+        println!("failed to remove spool entry {}: {}", id, error);
+    }
+
+    true
+}
+
+// Milliseconds since the epoch
+fn now() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("system time error");
+    now.as_secs() * 1000 + u64::from(now.subsec_nanos()) / 1_000_000
+}
+
+fn current_weight(weight: &ProviderWeight, now: u64) -> u64 {
+    let target = u64::from(weight.weight);
+
+    if let Some(ref ramp) = weight.ramp {
+        let from = u64::from(ramp.from);
+
+        if now <= ramp.start {
+            return from;
+        }
+
+        let elapsed = now - ramp.start;
+        if elapsed >= ramp.period {
+            return target;
+        }
+
+        if target >= from {
+            from + (target - from) * elapsed / ramp.period
+        } else {
+            from - (from - target) * elapsed / ramp.period
+        }
+    } else {
+        target
     }
 }
 
-unsafe impl<'s> Sync for Providers<'s> {}
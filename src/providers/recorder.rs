@@ -0,0 +1,206 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    error::Error, fmt::{self, Display, Formatter}, fs::{self, File},
+    io::{Error as IoError, Write}, path::PathBuf, sync::{Arc, Mutex, MutexGuard},
+};
+
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use rocket::http::Status;
+use serde_json::{self, Error as JsonError};
+
+use super::{Message, Provider, ProviderError, ProviderErrorKind};
+use app_errors::HttpError;
+use settings::Recorder as RecorderSettings;
+
+/// A message that was captured by the recorder provider.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    pub id: String,
+    #[serde(flatten)]
+    pub message: Message,
+    /// An RFC 3339 timestamp, with a fixed number of digits
+    /// so that recordings sort in the order they were received.
+    pub received_at: String,
+}
+
+impl Recording {
+    /// Whether any recipient matches `user`,
+    /// either as a full address or just the local part
+    /// in the style of restmail.
+    pub fn is_for(&self, user: &str) -> bool {
+        let user = user.to_lowercase();
+        self.message.recipients().iter().any(|address| {
+            let address = address.to_lowercase();
+            address == user || address.split('@').next() == Some(user.as_str())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RecorderError {
+    description: String,
+}
+
+impl RecorderError {
+    pub fn new(description: String) -> RecorderError {
+        RecorderError { description }
+    }
+}
+
+impl Error for RecorderError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Display for RecorderError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description)
+    }
+}
+
+impl From<RecorderError> for HttpError {
+    fn from(_error: RecorderError) -> HttpError {
+        HttpError::new(Status::InternalServerError)
+    }
+}
+
+impl From<IoError> for RecorderError {
+    fn from(error: IoError) -> RecorderError {
+        RecorderError::new(format!("recorder I/O error: {:?}", error))
+    }
+}
+
+impl From<JsonError> for RecorderError {
+    fn from(error: JsonError) -> RecorderError {
+        RecorderError::new(format!("recorder JSON error: {:?}", error))
+    }
+}
+
+impl From<RecorderError> for ProviderError {
+    fn from(error: RecorderError) -> ProviderError {
+        ProviderError {
+            kind: ProviderErrorKind::Configuration,
+            description: format!("failed to record message: {}", error),
+        }
+    }
+}
+
+/// Storage for recorded messages,
+/// in memory or as one JSON file per message.
+#[derive(Debug)]
+pub struct Recorder {
+    directory: Option<PathBuf>,
+    recordings: Mutex<Vec<Recording>>,
+}
+
+impl Recorder {
+    pub fn new(settings: &RecorderSettings) -> Recorder {
+        let directory = settings.directory.as_ref().map(PathBuf::from);
+        if let Some(ref directory) = directory {
+            fs::create_dir_all(directory).expect("failed to create recorder directory");
+        }
+
+        Recorder {
+            directory,
+            recordings: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, recording: Recording) -> Result<(), RecorderError> {
+        match self.directory {
+            Some(ref directory) => {
+                let mut file = File::create(directory.join(format!("{}.json", recording.id)))?;
+                file.write_all(&serde_json::to_vec(&recording)?)?;
+                Ok(())
+            }
+            None => {
+                self.lock().push(recording);
+                Ok(())
+            }
+        }
+    }
+
+    /// Every recorded message, oldest first.
+    pub fn recordings(&self) -> Result<Vec<Recording>, RecorderError> {
+        match self.directory {
+            Some(ref directory) => {
+                let mut recordings = Vec::new();
+                for path in json_files(directory)? {
+                    let recording: Recording = serde_json::from_reader(File::open(path)?)?;
+                    recordings.push(recording);
+                }
+                recordings.sort_by(|a, b| a.received_at.cmp(&b.received_at));
+                Ok(recordings)
+            }
+            None => Ok(self.lock().clone()),
+        }
+    }
+
+    /// Remove recorded messages, either all of them
+    /// or just the ones for a particular user.
+    pub fn clear(&self, user: Option<&str>) -> Result<(), RecorderError> {
+        let matches = |recording: &Recording| user.map_or(true, |user| recording.is_for(user));
+
+        match self.directory {
+            Some(ref directory) => {
+                for path in json_files(directory)? {
+                    let recording: Recording = serde_json::from_reader(File::open(&path)?)?;
+                    if matches(&recording) {
+                        fs::remove_file(path)?;
+                    }
+                }
+                Ok(())
+            }
+            None => {
+                self.lock().retain(|recording| !matches(recording));
+                Ok(())
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<Vec<Recording>> {
+        self.recordings.lock().expect("recorder lock error")
+    }
+}
+
+fn json_files(directory: &PathBuf) -> Result<Vec<PathBuf>, RecorderError> {
+    let mut paths = Vec::new();
+    for dir_entry in fs::read_dir(directory)? {
+        let path = dir_entry?.path();
+        if path.extension().map_or(false, |extension| extension == "json") {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+pub struct RecorderProvider {
+    recorder: Arc<Recorder>,
+}
+
+impl RecorderProvider {
+    pub fn new(recorder: Arc<Recorder>) -> RecorderProvider {
+        RecorderProvider { recorder }
+    }
+}
+
+impl Provider for RecorderProvider {
+    fn send(&self, message: &Message) -> Result<String, ProviderError> {
+        let mut rng = thread_rng();
+        let id = format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>());
+
+        self.recorder.record(Recording {
+            id: id.clone(),
+            message: message.clone(),
+            received_at: Utc::now().format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string(),
+        })?;
+
+        Ok(id)
+    }
+}
@@ -4,84 +4,133 @@
 
 use std::str::{from_utf8, Utf8Error};
 
-use reqwest::StatusCode;
-use sendgrid::{
-    errors::SendgridError,
-    v3::{
-        Content, Email as EmailAddress, Personalization, SGMailV3 as Message, V3Sender as Client,
-    },
-};
+use base64;
+use reqwest::{header::{Authorization, Bearer}, Client, StatusCode};
+use sendgrid::v3::{Content, Email as EmailAddress, Personalization, SGMailV3};
+use serde_json::{self, Error as JsonError, Value};
 
-use super::{Provider, ProviderError};
-use settings::{Sender, Sendgrid as SendgridSettings, Settings};
+use super::{classify_status, Message, Provider, ProviderError, ProviderErrorKind};
+use settings::Sendgrid as SendgridSettings;
 
 pub struct SendgridProvider<'s> {
     client: Client,
-    sender: &'s Sender,
+    key: &'s str,
+    sandbox: bool,
+    url: String,
 }
 
 impl<'s> SendgridProvider<'s> {
-    pub fn new(
-        sendgrid_settings: &SendgridSettings,
-        settings: &'s Settings,
-    ) -> SendgridProvider<'s> {
+    pub fn new(sendgrid_settings: &'s SendgridSettings) -> SendgridProvider<'s> {
         SendgridProvider {
-            client: Client::new(sendgrid_settings.key.clone()),
-            sender: &settings.sender,
+            client: Client::new(),
+            key: &sendgrid_settings.key,
+            sandbox: sendgrid_settings.sandbox,
+            url: format!("{}v3/mail/send", sendgrid_settings.baseuri),
         }
     }
 }
 
 impl<'s> Provider for SendgridProvider<'s> {
-    fn send(
-        &self,
-        to: &str,
-        cc: &[&str],
-        subject: &str,
-        body_text: &str,
-        body_html: Option<&str>,
-    ) -> Result<String, ProviderError> {
-        let mut message = Message::new();
+    fn send(&self, message: &Message) -> Result<String, ProviderError> {
+        let mut sg_message = SGMailV3::new();
         let mut from_address = EmailAddress::new();
-        from_address.set_email(&self.sender.address);
-        from_address.set_name(&self.sender.name);
-        message.set_from(from_address);
-        message.set_subject(subject);
+        from_address.set_email(&message.from.address);
+        from_address.set_name(&message.from.name);
+        sg_message.set_from(from_address);
+        sg_message.set_subject(&message.subject);
 
         let mut text = Content::new();
         text.set_content_type("text/plain");
-        text.set_value(body_text);
-        message.add_content(text);
+        text.set_value(&message.body_text);
+        sg_message.add_content(text);
 
-        if let Some(body_html) = body_html {
+        if let Some(ref body_html) = message.body_html {
             let mut html = Content::new();
             html.set_content_type("text/html");
             html.set_value(body_html);
-            message.add_content(html);
+            sg_message.add_content(html);
         }
 
         let mut personalization = Personalization::new();
-        let mut to_address = EmailAddress::new();
-        to_address.set_email(to);
-        personalization.add_to(to_address);
-        cc.iter().for_each(|cc| {
+        message.to.iter().for_each(|to| {
+            let mut to_address = EmailAddress::new();
+            to_address.set_email(to);
+            personalization.add_to(to_address);
+        });
+        message.cc.iter().for_each(|cc| {
             let mut cc_address = EmailAddress::new();
             cc_address.set_email(cc);
             personalization.add_cc(cc_address);
         });
-        message.add_personalization(personalization);
+        message.bcc.iter().for_each(|bcc| {
+            let mut bcc_address = EmailAddress::new();
+            bcc_address.set_email(bcc);
+            personalization.add_bcc(bcc_address);
+        });
+        sg_message.add_personalization(personalization);
+
+        // The sendgrid crate doesn't support everything that the v3 API does,
+        // so we extend its JSON before sending it ourselves.
+        let mut body = serde_json::to_value(&sg_message)?;
+        if let Some(ref reply_to) = message.reply_to {
+            body["reply_to"] = json!({ "email": reply_to });
+        }
+        if !message.headers.is_empty() {
+            body["headers"] = json!(message.headers);
+        }
+        if !message.attachments.is_empty() {
+            body["attachments"] = Value::Array(
+                message
+                    .attachments
+                    .iter()
+                    .map(|attachment| {
+                        let mut value = json!({
+                            "content": base64::encode(&attachment.content),
+                            "type": attachment.content_type,
+                            "filename": attachment.filename,
+                            "disposition": attachment.disposition.to_string(),
+                        });
+                        if let Some(ref content_id) = attachment.content_id {
+                            value["content_id"] = Value::String(content_id.to_string());
+                        }
+                        value
+                    })
+                    .collect(),
+            );
+        }
+        // Categories and custom args are echoed back to us in webhook events
+        if !message.categories.is_empty() {
+            body["categories"] = json!(message.categories);
+        }
+        if !message.metadata.is_empty() {
+            body["custom_args"] = json!(message.metadata);
+        }
+        if self.sandbox {
+            body["mail_settings"] = json!({ "sandbox_mode": { "enable": true } });
+        }
 
         self.client
-            .send(&message)
+            .post(self.url.as_str())
+            .header(Authorization(Bearer {
+                token: self.key.to_string(),
+            }))
+            .json(&body)
+            .send()
             .map_err(From::from)
             .and_then(|response| {
                 let status = response.status();
                 if status == StatusCode::Ok || status == StatusCode::Accepted {
+                    // Nothing is queued in sandbox mode, so there's no message id
+                    if self.sandbox {
+                        return Ok(String::from("sandbox"));
+                    }
+
                     response
                         .headers()
                         .get_raw("X-Message-Id")
                         .and_then(|raw_header| raw_header.one())
                         .ok_or(ProviderError {
+                            kind: ProviderErrorKind::Configuration,
                             description: String::from(
                                 "Missing or duplicate X-Message-Id header in Sendgrid response",
                             ),
@@ -90,6 +139,7 @@ impl<'s> Provider for SendgridProvider<'s> {
                         .map(|message_id| message_id.to_string())
                 } else {
                     Err(ProviderError {
+                        kind: classify_status(status),
                         description: format!("Sendgrid response: {}", status),
                     })
                 }
@@ -97,10 +147,11 @@ impl<'s> Provider for SendgridProvider<'s> {
     }
 }
 
-impl From<SendgridError> for ProviderError {
-    fn from(error: SendgridError) -> ProviderError {
+impl From<JsonError> for ProviderError {
+    fn from(error: JsonError) -> ProviderError {
         ProviderError {
-            description: format!("Sendgrid error: {:?}", error),
+            kind: ProviderErrorKind::Configuration,
+            description: format!("JSON error: {:?}", error),
         }
     }
 }
@@ -108,6 +159,7 @@ impl From<SendgridError> for ProviderError {
 impl From<Utf8Error> for ProviderError {
     fn from(error: Utf8Error) -> ProviderError {
         ProviderError {
+            kind: ProviderErrorKind::Configuration,
             description: format!("Failed to decode string as UTF-8: {:?}", error),
         }
     }
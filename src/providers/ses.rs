@@ -6,25 +6,37 @@ use std::boxed::Box;
 
 use rusoto_core::{reactor::RequestDispatcher, Region};
 use rusoto_credential::StaticProvider;
-use rusoto_ses::{Content, Destination, Message, SendEmailError, SendEmailRequest, Ses, SesClient};
+use rusoto_ses::{
+    MessageTag, RawMessage, SendRawEmailError, SendRawEmailRequest, Ses, SesClient,
+};
 
-use super::{Provider, ProviderError};
+use super::{Message, Provider, ProviderError, ProviderErrorKind};
+use dkim::{Signer, SES_SIGNED_HEADERS};
+use mime;
 use settings::Settings;
 
 pub struct SesProvider {
-    client: Box<Ses>,
-    sender: String,
+    client: Box<Ses + Send + Sync>,
+    configuration_set: Option<String>,
+    signer: Option<Signer>,
 }
 
 impl SesProvider {
     pub fn new(settings: &Settings) -> SesProvider {
-        let region = settings
-            .ses
-            .region
-            .parse::<Region>()
-            .expect("invalid region");
+        let region = if let Some(ref endpoint) = settings.ses.endpoint {
+            Region::Custom {
+                name: settings.ses.region.to_string(),
+                endpoint: endpoint.to_string(),
+            }
+        } else {
+            settings
+                .ses
+                .region
+                .parse::<Region>()
+                .expect("invalid region")
+        };
 
-        let client: Box<Ses> = if let Some(ref keys) = settings.ses.keys {
+        let client: Box<Ses + Send + Sync> = if let Some(ref keys) = settings.ses.keys {
             let creds =
                 StaticProvider::new(keys.access.to_string(), keys.secret.to_string(), None, None);
             Box::new(SesClient::new(RequestDispatcher::default(), creds, region))
@@ -34,56 +46,106 @@ impl SesProvider {
 
         SesProvider {
             client,
-            sender: format!("{} <{}>", settings.sender.name, settings.sender.address),
+            configuration_set: settings.ses.configurationset.clone(),
+            signer: settings
+                .dkim
+                .as_ref()
+                .map(|dkim| Signer::new(dkim, SES_SIGNED_HEADERS).expect("invalid DKIM settings")),
         }
     }
 }
 
 impl Provider for SesProvider {
-    fn send(
-        &self,
-        to: &str,
-        cc: &[&str],
-        subject: &str,
-        body_text: &str,
-        body_html: Option<&str>,
-    ) -> Result<String, ProviderError> {
-        let mut destination = Destination::default();
-        destination.to_addresses = Some(vec![to.to_string()]);
-        if cc.len() > 0 {
-            destination.cc_addresses = Some(cc.iter().map(|s| s.to_string()).collect());
-        }
+    fn send(&self, message: &Message) -> Result<String, ProviderError> {
+        // Bcc recipients are only included in the envelope, not the message.
+        let destinations = message
+            .recipients()
+            .iter()
+            .map(|address| address.to_string())
+            .collect();
 
-        let mut message = Message::default();
-        message.subject.data = subject.to_string();
-        message.body.text = Some(Content {
-            charset: None,
-            data: body_text.to_string(),
-        });
-        if let Some(html) = body_html {
-            message.body.html = Some(Content {
-                charset: None,
-                data: html.to_string(),
-            });
+        let mut composed = mime::compose(
+            message,
+            &mime::generate_message_id(&message.from.address),
+        );
+        if let Some(ref signer) = self.signer {
+            composed = signer.sign(&composed)?;
         }
 
-        let mut request = SendEmailRequest::default();
-        request.destination = destination;
-        request.message = message;
-        request.source = self.sender.to_string();
+        let mut request = SendRawEmailRequest::default();
+        request.destinations = Some(destinations);
+        request.raw_message = RawMessage {
+            data: composed.into_bytes(),
+        };
+        request.source = Some(mime::format_address(
+            &message.from.name,
+            &message.from.address,
+        ));
+        request.configuration_set_name = self.configuration_set.clone();
+        if !message.tags.is_empty() {
+            request.tags = Some(
+                message
+                    .tags
+                    .iter()
+                    .map(|(name, value)| MessageTag {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                    })
+                    .collect(),
+            );
+        }
 
         self.client
-            .send_email(&request)
+            .send_raw_email(&request)
             .sync()
             .map(|response| response.message_id)
             .map_err(From::from)
     }
 }
 
-impl From<SendEmailError> for ProviderError {
-    fn from(error: SendEmailError) -> ProviderError {
+impl From<SendRawEmailError> for ProviderError {
+    fn from(error: SendRawEmailError) -> ProviderError {
+        let kind = match error {
+            SendRawEmailError::AccountSendingPaused(_)
+            | SendRawEmailError::ConfigurationSetDoesNotExist(_)
+            | SendRawEmailError::ConfigurationSetSendingPaused(_)
+            | SendRawEmailError::MailFromDomainNotVerified(_)
+            | SendRawEmailError::Credentials(_) => ProviderErrorKind::Configuration,
+            SendRawEmailError::MessageRejected(ref message) => classify_rejection(message),
+            SendRawEmailError::Validation(_) => ProviderErrorKind::Permanent,
+            SendRawEmailError::HttpDispatch(_) => ProviderErrorKind::Transient,
+            SendRawEmailError::Unknown(ref message) => classify_unknown(message),
+        };
+
         ProviderError {
+            kind,
             description: format!("SES error: {:?}", error),
         }
     }
 }
+
+// SES also uses MessageRejected when the sender isn't verified,
+// which is our problem rather than the message's.
+fn classify_rejection(message: &str) -> ProviderErrorKind {
+    if message.contains("not verified") {
+        ProviderErrorKind::Configuration
+    } else {
+        ProviderErrorKind::Permanent
+    }
+}
+
+// Rusoto doesn't model the common AWS errors,
+// so they arrive as `Unknown` with the raw response body.
+fn classify_unknown(message: &str) -> ProviderErrorKind {
+    let transient_codes = [
+        "Throttling",
+        "RequestTimeout",
+        "ServiceUnavailable",
+        "InternalFailure",
+    ];
+    if transient_codes.iter().any(|code| message.contains(code)) {
+        ProviderErrorKind::Transient
+    } else {
+        ProviderErrorKind::Configuration
+    }
+}
@@ -0,0 +1,156 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use lettre::{
+    smtp::{
+        authentication::{Credentials, Mechanism},
+        client::net::{ClientTlsParameters, DEFAULT_TLS_PROTOCOLS}, error::Error as SmtpError,
+        response::Category,
+    },
+    ClientSecurity, EmailTransport, Error as EmailError, SimpleSendableEmail, SmtpTransport,
+};
+use native_tls::{Error as TlsError, TlsConnector};
+
+use super::{Message, Provider, ProviderError, ProviderErrorKind};
+use dkim::{Signer, SIGNED_HEADERS};
+use mime;
+use settings::Settings;
+
+pub struct SmtpProvider<'s> {
+    host: &'s str,
+    port: u16,
+    credentials: Option<Credentials>,
+    security: &'s str,
+    signer: Option<Signer>,
+}
+
+impl<'s> SmtpProvider<'s> {
+    pub fn new(settings: &'s Settings) -> SmtpProvider<'s> {
+        let credentials = match (settings.smtp.user.as_ref(), settings.smtp.password.as_ref()) {
+            (Some(user), Some(password)) => {
+                Some(Credentials::new(user.to_string(), password.to_string()))
+            }
+            _ => None,
+        };
+
+        // Credentials shouldn't cross the network in the clear
+        // unless that's been asked for explicitly
+        let security = match settings.smtp.security {
+            Some(ref security) => security.as_str(),
+            None => if credentials.is_some() {
+                "required"
+            } else {
+                "none"
+            },
+        };
+
+        SmtpProvider {
+            host: &settings.smtp.host,
+            port: settings.smtp.port,
+            credentials,
+            security,
+            signer: settings
+                .dkim
+                .as_ref()
+                .map(|dkim| Signer::new(dkim, SIGNED_HEADERS).expect("invalid DKIM settings")),
+        }
+    }
+
+    fn client_security(&self) -> Result<ClientSecurity, ProviderError> {
+        if self.security == "none" {
+            return Ok(ClientSecurity::None);
+        }
+
+        let mut builder = TlsConnector::builder()?;
+        builder.supported_protocols(DEFAULT_TLS_PROTOCOLS)?;
+        let parameters = ClientTlsParameters::new(self.host.to_string(), builder.build()?);
+
+        Ok(match self.security {
+            "opportunistic" => ClientSecurity::Opportunistic(parameters),
+            "tls" => ClientSecurity::Wrapper(parameters),
+            _ => ClientSecurity::Required(parameters),
+        })
+    }
+}
+
+impl<'s> Provider for SmtpProvider<'s> {
+    fn send(&self, message: &Message) -> Result<String, ProviderError> {
+        let message_id = mime::generate_message_id(&message.from.address);
+        let mut composed = mime::compose(message, &message_id);
+        if let Some(ref signer) = self.signer {
+            composed = signer.sign(&composed)?;
+        }
+
+        // Bcc recipients are only included in the envelope, not the message.
+        let recipients: Vec<String> = message
+            .recipients()
+            .iter()
+            .map(|address| address.to_string())
+            .collect();
+        let email = SimpleSendableEmail::new(
+            message.from.address.to_string(),
+            &recipients,
+            message_id.clone(),
+            composed,
+        )?;
+
+        let mut builder = SmtpTransport::builder((self.host, self.port), self.client_security()?)?;
+        if let Some(ref credentials) = self.credentials {
+            // PLAIN is named explicitly so that authentication still works
+            // when `smtp.security` is `none` or STARTTLS isn't offered,
+            // which lettre would otherwise refuse.
+            builder = builder
+                .credentials(credentials.clone())
+                .authentication_mechanism(Mechanism::Plain);
+        }
+
+        let mut transport = builder.build();
+        let result = transport.send(&email);
+        transport.close();
+
+        result.map(|_| message_id).map_err(From::from)
+    }
+}
+
+impl From<SmtpError> for ProviderError {
+    fn from(error: SmtpError) -> ProviderError {
+        let kind = match error {
+            SmtpError::Transient(_) | SmtpError::Io(_) | SmtpError::Resolution => {
+                ProviderErrorKind::Transient
+            }
+            // 53x replies are about authentication, e.g. 535 for bad credentials,
+            // so they'll fail the same way for every message
+            SmtpError::Permanent(ref response)
+                if response.code.category == Category::Unspecified3 =>
+            {
+                ProviderErrorKind::Configuration
+            }
+            SmtpError::Permanent(_) => ProviderErrorKind::Permanent,
+            _ => ProviderErrorKind::Configuration,
+        };
+
+        ProviderError {
+            kind,
+            description: format!("SMTP error: {:?}", error),
+        }
+    }
+}
+
+impl From<EmailError> for ProviderError {
+    fn from(error: EmailError) -> ProviderError {
+        ProviderError {
+            kind: ProviderErrorKind::Permanent,
+            description: format!("Email error: {:?}", error),
+        }
+    }
+}
+
+impl From<TlsError> for ProviderError {
+    fn from(error: TlsError) -> ProviderError {
+        ProviderError {
+            kind: ProviderErrorKind::Configuration,
+            description: format!("TLS error: {}", error),
+        }
+    }
+}
@@ -0,0 +1,1331 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    env, fs, io::{BufRead, BufReader, Read, Write}, net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering}, mpsc, Arc,
+    },
+    thread,
+};
+
+use base64;
+use rand::{thread_rng, Rng};
+use serde_json::{self, Value};
+
+use super::*;
+use settings::{
+    AwsKeys, CircuitBreaker, Dkim, Mailgun as MailgunSettings, Mock as MockSettings, MockFault,
+    Ramp, Recorder as RecorderSettings, Retry, Sender, Sendgrid as SendgridSettings, Settings,
+    Spool as SpoolSettings,
+};
+
+#[test]
+fn smtp_send() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind error");
+    let port = listener.local_addr().expect("address error").port();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept error");
+        sender.send(smtp_sink(stream)).expect("channel error");
+    });
+
+    let mut settings = create_settings();
+    settings.provider = String::from("smtp");
+    settings.smtp.host = String::from("127.0.0.1");
+    settings.smtp.port = port;
+
+    let mut headers = HashMap::new();
+    headers.insert(String::from("X-Qux"), String::from("quux"));
+
+    let providers = Providers::new(&settings);
+    let mut message = create_message();
+    message.from = Sender {
+        address: String::from("security@firefox.com"),
+        name: String::from("Firefox Accounts Security"),
+    };
+    message.to = vec![
+        String::from("foo@example.com"),
+        String::from("qux@example.com"),
+    ];
+    message.cc = vec![String::from("bar@example.com")];
+    message.bcc = vec![String::from("quux@example.com")];
+    message.reply_to = Some(String::from("baz@example.com"));
+    message.headers = headers;
+    message.subject = String::from("wibble");
+    message.body_text = String::from("blee");
+    message.body_html = Some(String::from("<a>blee</a>"));
+    match providers.send(&message, None) {
+        Ok(delivery) => assert!(delivery.message_id.starts_with("smtp:")),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
+    let (commands, data) = receiver.recv().expect("channel error");
+    assert!(commands.contains(&String::from("MAIL FROM:<security@firefox.com>")));
+    assert!(commands.contains(&String::from("RCPT TO:<foo@example.com>")));
+    assert!(commands.contains(&String::from("RCPT TO:<qux@example.com>")));
+    assert!(commands.contains(&String::from("RCPT TO:<bar@example.com>")));
+    assert!(commands.contains(&String::from("RCPT TO:<quux@example.com>")));
+    assert!(!data.contains("quux@example.com"));
+    assert!(data.contains("Subject: wibble"));
+    assert!(
+        data.lines()
+            .any(|line| line.starts_with("Reply-To:") && line.contains("baz@example.com"))
+    );
+    assert!(data.contains("X-Qux: quux"));
+    assert!(data.contains("blee"));
+    assert!(data.contains("<a>blee</a>"));
+}
+
+#[test]
+fn smtp_send_with_credentials() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind error");
+    let port = listener.local_addr().expect("address error").port();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().expect("accept error");
+            sender.send(smtp_sink(stream)).expect("channel error");
+        }
+    });
+
+    let mut settings = create_settings();
+    settings.provider = String::from("smtp");
+    settings.smtp.host = String::from("127.0.0.1");
+    settings.smtp.port = port;
+    settings.smtp.user = Some(String::from("foo"));
+    settings.smtp.password = Some(String::from("bar"));
+
+    // The sink doesn't offer STARTTLS, so by default nothing is sent
+    {
+        let providers = Providers::new(&settings);
+        match send_to(&providers, "smtp") {
+            Ok(_) => assert!(false, "send should have failed"),
+            Err(error) => assert_eq!(error.kind, ProviderErrorKind::Configuration),
+        }
+    }
+
+    let (commands, _) = receiver.recv().expect("channel error");
+    assert!(!commands.iter().any(|command| command.starts_with("AUTH")));
+    assert!(!commands.iter().any(|command| command.starts_with("MAIL")));
+
+    settings.smtp.security = Some(String::from("none"));
+    let providers = Providers::new(&settings);
+    if let Err(error) = send_to(&providers, "smtp") {
+        assert!(false, error.description().to_string());
+    }
+
+    let (commands, _) = receiver.recv().expect("channel error");
+    assert!(commands.iter().any(|command| command.starts_with("AUTH PLAIN")));
+    assert!(commands.contains(&String::from("MAIL FROM:<accounts@firefox.com>")));
+}
+
+#[test]
+fn smtp_error_kind() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind error");
+    let port = listener.local_addr().expect("address error").port();
+
+    thread::spawn(move || {
+        let rejections = [
+            ("AUTH", "535 5.7.8 Authentication credentials invalid"),
+            ("RCPT", "550 5.1.1 User unknown"),
+        ];
+        for rejection in &rejections {
+            let (stream, _) = listener.accept().expect("accept error");
+            smtp_sink_rejecting(stream, Some(*rejection));
+        }
+    });
+
+    let mut settings = create_settings();
+    settings.provider = String::from("smtp");
+    settings.smtp.host = String::from("127.0.0.1");
+    settings.smtp.port = port;
+    settings.smtp.user = Some(String::from("foo"));
+    settings.smtp.password = Some(String::from("bar"));
+    settings.smtp.security = Some(String::from("none"));
+
+    let providers = Providers::new(&settings);
+    match send_to(&providers, "smtp") {
+        Ok(_) => assert!(false, "send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Configuration),
+    }
+
+    match send_to(&providers, "smtp") {
+        Ok(_) => assert!(false, "send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Permanent),
+    }
+}
+
+#[test]
+fn smtp_send_dkim() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind error");
+    let port = listener.local_addr().expect("address error").port();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept error");
+        sender.send(smtp_sink(stream)).expect("channel error");
+    });
+
+    let mut settings = create_settings();
+    settings.dkim = Some(Dkim {
+        algorithm: String::from("ed25519-sha256"),
+        domain: String::from("firefox.com"),
+        selector: String::from("fxa"),
+        privatekey: base64::decode(
+            "MC4CAQAwBQYDK2VwBCIEIBXE6Lzs9gUHhittjDE9kkxj7yk7rLTfUmjJvAc1jA4R",
+        ).expect("base64 error"),
+    });
+    settings.provider = String::from("smtp");
+    settings.smtp.host = String::from("127.0.0.1");
+    settings.smtp.port = port;
+
+    let providers = Providers::new(&settings);
+    if let Err(error) = send_to(&providers, "smtp") {
+        assert!(false, error.description().to_string());
+    }
+
+    let (_, data) = receiver.recv().expect("channel error");
+    assert!(data.starts_with(
+        "DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed; d=firefox.com; s=fxa; "
+    ));
+    assert!(data.contains("\nFrom: \"Firefox Accounts\" <accounts@firefox.com>\n"));
+}
+
+#[test]
+fn ses_send() {
+    let (port, receiver) = http_stand_in(
+        "200 OK",
+        &["Content-Type: text/xml"],
+        "<SendRawEmailResponse xmlns=\"http://ses.amazonaws.com/doc/2010-12-01/\">\
+         <SendRawEmailResult><MessageId>0100deadbeef</MessageId></SendRawEmailResult>\
+         <ResponseMetadata><RequestId>foo</RequestId></ResponseMetadata>\
+         </SendRawEmailResponse>",
+    );
+
+    let mut settings = create_settings();
+    settings.provider = String::from("ses");
+    settings.ses.keys = Some(AwsKeys {
+        access: String::from("A"),
+        secret: String::from("s"),
+    });
+    settings.ses.configurationset = Some(String::from("wibble"));
+    settings.ses.endpoint = Some(format!("http://127.0.0.1:{}", port));
+
+    let mut tags = HashMap::new();
+    tags.insert(String::from("template"), String::from("verify-email"));
+
+    let providers = Providers::new(&settings);
+    let mut message = create_message();
+    message.bcc = vec![String::from("bar@example.com")];
+    message.subject = String::from("baz");
+    message.body_text = String::from("qux");
+    message.tags = tags;
+    match providers.send(&message, None) {
+        Ok(delivery) => assert_eq!(delivery.message_id, "ses:0100deadbeef"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
+    let request = receiver.recv().expect("channel error");
+    assert!(request.contains("Action=SendRawEmail"));
+    assert!(request.contains("ConfigurationSetName=wibble"));
+    assert!(request.contains("Destinations.member.1=foo%40example.com"));
+    assert!(request.contains("Destinations.member.2=bar%40example.com"));
+    assert!(request.contains("Tags.member.1.Name=template"));
+    assert!(request.contains("Tags.member.1.Value=verify-email"));
+}
+
+#[test]
+fn sendgrid_send() {
+    let (port, receiver) = http_stand_in("202 Accepted", &["X-Message-Id: deadbeef"], "");
+
+    let mut settings = create_settings();
+    settings.provider = String::from("sendgrid");
+    settings.sendgrid = Some(SendgridSettings {
+        key: String::from("0"),
+        sandbox: false,
+        baseuri: format!("http://127.0.0.1:{}/", port),
+        webhookkey: None,
+    });
+
+    let mut metadata = HashMap::new();
+    metadata.insert(String::from("flow_id"), String::from("abad1dea"));
+
+    let providers = Providers::new(&settings);
+    let mut message = create_message();
+    message.categories = vec![String::from("verify-email")];
+    message.metadata = metadata;
+    match providers.send(&message, None) {
+        Ok(delivery) => assert_eq!(delivery.message_id, "sendgrid:deadbeef"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
+    let request = receiver.recv().expect("channel error");
+    assert!(request.starts_with("POST /v3/mail/send HTTP/1.1\r\n"));
+    assert!(request.contains("Bearer 0"));
+
+    let body: Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap())
+        .expect("JSON error");
+    assert_eq!(body["categories"], json!(["verify-email"]));
+    assert_eq!(body["custom_args"], json!({ "flow_id": "abad1dea" }));
+    assert_eq!(body["mail_settings"], Value::Null);
+}
+
+#[test]
+fn sendgrid_sandbox() {
+    let (port, receiver) = http_stand_in("200 OK", &[], "");
+
+    let mut settings = create_settings();
+    settings.provider = String::from("sendgrid");
+    settings.sendgrid = Some(SendgridSettings {
+        key: String::from("0"),
+        sandbox: true,
+        baseuri: format!("http://127.0.0.1:{}/", port),
+        webhookkey: None,
+    });
+
+    let providers = Providers::new(&settings);
+    let message = create_message();
+    match providers.send(&message, None) {
+        Ok(delivery) => assert_eq!(delivery.message_id, "sendgrid:sandbox"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
+    let request = receiver.recv().expect("channel error");
+    let body: Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap())
+        .expect("JSON error");
+    assert_eq!(
+        body["mail_settings"],
+        json!({ "sandbox_mode": { "enable": true } })
+    );
+}
+
+#[test]
+fn mailgun_send() {
+    let (port, receiver) = http_stand_in(
+        "200 OK",
+        &["Content-Type: application/json"],
+        r#"{"id":"<20180601.1@mail.example.com>","message":"Queued. Thank you."}"#,
+    );
+
+    let mut settings = create_settings();
+    settings.provider = String::from("mailgun");
+    settings.mailgun = Some(MailgunSettings {
+        key: String::from("key-0123456789abcdef0123456789abcdef"),
+        domain: String::from("mail.example.com"),
+        baseuri: format!("http://127.0.0.1:{}/v3/", port),
+    });
+
+    let mut headers = HashMap::new();
+    headers.insert(String::from("X-Qux"), String::from("quux"));
+
+    let providers = Providers::new(&settings);
+    let mut message = create_message();
+    message.cc = vec![String::from("bar@example.com")];
+    message.bcc = vec![String::from("quux@example.com")];
+    message.reply_to = Some(String::from("baz@example.com"));
+    message.headers = headers;
+    message.subject = String::from("wibble");
+    message.body_text = String::from("blee");
+    message.body_html = Some(String::from("<a>blee</a>"));
+    message.attachments = vec![Attachment {
+        filename: String::from("foo.txt"),
+        content_type: String::from("text/plain"),
+        content: b"attached".to_vec(),
+        disposition: Disposition::Attachment,
+        content_id: None,
+    }];
+    match providers.send(&message, None) {
+        Ok(delivery) => assert_eq!(delivery.message_id, "mailgun:20180601.1@mail.example.com"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
+    let request = receiver.recv().expect("channel error");
+    assert!(request.starts_with("POST /v3/mail.example.com/messages HTTP/1.1\r\n"));
+    // base64("api:key-0123456789abcdef0123456789abcdef")
+    assert!(request.contains(
+        "Basic YXBpOmtleS0wMTIzNDU2Nzg5YWJjZGVmMDEyMzQ1Njc4OWFiY2RlZg=="
+    ));
+    assert!(request.contains("Firefox Accounts <accounts@firefox.com>"));
+    assert!(request.contains("name=\"to\"\r\n\r\nfoo@example.com"));
+    assert!(request.contains("name=\"cc\"\r\n\r\nbar@example.com"));
+    assert!(request.contains("name=\"bcc\"\r\n\r\nquux@example.com"));
+    assert!(request.contains("name=\"h:Reply-To\"\r\n\r\nbaz@example.com"));
+    assert!(request.contains("name=\"h:X-Qux\"\r\n\r\nquux"));
+    assert!(request.contains("name=\"subject\"\r\n\r\nwibble"));
+    assert!(request.contains("name=\"html\"\r\n\r\n<a>blee</a>"));
+    assert!(request.contains("name=\"attachment\"; filename=\"foo.txt\""));
+    assert!(request.contains("Content-Type: text/plain"));
+    assert!(request.contains("attached"));
+}
+
+#[test]
+fn mailgun_error_status() {
+    let (port, _receiver) = http_stand_in(
+        "429 Too Many Requests",
+        &["Content-Type: application/json"],
+        "{}",
+    );
+
+    let mut settings = create_settings();
+    settings.provider = String::from("mailgun");
+    settings.mailgun = Some(MailgunSettings {
+        key: String::from("key-0123456789abcdef0123456789abcdef"),
+        domain: String::from("mail.example.com"),
+        baseuri: format!("http://127.0.0.1:{}/v3/", port),
+    });
+
+    let providers = Providers::new(&settings);
+    let message = create_message();
+    match providers.send(&message, None) {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => {
+            assert_eq!(error.description(), "Mailgun response: 429 Too Many Requests");
+            assert_eq!(error.kind, ProviderErrorKind::Transient);
+        }
+    }
+}
+
+#[test]
+fn failover() {
+    let failover = vec![String::from("mock")];
+    let routing = Routing::default();
+    let providers = create_providers("fail", &failover, &routing);
+    let message = create_message();
+    match providers.send(&message, None) {
+        Ok(delivery) => assert_eq!(delivery.message_id, "mock:deadbeef"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+}
+
+#[test]
+fn failover_exhausted() {
+    let failover = vec![String::from("fail"), String::from("wibble")];
+    let routing = Routing::default();
+    let providers = create_providers("mock", &failover, &routing);
+    let message = create_message();
+    match providers.send(&message, Some("fail")) {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => {
+            assert_eq!(
+                error.description(),
+                "failed to send; Invalid provider `wibble`"
+            );
+            assert_eq!(error.kind, ProviderErrorKind::Transient);
+        }
+    }
+}
+
+#[test]
+fn failover_permanent_error() {
+    let failover = vec![String::from("mock")];
+    let routing = Routing::default();
+    let providers = create_providers("reject", &failover, &routing);
+    let message = create_message();
+    match providers.send(&message, None) {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => {
+            assert_eq!(error.description(), "failed to send");
+            assert_eq!(error.kind, ProviderErrorKind::Permanent);
+        }
+    }
+}
+
+#[test]
+fn provider_error_status() {
+    let error = ProviderError::new(ProviderErrorKind::Transient, String::from("foo"));
+    assert_eq!(
+        HttpError::from(error),
+        HttpError::with_retry_after(Status::ServiceUnavailable, RETRY_AFTER)
+    );
+
+    let error = ProviderError::new(ProviderErrorKind::Permanent, String::from("foo"));
+    assert_eq!(
+        HttpError::from(error),
+        HttpError::new(Status::UnprocessableEntity)
+    );
+
+    let error = ProviderError::new(ProviderErrorKind::Configuration, String::from("foo"));
+    assert_eq!(
+        HttpError::from(error),
+        HttpError::new(Status::InternalServerError)
+    );
+}
+
+#[test]
+fn route_by_weight() {
+    let failover = Vec::new();
+    let routing = Routing {
+        sticky: false,
+        weights: vec![create_weight("fail", 0, None), create_weight("mock", 10, None)],
+    };
+    let providers = create_providers("fail", &failover, &routing);
+    for _ in 0..10 {
+        assert_eq!(providers.route("foo@example.com"), "mock");
+    }
+}
+
+#[test]
+fn route_without_weights() {
+    let failover = Vec::new();
+    let routing = Routing {
+        sticky: false,
+        weights: vec![create_weight("mock", 0, None)],
+    };
+    let providers = create_providers("fail", &failover, &routing);
+    assert_eq!(providers.route("foo@example.com"), "fail");
+}
+
+#[test]
+fn route_sticky() {
+    let failover = Vec::new();
+    let routing = Routing {
+        sticky: true,
+        weights: vec![create_weight("fail", 50, None), create_weight("mock", 50, None)],
+    };
+    let providers = create_providers("fail", &failover, &routing);
+    for _ in 0..10 {
+        assert_eq!(providers.route("foo@example.com"), "mock");
+        assert_eq!(providers.route("FOO@example.com"), "mock");
+        assert_eq!(providers.route("qux@example.com"), "fail");
+    }
+}
+
+#[test]
+fn ramp_weight() {
+    let weight = create_weight(
+        "sendgrid",
+        90,
+        Some(Ramp {
+            start: 1000,
+            period: 100,
+            from: 10,
+        }),
+    );
+    assert_eq!(current_weight(&weight, 0), 10);
+    assert_eq!(current_weight(&weight, 1000), 10);
+    assert_eq!(current_weight(&weight, 1050), 50);
+    assert_eq!(current_weight(&weight, 1099), 89);
+    assert_eq!(current_weight(&weight, 1100), 90);
+    assert_eq!(current_weight(&weight, 2000), 90);
+
+    let weight = create_weight(
+        "ses",
+        10,
+        Some(Ramp {
+            start: 1000,
+            period: 100,
+            from: 90,
+        }),
+    );
+    assert_eq!(current_weight(&weight, 1000), 90);
+    assert_eq!(current_weight(&weight, 1050), 50);
+    assert_eq!(current_weight(&weight, 1100), 10);
+}
+
+#[test]
+fn circuit_opens_after_consecutive_failures() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let providers = create_providers("fail", &failover, &routing);
+
+    for _ in 0..2 {
+        match send_to(&providers, "fail") {
+            Ok(_) => assert!(false, "Providers::send should have failed"),
+            Err(error) => assert_eq!(error.description(), "failed to send"),
+        }
+    }
+
+    match send_to(&providers, "fail") {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => {
+            assert_eq!(error.description(), "Circuit open for provider `fail`");
+            assert_eq!(error.kind, ProviderErrorKind::Transient);
+        }
+    }
+
+    let health = providers.health();
+    assert_eq!(health.get("fail"), Some(&CircuitState::Open));
+    assert_eq!(health.get("mock"), Some(&CircuitState::Closed));
+}
+
+#[test]
+fn circuit_fails_over_when_open() {
+    let failover = vec![String::from("mock")];
+    let routing = Routing::default();
+    let providers = create_providers("fail", &failover, &routing);
+
+    for _ in 0..3 {
+        match send_to(&providers, "fail") {
+            Ok(delivery) => assert_eq!(delivery.message_id, "mock:deadbeef"),
+            Err(error) => assert!(false, error.description().to_string()),
+        }
+    }
+
+    assert_eq!(providers.health().get("fail"), Some(&CircuitState::Open));
+}
+
+#[test]
+fn circuit_probe() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let providers = create_providers("fail", &failover, &routing);
+    let failure = Err(ProviderError::new(
+        ProviderErrorKind::Transient,
+        String::from("foo"),
+    ));
+
+    providers.record_result("fail", &failure, 0);
+    assert_eq!(providers.circuit_state("fail", 0), CircuitState::Closed);
+    providers.record_result("fail", &failure, 0);
+    assert_eq!(providers.circuit_state("fail", 0), CircuitState::Open);
+    assert!(providers.check_circuit("fail", 999).is_err());
+
+    // Only one probe is let through at a time
+    assert_eq!(providers.circuit_state("fail", 1000), CircuitState::HalfOpen);
+    assert!(providers.check_circuit("fail", 1000).is_ok());
+    assert!(providers.check_circuit("fail", 1001).is_err());
+
+    // A failed probe opens the circuit again
+    providers.record_result("fail", &failure, 1002);
+    assert_eq!(providers.circuit_state("fail", 1500), CircuitState::Open);
+    assert!(providers.check_circuit("fail", 1500).is_err());
+
+    // A successful probe closes it
+    assert!(providers.check_circuit("fail", 2002).is_ok());
+    providers.record_result("fail", &Ok(String::from("bar")), 2003);
+    assert_eq!(providers.circuit_state("fail", 2003), CircuitState::Closed);
+    assert!(providers.check_circuit("fail", 2003).is_ok());
+}
+
+#[test]
+fn circuit_ignores_permanent_errors() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let providers = create_providers("reject", &failover, &routing);
+
+    for _ in 0..3 {
+        match send_to(&providers, "reject") {
+            Ok(_) => assert!(false, "Providers::send should have failed"),
+            Err(error) => assert_eq!(error.kind, ProviderErrorKind::Permanent),
+        }
+    }
+
+    assert_eq!(providers.health().get("reject"), Some(&CircuitState::Closed));
+}
+
+#[test]
+fn route_skips_open_circuits() {
+    let failover = Vec::new();
+    let routing = Routing {
+        sticky: false,
+        weights: vec![create_weight("fail", 50, None), create_weight("mock", 50, None)],
+    };
+    let providers = create_providers("fail", &failover, &routing);
+    let failure = Err(ProviderError::new(
+        ProviderErrorKind::Configuration,
+        String::from("foo"),
+    ));
+    providers.record_result("fail", &failure, now());
+    providers.record_result("fail", &failure, now());
+
+    for _ in 0..10 {
+        assert_eq!(providers.route("foo@example.com"), "mock");
+    }
+}
+
+#[test]
+fn spool_removes_sent_messages() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let mut providers = create_providers("mock", &failover, &routing);
+    let (spool, directory) = create_spool();
+    providers.spool = Some(spool);
+
+    match send_to(&providers, "mock") {
+        Ok(delivery) => {
+            assert_eq!(delivery.message_id, "mock:deadbeef");
+            assert!(!delivery.spooled);
+        }
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+    assert_eq!(providers.spooled().len(), 0);
+
+    match send_to(&providers, "reject") {
+        Ok(_) => assert!(false, "send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Permanent),
+    }
+    assert_eq!(providers.spooled().len(), 0);
+
+    fs::remove_dir_all(directory).expect("cleanup error");
+}
+
+#[test]
+fn spool_keeps_transient_failures() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    // Keep the circuit closed, so that replays reach the provider
+    let circuit_breaker = CircuitBreaker::default();
+    let mut providers = create_providers("fail", &failover, &routing);
+    providers.circuit_breaker = &circuit_breaker;
+    let (spool, directory) = create_spool();
+    providers.spool = Some(spool);
+
+    let delivery = send_to(&providers, "fail").expect("send should have been spooled");
+    assert!(delivery.spooled);
+    let message_id = delivery.message_id;
+    assert!(message_id.starts_with("spool:"));
+
+    let entries = providers.spooled();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(format!("spool:{}", entries[0].0), message_id);
+    assert_eq!(entries[0].1.provider, Some(String::from("fail")));
+
+    // Still failing, so the entry stays put
+    let mut sent = Vec::new();
+    assert_eq!(
+        providers.replay(|spool_message_id, message_id| {
+            sent.push((spool_message_id.to_string(), message_id.to_string()))
+        }),
+        1
+    );
+    assert_eq!(providers.spooled().len(), 1);
+    assert!(sent.is_empty());
+
+    providers.add("fail", Box::new(Mock::default()));
+    assert_eq!(
+        providers.replay(|spool_message_id, message_id| {
+            sent.push((spool_message_id.to_string(), message_id.to_string()))
+        }),
+        0
+    );
+    assert_eq!(providers.spooled().len(), 0);
+    assert_eq!(sent, vec![(message_id, String::from("fail:deadbeef"))]);
+
+    fs::remove_dir_all(directory).expect("cleanup error");
+}
+
+#[test]
+fn replay_skips_messages_in_flight() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let mut providers = create_providers("mock", &failover, &routing);
+    let (spool, directory) = create_spool();
+    providers.spool = Some(spool);
+
+    let message = create_message();
+    let spool_id = providers
+        .spool(&message, Some("mock"))
+        .expect("spool error");
+    assert!(spool_id.is_some());
+    assert_eq!(providers.spooled().len(), 1);
+
+    // The entry belongs to whoever spooled it until it's settled
+    assert_eq!(providers.replay(|_, _| assert!(false, "nothing should be sent")), 0);
+    assert_eq!(providers.spooled().len(), 1);
+
+    match providers.send_spooled(spool_id, &message, Some("mock")) {
+        Ok(delivery) => assert_eq!(delivery.message_id, "mock:deadbeef"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+    assert_eq!(providers.spooled().len(), 0);
+
+    let spool_id = providers
+        .spool(&message, Some("mock"))
+        .expect("spool error");
+    providers.unspool(spool_id);
+    assert_eq!(providers.spooled().len(), 0);
+    assert!(providers.in_flight.lock().unwrap().is_empty());
+
+    fs::remove_dir_all(directory).expect("cleanup error");
+}
+
+#[test]
+fn add_provider() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let mut providers = create_providers("mock", &failover, &routing);
+    providers.add("custom", Box::new(Mock::default()));
+
+    match send_to(&providers, "custom") {
+        Ok(delivery) => assert_eq!(delivery.message_id, "custom:deadbeef"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+    assert_eq!(providers.health().get("custom"), Some(&CircuitState::Closed));
+
+    // Registering an existing id replaces that provider
+    providers.add("custom", Box::new(FailingProvider(ProviderErrorKind::Permanent)));
+    match send_to(&providers, "custom") {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Permanent),
+    }
+}
+
+#[test]
+fn retry_transient_failures() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let circuit_breaker = CircuitBreaker::default();
+    let retry = create_retry(3, 1000);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut providers = create_providers("flaky", &failover, &routing);
+    providers.circuit_breaker = &circuit_breaker;
+    providers.retry = &retry;
+    providers.add(
+        "flaky",
+        Box::new(FlakyProvider {
+            kind: ProviderErrorKind::Transient,
+            failures: 2,
+            calls: calls.clone(),
+        }),
+    );
+
+    match send_to(&providers, "flaky") {
+        Ok(delivery) => {
+            assert_eq!(delivery.message_id, "flaky:deadbeef");
+            assert_eq!(delivery.attempts, 3);
+        }
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn retry_gives_up_after_max_attempts() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let circuit_breaker = CircuitBreaker::default();
+    let retry = create_retry(3, 1000);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut providers = create_providers("flaky", &failover, &routing);
+    providers.circuit_breaker = &circuit_breaker;
+    providers.retry = &retry;
+    providers.add(
+        "flaky",
+        Box::new(FlakyProvider {
+            kind: ProviderErrorKind::Transient,
+            failures: 10,
+            calls: calls.clone(),
+        }),
+    );
+
+    match send_to(&providers, "flaky") {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Transient),
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn retry_respects_deadline() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let circuit_breaker = CircuitBreaker::default();
+    let mut retry = create_retry(3, 0);
+    retry.basedelay = 100;
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut providers = create_providers("flaky", &failover, &routing);
+    providers.circuit_breaker = &circuit_breaker;
+    providers.retry = &retry;
+    providers.add(
+        "flaky",
+        Box::new(FlakyProvider {
+            kind: ProviderErrorKind::Transient,
+            failures: 1,
+            calls: calls.clone(),
+        }),
+    );
+
+    match send_to(&providers, "flaky") {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Transient),
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn retry_ignores_permanent_errors() {
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let circuit_breaker = CircuitBreaker::default();
+    let retry = create_retry(3, 1000);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut providers = create_providers("flaky", &failover, &routing);
+    providers.circuit_breaker = &circuit_breaker;
+    providers.retry = &retry;
+    providers.add(
+        "flaky",
+        Box::new(FlakyProvider {
+            kind: ProviderErrorKind::Permanent,
+            failures: 1,
+            calls: calls.clone(),
+        }),
+    );
+
+    match send_to(&providers, "flaky") {
+        Ok(_) => assert!(false, "Providers::send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Permanent),
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn attempts_include_failover() {
+    let failover = vec![String::from("mock")];
+    let routing = Routing::default();
+    let providers = create_providers("fail", &failover, &routing);
+
+    match send_to(&providers, "fail") {
+        Ok(delivery) => {
+            assert_eq!(delivery.message_id, "mock:deadbeef");
+            assert_eq!(delivery.attempts, 2);
+        }
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+}
+
+#[test]
+fn mock_magic_addresses() {
+    let mock = Mock::new(&MockSettings {
+        magicaddresses: true,
+        maxdelay: 100,
+        ..MockSettings::default()
+    });
+
+    match mock_send(&mock, "foo@example.com") {
+        Ok(message_id) => assert_eq!(message_id, "deadbeef"),
+        Err(error) => assert!(false, error.description().to_string()),
+    }
+
+    for &(address, kind) in &[
+        ("transient@mock.invalid", ProviderErrorKind::Transient),
+        ("permanent-1@mock.invalid", ProviderErrorKind::Permanent),
+        ("configuration@MOCK.invalid", ProviderErrorKind::Configuration),
+    ] {
+        match mock_send(&mock, address) {
+            Ok(_) => assert!(false, "send should have failed"),
+            Err(error) => assert_eq!(error.kind, kind),
+        }
+    }
+
+    let started = SystemTime::now();
+    assert!(mock_send(&mock, "delay-50@mock.invalid").is_ok());
+    assert!(started.elapsed().unwrap() >= Duration::from_millis(50));
+
+    // Delays are capped
+    let started = SystemTime::now();
+    assert!(mock_send(&mock, "delay-60000@mock.invalid").is_ok());
+    let elapsed = started.elapsed().unwrap();
+    assert!(elapsed >= Duration::from_millis(100));
+    assert!(elapsed < Duration::from_secs(10));
+}
+
+#[test]
+fn mock_magic_addresses_disabled() {
+    let mock = Mock::new(&MockSettings {
+        maxdelay: 100,
+        ..MockSettings::default()
+    });
+
+    for address in &[
+        "transient@mock.invalid",
+        "permanent-1@mock.invalid",
+        "configuration@MOCK.invalid",
+    ] {
+        match mock_send(&mock, address) {
+            Ok(message_id) => assert_eq!(message_id, "deadbeef"),
+            Err(error) => assert!(false, error.description().to_string()),
+        }
+    }
+
+    let started = SystemTime::now();
+    assert!(mock_send(&mock, "delay-60000@mock.invalid").is_ok());
+    assert!(started.elapsed().unwrap() < Duration::from_millis(100));
+}
+
+#[test]
+fn mock_settings() {
+    let mock = Mock::new(&MockSettings {
+        latency: 20,
+        failurerate: 0,
+        faults: vec![MockFault {
+            pattern: String::from("^bounce-.*@example\\.com$"),
+            kind: String::from("permanent"),
+        }],
+        magicaddresses: false,
+        maxdelay: 0,
+    });
+
+    match mock_send(&mock, "bounce-foo@example.com") {
+        Ok(_) => assert!(false, "send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Permanent),
+    }
+
+    let started = SystemTime::now();
+    assert!(mock_send(&mock, "foo@example.com").is_ok());
+    assert!(started.elapsed().unwrap() >= Duration::from_millis(20));
+
+    let mock = Mock::new(&MockSettings {
+        latency: 0,
+        failurerate: 100,
+        faults: Vec::new(),
+        magicaddresses: false,
+        maxdelay: 0,
+    });
+    match mock_send(&mock, "foo@example.com") {
+        Ok(_) => assert!(false, "send should have failed"),
+        Err(error) => assert_eq!(error.kind, ProviderErrorKind::Transient),
+    }
+}
+
+#[test]
+fn recorder_send() {
+    let mut settings = create_settings();
+    settings.provider = String::from("recorder");
+    settings.recorder.enabled = true;
+    settings.recorder.directory = None;
+
+    let providers = Providers::new(&settings);
+    let recorder = providers.recorder().expect("recorder should be enabled");
+    let message_id = send_to(&providers, "recorder")
+        .expect("send should have succeeded")
+        .message_id;
+
+    let recordings = recorder.recordings().expect("recordings error");
+    assert_eq!(recordings.len(), 1);
+    assert_eq!(format!("recorder:{}", recordings[0].id), message_id);
+    assert_eq!(recordings[0].message.to, vec![String::from("foo@example.com")]);
+    assert_eq!(recordings[0].message.subject, "bar");
+    assert_eq!(recordings[0].message.body_text, "baz");
+    assert!(recordings[0].is_for("foo"));
+    assert!(recordings[0].is_for("FOO@example.com"));
+    assert!(!recordings[0].is_for("bar"));
+}
+
+#[test]
+fn recorder_directory() {
+    let mut rng = thread_rng();
+    let directory = env::temp_dir()
+        .join(format!("fxa-email-recorder-{:016x}", rng.gen::<u64>()))
+        .to_string_lossy()
+        .into_owned();
+    let recorder = Arc::new(Recorder::new(&RecorderSettings {
+        enabled: true,
+        directory: Some(directory.clone()),
+    }));
+
+    let failover = Vec::new();
+    let routing = Routing::default();
+    let mut providers = create_providers("mock", &failover, &routing);
+    providers.add(
+        "recorder",
+        Box::new(RecorderProvider::new(recorder.clone())),
+    );
+
+    // Recordings are keyed by the recorder's own id, without the provider prefix
+    let first = send_to(&providers, "recorder").expect("send error").message_id[9..].to_string();
+    thread::sleep(Duration::from_millis(2));
+    let second = send_to(&providers, "recorder").expect("send error").message_id[9..].to_string();
+    assert!(
+        fs::metadata(format!("{}/{}.json", directory, first))
+            .expect("recording should exist")
+            .is_file()
+    );
+
+    // A fresh recorder sees what was written by the last one
+    let reloaded = Recorder::new(&RecorderSettings {
+        enabled: true,
+        directory: Some(directory.clone()),
+    });
+    let recordings = reloaded.recordings().expect("recordings error");
+    assert_eq!(recordings.len(), 2);
+    assert_eq!(recordings[0].id, first);
+    assert_eq!(recordings[1].id, second);
+
+    recorder.clear(Some("bar")).expect("clear error");
+    assert_eq!(recorder.recordings().expect("recordings error").len(), 2);
+    recorder.clear(Some("foo@example.com")).expect("clear error");
+    assert_eq!(recorder.recordings().expect("recordings error").len(), 0);
+
+    fs::remove_dir_all(directory).expect("cleanup error");
+}
+
+fn send_to(providers: &Providers, provider_id: &str) -> Result<Delivery, ProviderError> {
+    providers.send(&create_message(), Some(provider_id))
+}
+
+fn mock_send(mock: &Mock, address: &str) -> Result<String, ProviderError> {
+    let mut message = create_message();
+    message.to = vec![address.to_string()];
+    mock.send(&message)
+}
+
+fn create_message() -> Message {
+    Message {
+        from: Sender {
+            address: String::from("accounts@firefox.com"),
+            name: String::from("Firefox Accounts"),
+        },
+        to: vec![String::from("foo@example.com")],
+        cc: Vec::new(),
+        bcc: Vec::new(),
+        reply_to: None,
+        headers: HashMap::new(),
+        subject: String::from("bar"),
+        body_text: String::from("baz"),
+        body_html: None,
+        attachments: Vec::new(),
+        tags: HashMap::new(),
+        categories: Vec::new(),
+        metadata: HashMap::new(),
+    }
+}
+
+// SES is always configured, which needs a valid region
+fn create_settings() -> Settings {
+    let mut settings = Settings::default();
+    settings.ses.region = String::from("us-east-1");
+    settings
+}
+
+fn create_weight(provider: &str, weight: u8, ramp: Option<Ramp>) -> ProviderWeight {
+    ProviderWeight {
+        provider: provider.to_string(),
+        weight,
+        ramp,
+    }
+}
+
+fn create_retry(attempts: u32, deadline: u64) -> Retry {
+    Retry {
+        attempts,
+        basedelay: 1,
+        jitter: 0,
+        deadline,
+    }
+}
+
+fn create_providers<'s>(
+    default_provider: &'s str,
+    failover: &'s [String],
+    routing: &'s Routing,
+) -> Providers<'s> {
+    lazy_static! {
+        static ref CIRCUIT_BREAKER: CircuitBreaker = CircuitBreaker {
+            threshold: 2,
+            cooldown: 1000,
+        };
+        static ref RETRY: Retry = Retry {
+            attempts: 1,
+            basedelay: 0,
+            jitter: 0,
+            deadline: 0,
+        };
+    }
+
+    let mut providers = Providers {
+        circuit_breaker: &CIRCUIT_BREAKER,
+        default_provider,
+        failover,
+        health: Mutex::new(HashMap::new()),
+        in_flight: Mutex::new(HashSet::new()),
+        providers: HashMap::new(),
+        recorder: None,
+        retry: &RETRY,
+        routing,
+        spool: None,
+    };
+    providers.add("fail", Box::new(FailingProvider(ProviderErrorKind::Transient)));
+    providers.add("reject", Box::new(FailingProvider(ProviderErrorKind::Permanent)));
+    providers.add("mock", Box::new(Mock::default()));
+    providers
+}
+
+fn create_spool() -> (Spool, String) {
+    let mut rng = thread_rng();
+    let directory = env::temp_dir()
+        .join(format!("fxa-email-spool-{:016x}", rng.gen::<u64>()))
+        .to_string_lossy()
+        .into_owned();
+    let spool = Spool::new(&SpoolSettings {
+        directory: directory.clone(),
+    });
+    (spool, directory)
+}
+
+struct FailingProvider(ProviderErrorKind);
+
+impl Provider for FailingProvider {
+    fn send(&self, _message: &Message) -> Result<String, ProviderError> {
+        Err(ProviderError::new(self.0, String::from("failed to send")))
+    }
+}
+
+// Fails a set number of times before succeeding, counting every call
+struct FlakyProvider {
+    kind: ProviderErrorKind,
+    failures: usize,
+    calls: Arc<AtomicUsize>,
+}
+
+impl Provider for FlakyProvider {
+    fn send(&self, _message: &Message) -> Result<String, ProviderError> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+            Err(ProviderError::new(self.kind, String::from("failed to send")))
+        } else {
+            Ok(String::from("deadbeef"))
+        }
+    }
+}
+
+// A minimal SMTP sink that accepts a single message
+// and returns the commands it received, plus the message data.
+fn smtp_sink<S: ::std::io::Read + Write>(stream: S) -> (Vec<String>, String) {
+    smtp_sink_rejecting(stream, None)
+}
+
+// Like `smtp_sink`, but replies to commands that start with
+// the first part of `rejection` with the second part
+fn smtp_sink_rejecting<S: ::std::io::Read + Write>(
+    stream: S,
+    rejection: Option<(&str, &str)>,
+) -> (Vec<String>, String) {
+    let mut reader = BufReader::new(stream);
+    let mut commands = Vec::new();
+    let mut data = String::new();
+    let mut in_data = false;
+
+    reply(&mut reader, "220 localhost ESMTP sink");
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let line = line.trim_right_matches("\r\n").to_string();
+
+        if in_data {
+            if line == "." {
+                in_data = false;
+                reply(&mut reader, "250 OK");
+            } else {
+                data.push_str(&line);
+                data.push_str("\n");
+            }
+            continue;
+        }
+
+        let command = line.to_uppercase();
+        if let Some((prefix, response)) = rejection {
+            if command.starts_with(prefix) {
+                reply(&mut reader, response);
+                commands.push(line);
+                continue;
+            }
+        }
+
+        if command.starts_with("EHLO") {
+            reply(&mut reader, "250-localhost\r\n250 AUTH PLAIN");
+        } else if command.starts_with("DATA") {
+            in_data = true;
+            reply(&mut reader, "354 Go ahead");
+        } else if command.starts_with("QUIT") {
+            reply(&mut reader, "221 Bye");
+            break;
+        } else {
+            reply(&mut reader, "250 OK");
+        }
+
+        commands.push(line);
+    }
+
+    (commands, data)
+}
+
+fn reply<S: ::std::io::Read + Write>(reader: &mut BufReader<S>, response: &str) {
+    let stream = reader.get_mut();
+    stream
+        .write_all(format!("{}\r\n", response).as_bytes())
+        .expect("write error");
+    stream.flush().expect("flush error");
+}
+
+// A minimal HTTP server that accepts a single request, responds to it
+// with the given status, headers and body, and returns the raw request.
+fn http_stand_in(
+    status: &'static str,
+    headers: &'static [&'static str],
+    body: &'static str,
+) -> (u16, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind error");
+    let port = listener.local_addr().expect("address error").port();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept error");
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        let mut content_length = 0;
+        let mut chunked = false;
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            let lowercase = line.to_lowercase();
+            if lowercase.starts_with("content-length:") {
+                content_length = lowercase[15..].trim().parse().expect("content-length error");
+            } else if lowercase.starts_with("transfer-encoding:") && lowercase.contains("chunked") {
+                chunked = true;
+            }
+            request.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+
+        let mut content = Vec::new();
+        if chunked {
+            loop {
+                let mut size = String::new();
+                reader.read_line(&mut size).expect("read error");
+                let size = usize::from_str_radix(size.trim(), 16).expect("chunk size error");
+                let mut chunk = vec![0; size + 2];
+                reader.read_exact(&mut chunk).expect("read error");
+                if size == 0 {
+                    break;
+                }
+                content.extend_from_slice(&chunk[..size]);
+            }
+        } else {
+            content.resize(content_length, 0);
+            reader.read_exact(&mut content).expect("read error");
+        }
+        request.push_str(&String::from_utf8_lossy(&content));
+
+        let stream = reader.get_mut();
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    headers
+                        .iter()
+                        .map(|header| format!("{}\r\n", header))
+                        .collect::<String>(),
+                    body.len(),
+                    body
+                ).as_bytes(),
+            )
+            .expect("write error");
+        stream.flush().expect("flush error");
+
+        sender.send(request).expect("channel error");
+    });
+
+    (port, receiver)
+}
@@ -0,0 +1,109 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::HashMap, fs::{self, File}, io::{Error as IoError, Read}, path::PathBuf,
+    sync::Mutex, thread, time::{Duration, Instant},
+};
+
+use super::{Message, Queue, QueueError, BATCH_SIZE};
+
+// Like SQS, received messages are hidden from other receives
+// for this many seconds unless they're deleted first
+const VISIBILITY_TIMEOUT: u64 = 30;
+
+// How long an empty receive waits, so that callers don't spin
+const POLL_INTERVAL: u64 = 1;
+
+/// A queue for developing and testing offline,
+/// stored as a directory of JSON files in the same format as SQS message bodies.
+/// Files are received oldest first and any without a `.json` extension are ignored,
+/// so they can be written to a temporary name and renamed into place.
+pub struct LocalQueue {
+    directory: PathBuf,
+    // Visibility only needs to survive for the lifetime of the process
+    hidden: Mutex<HashMap<String, Instant>>,
+}
+
+impl LocalQueue {
+    pub fn new(directory: PathBuf) -> LocalQueue {
+        fs::create_dir_all(&directory).expect("failed to create local queue directory");
+        LocalQueue {
+            directory,
+            hidden: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", id))
+    }
+}
+
+impl Queue for LocalQueue {
+    fn receive(&self) -> Result<Vec<Message>, QueueError> {
+        let now = Instant::now();
+        let mut hidden = self.hidden.lock().expect("local queue lock poisoned");
+        hidden.retain(|_, visible_at| *visible_at > now);
+
+        let mut paths = Vec::new();
+        for dir_entry in fs::read_dir(&self.directory)? {
+            let path = dir_entry?.path();
+            if path.extension().map_or(false, |extension| extension == "json") {
+                let modified = fs::metadata(&path)?.modified()?;
+                paths.push((modified, path));
+            }
+        }
+        paths.sort_by_key(|&(modified, _)| modified);
+
+        let mut messages = Vec::new();
+        for (_, path) in paths {
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) if !hidden.contains_key(id) => id.to_string(),
+                _ => continue,
+            };
+
+            let mut body = String::new();
+            File::open(&path)?.read_to_string(&mut body)?;
+            hidden.insert(id.clone(), now + Duration::from_secs(VISIBILITY_TIMEOUT));
+            messages.push(Message { id, body });
+
+            if messages.len() == BATCH_SIZE {
+                break;
+            }
+        }
+
+        if messages.is_empty() {
+            drop(hidden);
+            thread::sleep(Duration::from_secs(POLL_INTERVAL));
+        }
+
+        Ok(messages)
+    }
+
+    fn delete(&self, message: &Message) -> Result<(), QueueError> {
+        fs::remove_file(self.path(&message.id))?;
+        self.hidden
+            .lock()
+            .expect("local queue lock poisoned")
+            .remove(&message.id);
+        Ok(())
+    }
+
+    fn change_visibility(&self, message: &Message, timeout: u64) -> Result<(), QueueError> {
+        self.hidden
+            .lock()
+            .expect("local queue lock poisoned")
+            .insert(
+                message.id.clone(),
+                Instant::now() + Duration::from_secs(timeout),
+            );
+        Ok(())
+    }
+}
+
+impl From<IoError> for QueueError {
+    fn from(error: IoError) -> QueueError {
+        QueueError::new(format!("local queue I/O error: {:?}", error))
+    }
+}
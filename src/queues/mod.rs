@@ -0,0 +1,282 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Consumes the bounce, complaint and delivery notifications
+//! that SES publishes to SQS, via SNS,
+//! or that are written to a local directory when developing offline.
+//! Bounces and complaints are recorded in the auth db
+//! so that `Bounces::check` can see them,
+//! and every notification updates the status of its message
+//! if `status.directory` is set.
+
+use std::{
+    boxed::Box, error::Error, fmt::{self, Display, Formatter}, path::Path,
+};
+
+use serde_json::Error as JsonError;
+
+use self::{
+    local::LocalQueue, notification::{Bounce, Complaint, Notification, NotificationType},
+    sqs::SqsQueue,
+};
+use auth_db::{BounceSubtype, BounceType, Db, DbError};
+use settings::Settings;
+use status::{self, MessageStatus, StatusError, StatusStore};
+
+mod local;
+pub mod notification;
+mod sqs;
+
+#[cfg(test)]
+mod test;
+
+// SQS won't return more than 10 messages at a time
+const BATCH_SIZE: usize = 10;
+
+// How long to wait before retrying a message that couldn't be handled, in seconds
+const RETRY_DELAY: u64 = 60;
+
+/// A message received from a queue.
+#[derive(Clone, Debug)]
+pub struct Message {
+    /// Identifies this receipt of the message,
+    /// e.g. the receipt handle for SQS.
+    pub id: String,
+    pub body: String,
+}
+
+/// The operations that notification handling needs from a queue,
+/// so that it can run against SQS or locally.
+/// Each queue is polled on its own thread.
+pub trait Queue: Send + Sync {
+    /// Waits for a batch of messages.
+    /// They're hidden from other receives until they're deleted
+    /// or their visibility timeout expires.
+    fn receive(&self) -> Result<Vec<Message>, QueueError>;
+
+    fn delete(&self, message: &Message) -> Result<(), QueueError>;
+
+    /// Sets how many seconds until a received message
+    /// becomes visible again.
+    fn change_visibility(&self, message: &Message, timeout: u64) -> Result<(), QueueError>;
+}
+
+#[derive(Debug)]
+pub struct QueueError {
+    description: String,
+}
+
+impl QueueError {
+    pub fn new(description: String) -> QueueError {
+        QueueError { description }
+    }
+}
+
+impl Error for QueueError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Display for QueueError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description)
+    }
+}
+
+impl From<DbError> for QueueError {
+    fn from(error: DbError) -> QueueError {
+        QueueError::new(format!("database error: {}", error.description()))
+    }
+}
+
+impl From<JsonError> for QueueError {
+    fn from(error: JsonError) -> QueueError {
+        QueueError::new(format!("JSON error: {:?}", error))
+    }
+}
+
+impl From<StatusError> for QueueError {
+    fn from(error: StatusError) -> QueueError {
+        QueueError::new(format!("status error: {}", error.description()))
+    }
+}
+
+pub struct Queues<'a> {
+    bounce: Box<Queue>,
+    complaint: Box<Queue>,
+    delivery: Box<Queue>,
+    db: Box<&'a (Db + Sync)>,
+    status: Option<StatusStore>,
+}
+
+impl<'a> Queues<'a> {
+    pub fn new(settings: &'a Settings, db: Box<&'a (Db + Sync)>) -> Queues<'a> {
+        let (bounce, complaint, delivery): (Box<Queue>, Box<Queue>, Box<Queue>) =
+            if settings.queue == "local" {
+                let directory = Path::new(
+                    &settings
+                        .localqueues
+                        .as_ref()
+                        .expect("missing localqueues settings")
+                        .directory,
+                );
+                (
+                    Box::new(LocalQueue::new(directory.join("bounce"))),
+                    Box::new(LocalQueue::new(directory.join("complaint"))),
+                    Box::new(LocalQueue::new(directory.join("delivery"))),
+                )
+            } else {
+                let urls = &settings.sqs.urls;
+                (
+                    Box::new(SqsQueue::new(&settings.sqs, &urls.bounce)),
+                    Box::new(SqsQueue::new(&settings.sqs, &urls.complaint)),
+                    Box::new(SqsQueue::new(&settings.sqs, &urls.delivery)),
+                )
+            };
+
+        Queues {
+            bounce,
+            complaint,
+            delivery,
+            db,
+            status: settings.status.as_ref().map(StatusStore::new),
+        }
+    }
+
+    /// Long-polls the queue that `notification_type` notifications
+    /// are published to for a batch of them
+    /// and handles them, returning the number of messages processed.
+    /// Queues can be processed concurrently from different threads,
+    /// so that waiting on an empty queue doesn't hold up the others.
+    ///
+    /// Messages are only deleted from a queue once they've been handled,
+    /// so if the auth db is unavailable they'll be redelivered later.
+    pub fn process(&self, notification_type: NotificationType) -> Result<usize, QueueError> {
+        let queue: &Queue = match notification_type {
+            NotificationType::Bounce => &*self.bounce,
+            NotificationType::Complaint => &*self.complaint,
+            NotificationType::Delivery => &*self.delivery,
+        };
+        let messages = queue.receive()?;
+
+        for (index, message) in messages.iter().enumerate() {
+            if let Err(error) = self.process_message(queue, message) {
+                // Back off before retrying the message that failed,
+                // but release the rest of the batch straight away
+                queue.change_visibility(message, RETRY_DELAY)?;
+                for message in messages[index + 1..].iter() {
+                    queue.change_visibility(message, 0)?;
+                }
+                return Err(error);
+            }
+        }
+
+        Ok(messages.len())
+    }
+
+    fn process_message(&self, queue: &Queue, message: &Message) -> Result<(), QueueError> {
+        match notification::parse(&message.body) {
+            Ok(Some(notification)) => self.handle(&notification)?,
+            Ok(None) => (),
+            // Redelivering a message that can't be parsed won't help
+            Err(error) => {
+                // TODO: replace this with proper logging when we have it
+                println!(
+                    "discarding unparseable notification: {}",
+                    QueueError::from(error)
+                );
+            }
+        }
+
+        queue.delete(message)
+    }
+
+    // Notifications are handled by type rather than by queue,
+    // in case SES publishes them all to the same topic.
+    // The status is updated first so that it's not left behind
+    // if the auth db fails and the notification is redelivered.
+    fn handle(&self, notification: &Notification) -> Result<(), QueueError> {
+        let message_id = &status::message_id("ses", &notification.mail.message_id);
+        match notification.notification_type {
+            NotificationType::Bounce => if let Some(ref bounce) = notification.bounce {
+                self.record_status(message_id, |status| {
+                    status.bounced_at = Some(bounce.timestamp.to_string())
+                })?;
+                self.record_bounce(bounce)
+            } else {
+                discard(notification);
+                Ok(())
+            },
+            NotificationType::Complaint => if let Some(ref complaint) = notification.complaint {
+                self.record_status(message_id, |status| {
+                    status.complained_at = Some(complaint.timestamp.to_string())
+                })?;
+                self.record_complaint(complaint)
+            } else {
+                discard(notification);
+                Ok(())
+            },
+            NotificationType::Delivery => if let Some(ref delivery) = notification.delivery {
+                self.record_status(message_id, |status| {
+                    status.delivered_at = Some(delivery.timestamp.to_string());
+                    status.reporting_mta = delivery.reporting_mta.clone();
+                    status.smtp_response = delivery.smtp_response.clone();
+                })
+            } else {
+                discard(notification);
+                Ok(())
+            },
+        }
+    }
+
+    fn record_status<F>(&self, message_id: &str, update: F) -> Result<(), QueueError>
+    where
+        F: FnOnce(&mut MessageStatus),
+    {
+        if let Some(ref status) = self.status {
+            let status = status.update(message_id, update)?;
+            // TODO: replace this with proper logging when we have it
+            println!("message {} is {:?}", message_id, status.state());
+        }
+
+        Ok(())
+    }
+
+    fn record_bounce(&self, bounce: &Bounce) -> Result<(), QueueError> {
+        for recipient in &bounce.bounced_recipients {
+            self.db.create_bounce(
+                &recipient.email_address,
+                From::from(bounce.bounce_type),
+                From::from(bounce.bounce_sub_type),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn record_complaint(&self, complaint: &Complaint) -> Result<(), QueueError> {
+        let bounce_subtype = complaint
+            .complaint_feedback_type
+            .map_or(BounceSubtype::Unmapped, From::from);
+
+        for recipient in &complaint.complained_recipients {
+            self.db.create_bounce(
+                &recipient.email_address,
+                BounceType::Complaint,
+                bounce_subtype,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn discard(notification: &Notification) {
+    // TODO: replace this with proper logging when we have it
+    println!(
+        "discarding {:?} notification without details for message {}",
+        notification.notification_type, notification.mail.message_id
+    );
+}
@@ -0,0 +1,212 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Types for the notifications that SES publishes to SNS,
+//! as described in https://docs.aws.amazon.com/ses/latest/DeveloperGuide/notification-contents.html
+
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+use serde_json::{self, Error as JsonError, Value};
+
+use auth_db;
+
+// The envelope that SNS wraps around each notification
+// before delivering it to the queue.
+#[derive(Debug, Deserialize)]
+struct SnsNotification {
+    #[serde(rename = "Type")]
+    notification_type: String,
+    #[serde(rename = "Message")]
+    message: String,
+}
+
+/// Parses the body of an SQS message, returning `None`
+/// for SNS messages that aren't notifications,
+/// e.g. subscription confirmations.
+pub fn parse(body: &str) -> Result<Option<Notification>, JsonError> {
+    let envelope: SnsNotification = serde_json::from_str(body)?;
+    if envelope.notification_type == "Notification" {
+        serde_json::from_str(&envelope.message).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub notification_type: NotificationType,
+    pub mail: Mail,
+    pub bounce: Option<Bounce>,
+    pub complaint: Option<Complaint>,
+    pub delivery: Option<Delivery>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum NotificationType {
+    Bounce,
+    Complaint,
+    Delivery,
+}
+
+/// The original message that the notification is about.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mail {
+    pub message_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bounce {
+    #[serde(deserialize_with = "or_default")]
+    pub bounce_type: BounceType,
+    #[serde(deserialize_with = "or_default")]
+    pub bounce_sub_type: BounceSubtype,
+    pub bounced_recipients: Vec<BouncedRecipient>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BouncedRecipient {
+    pub email_address: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum BounceType {
+    Undetermined,
+    Permanent,
+    Transient,
+}
+
+impl Default for BounceType {
+    fn default() -> BounceType {
+        BounceType::Undetermined
+    }
+}
+
+impl From<BounceType> for auth_db::BounceType {
+    fn from(bounce_type: BounceType) -> auth_db::BounceType {
+        match bounce_type {
+            BounceType::Permanent => auth_db::BounceType::Hard,
+            // SES can't tell whether these will recur,
+            // so treat them as the less severe kind
+            BounceType::Transient | BounceType::Undetermined => auth_db::BounceType::Soft,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum BounceSubtype {
+    Undetermined,
+    General,
+    NoEmail,
+    Suppressed,
+    MailboxFull,
+    MessageTooLarge,
+    ContentRejected,
+    AttachmentRejected,
+}
+
+impl Default for BounceSubtype {
+    fn default() -> BounceSubtype {
+        BounceSubtype::Undetermined
+    }
+}
+
+impl From<BounceSubtype> for auth_db::BounceSubtype {
+    fn from(bounce_subtype: BounceSubtype) -> auth_db::BounceSubtype {
+        match bounce_subtype {
+            BounceSubtype::Undetermined => auth_db::BounceSubtype::Undetermined,
+            BounceSubtype::General => auth_db::BounceSubtype::General,
+            BounceSubtype::NoEmail => auth_db::BounceSubtype::NoEmail,
+            BounceSubtype::Suppressed => auth_db::BounceSubtype::Suppressed,
+            BounceSubtype::MailboxFull => auth_db::BounceSubtype::MailboxFull,
+            BounceSubtype::MessageTooLarge => auth_db::BounceSubtype::MessageTooLarge,
+            BounceSubtype::ContentRejected => auth_db::BounceSubtype::ContentRejected,
+            BounceSubtype::AttachmentRejected => auth_db::BounceSubtype::AttachmentRejected,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Complaint {
+    pub complained_recipients: Vec<ComplainedRecipient>,
+    /// Only set when the report came from a feedback loop
+    /// that says why the recipient complained.
+    #[serde(default, deserialize_with = "optional_or_default")]
+    pub complaint_feedback_type: Option<ComplaintFeedbackType>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComplainedRecipient {
+    pub email_address: String,
+}
+
+/// The feedback types from
+/// https://www.iana.org/assignments/marf-parameters/marf-parameters.xml#marf-parameters-2
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComplaintFeedbackType {
+    Abuse,
+    AuthFailure,
+    Fraud,
+    NotSpam,
+    Other,
+    Virus,
+    /// A feedback type that isn't in the list above.
+    Unknown,
+}
+
+impl Default for ComplaintFeedbackType {
+    fn default() -> ComplaintFeedbackType {
+        ComplaintFeedbackType::Unknown
+    }
+}
+
+impl From<ComplaintFeedbackType> for auth_db::BounceSubtype {
+    fn from(feedback_type: ComplaintFeedbackType) -> auth_db::BounceSubtype {
+        match feedback_type {
+            ComplaintFeedbackType::Abuse => auth_db::BounceSubtype::Abuse,
+            ComplaintFeedbackType::AuthFailure => auth_db::BounceSubtype::AuthFailure,
+            ComplaintFeedbackType::Fraud => auth_db::BounceSubtype::Fraud,
+            ComplaintFeedbackType::NotSpam => auth_db::BounceSubtype::NotSpam,
+            ComplaintFeedbackType::Other => auth_db::BounceSubtype::Other,
+            ComplaintFeedbackType::Virus => auth_db::BounceSubtype::Virus,
+            ComplaintFeedbackType::Unknown => auth_db::BounceSubtype::Unmapped,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    pub timestamp: String,
+    #[serde(rename = "reportingMTA")]
+    pub reporting_mta: Option<String>,
+    pub smtp_response: Option<String>,
+}
+
+// SES and the feedback loops behind it may add values that we don't know about yet,
+// which are better recorded with the enum's default than dropped.
+fn or_default<'d, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'d>,
+    T: DeserializeOwned + Default,
+{
+    let value: String = Deserialize::deserialize(deserializer)?;
+    Ok(serde_json::from_value(Value::String(value)).unwrap_or_default())
+}
+
+fn optional_or_default<'d, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'d>,
+    T: DeserializeOwned + Default,
+{
+    let value: Option<String> = Deserialize::deserialize(deserializer)?;
+    Ok(value.map(|value| serde_json::from_value(Value::String(value)).unwrap_or_default()))
+}
@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::boxed::Box;
+
+use rusoto_core::{reactor::RequestDispatcher, Region};
+use rusoto_credential::StaticProvider;
+use rusoto_sqs::{
+    ChangeMessageVisibilityError, ChangeMessageVisibilityRequest, DeleteMessageError,
+    DeleteMessageRequest, ReceiveMessageError, ReceiveMessageRequest, Sqs, SqsClient,
+};
+
+use super::{Message, Queue, QueueError, BATCH_SIZE};
+use settings::Sqs as SqsSettings;
+
+// SQS won't wait any longer than this before returning an empty batch
+const WAIT_TIME: i64 = 20;
+
+pub struct SqsQueue {
+    client: Box<Sqs + Send + Sync>,
+    url: String,
+}
+
+impl SqsQueue {
+    pub fn new(settings: &SqsSettings, url: &str) -> SqsQueue {
+        // SQS serves each queue from the host in its URL,
+        // which lets the URL point at a local stand-in too.
+        let region = Region::Custom {
+            name: settings.region.to_string(),
+            endpoint: origin(url).to_string(),
+        };
+
+        let client: Box<Sqs + Send + Sync> = if let Some(ref keys) = settings.keys {
+            let creds =
+                StaticProvider::new(keys.access.to_string(), keys.secret.to_string(), None, None);
+            Box::new(SqsClient::new(RequestDispatcher::default(), creds, region))
+        } else {
+            Box::new(SqsClient::simple(region))
+        };
+
+        SqsQueue {
+            client,
+            url: url.to_string(),
+        }
+    }
+}
+
+impl Queue for SqsQueue {
+    fn receive(&self) -> Result<Vec<Message>, QueueError> {
+        let mut request = ReceiveMessageRequest::default();
+        request.queue_url = self.url.clone();
+        request.max_number_of_messages = Some(BATCH_SIZE as i64);
+        request.wait_time_seconds = Some(WAIT_TIME);
+
+        let messages = self
+            .client
+            .receive_message(&request)
+            .sync()?
+            .messages
+            .unwrap_or_default();
+
+        Ok(messages
+            .into_iter()
+            .filter_map(|message| {
+                let body = message.body.unwrap_or_default();
+                message
+                    .receipt_handle
+                    .map(|receipt_handle| Message {
+                        id: receipt_handle,
+                        body,
+                    })
+            })
+            .collect())
+    }
+
+    fn delete(&self, message: &Message) -> Result<(), QueueError> {
+        self.client
+            .delete_message(&DeleteMessageRequest {
+                queue_url: self.url.clone(),
+                receipt_handle: message.id.clone(),
+            })
+            .sync()
+            .map_err(From::from)
+    }
+
+    fn change_visibility(&self, message: &Message, timeout: u64) -> Result<(), QueueError> {
+        self.client
+            .change_message_visibility(&ChangeMessageVisibilityRequest {
+                queue_url: self.url.clone(),
+                receipt_handle: message.id.clone(),
+                visibility_timeout: timeout as i64,
+            })
+            .sync()
+            .map_err(From::from)
+    }
+}
+
+impl From<ChangeMessageVisibilityError> for QueueError {
+    fn from(error: ChangeMessageVisibilityError) -> QueueError {
+        QueueError::new(format!("SQS visibility error: {:?}", error))
+    }
+}
+
+impl From<DeleteMessageError> for QueueError {
+    fn from(error: DeleteMessageError) -> QueueError {
+        QueueError::new(format!("SQS delete error: {:?}", error))
+    }
+}
+
+impl From<ReceiveMessageError> for QueueError {
+    fn from(error: ReceiveMessageError) -> QueueError {
+        QueueError::new(format!("SQS receive error: {:?}", error))
+    }
+}
+
+// Returns the scheme, host and port of a queue URL
+pub fn origin(url: &str) -> &str {
+    let scheme_length = url.find("://").map_or(0, |index| index + 3);
+    match url[scheme_length..].find('/') {
+        Some(index) => &url[..scheme_length + index],
+        None => url,
+    }
+}
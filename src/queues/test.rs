@@ -0,0 +1,773 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    fs::{self, File}, io::{BufRead, BufReader, Read, Write}, net::{TcpListener, TcpStream},
+    path::Path, sync::{mpsc, Arc, Mutex}, thread, time::Duration, vec,
+};
+
+use serde_json;
+
+use super::*;
+use auth_db::test::{DbMock, DbMockError};
+use settings::{
+    test::TemporaryDirectory, AwsKeys, LocalQueues as LocalQueuesSettings,
+    Status as StatusSettings,
+};
+use status::{self, State};
+
+const BOUNCE_NOTIFICATION: &str = r#"{
+    "notificationType": "Bounce",
+    "bounce": {
+        "bounceType": "Permanent",
+        "bounceSubType": "General",
+        "bouncedRecipients": [
+            {
+                "emailAddress": "foo@example.com",
+                "action": "failed",
+                "status": "5.1.1",
+                "diagnosticCode": "smtp; 550 5.1.1 user unknown"
+            },
+            { "emailAddress": "bar@example.com" }
+        ],
+        "timestamp": "2018-06-01T00:00:00.000Z",
+        "feedbackId": "00000137860315fd-869464a4-8680-4114-98d3-716fe35851f9-000000",
+        "reportingMTA": "dsn; a27-23.smtp-out.us-west-2.amazonses.com"
+    },
+    "mail": {
+        "timestamp": "2018-06-01T00:00:00.000Z",
+        "messageId": "00000137860315fd-34208509-5b74-41f3-95c5-22c1edc3c924-000000",
+        "source": "accounts@firefox.com",
+        "destination": ["foo@example.com", "bar@example.com"]
+    }
+}"#;
+
+const COMPLAINT_NOTIFICATION: &str = r#"{
+    "notificationType": "Complaint",
+    "complaint": {
+        "userAgent": "AnyCompany Feedback Loop (V0.01)",
+        "complainedRecipients": [
+            { "emailAddress": "foo@example.com" },
+            { "emailAddress": "bar@example.com" }
+        ],
+        "complaintFeedbackType": "abuse",
+        "arrivalDate": "2018-06-01T00:00:00.000Z",
+        "timestamp": "2018-06-01T00:00:01.000Z",
+        "feedbackId": "000001378603177f-18c07c78-fa81-4a58-9dd1-fedc3cb8f49a-000000"
+    },
+    "mail": {
+        "timestamp": "2018-06-01T00:00:00.000Z",
+        "messageId": "000001378603177f-7a5433e7-8edb-42ae-af10-f0181f34d6ee-000000",
+        "source": "accounts@firefox.com",
+        "destination": ["foo@example.com", "bar@example.com"]
+    }
+}"#;
+
+const DELIVERY_NOTIFICATION: &str = r#"{
+    "notificationType": "Delivery",
+    "mail": {
+        "timestamp": "2018-06-01T00:00:00.000Z",
+        "messageId": "0000014644fe5ef6-9a483358-9170-4cb4-a269-f5dcdf415321-000000",
+        "source": "accounts@firefox.com",
+        "destination": ["foo@example.com"]
+    },
+    "delivery": {
+        "timestamp": "2018-06-01T00:00:01.000Z",
+        "processingTimeMillis": 546,
+        "recipients": ["foo@example.com"],
+        "smtpResponse": "250 ok:  Message 64111812 accepted",
+        "reportingMTA": "a8-70.smtp-out.amazonses.com",
+        "remoteMtaIp": "127.0.2.0"
+    }
+}"#;
+
+#[test]
+fn parse_bounce() {
+    let notification = notification::parse(&sns_wrap(BOUNCE_NOTIFICATION))
+        .expect("parse error")
+        .expect("notification should be present");
+    assert_eq!(notification.notification_type, NotificationType::Bounce);
+    assert_eq!(
+        notification.mail.message_id,
+        "00000137860315fd-34208509-5b74-41f3-95c5-22c1edc3c924-000000"
+    );
+
+    let bounce = notification.bounce.expect("bounce should be present");
+    assert_eq!(bounce.bounce_type, notification::BounceType::Permanent);
+    assert_eq!(bounce.bounce_sub_type, notification::BounceSubtype::General);
+    assert_eq!(bounce.bounced_recipients.len(), 2);
+    assert_eq!(
+        bounce.bounced_recipients[0].email_address,
+        "foo@example.com"
+    );
+    assert_eq!(
+        bounce.bounced_recipients[1].email_address,
+        "bar@example.com"
+    );
+}
+
+#[test]
+fn parse_complaint() {
+    let notification = notification::parse(&sns_wrap(COMPLAINT_NOTIFICATION))
+        .expect("parse error")
+        .expect("notification should be present");
+    assert_eq!(notification.notification_type, NotificationType::Complaint);
+    assert!(notification.bounce.is_none());
+
+    let complaint = notification.complaint.expect("complaint should be present");
+    assert_eq!(
+        complaint.complaint_feedback_type,
+        Some(notification::ComplaintFeedbackType::Abuse)
+    );
+    assert_eq!(complaint.complained_recipients.len(), 2);
+    assert_eq!(
+        complaint.complained_recipients[0].email_address,
+        "foo@example.com"
+    );
+
+    let notification = notification::parse(&sns_wrap(
+        &COMPLAINT_NOTIFICATION.replace("\"abuse\"", "\"auth-failure\""),
+    )).expect("parse error")
+        .expect("notification should be present");
+    let complaint = notification.complaint.expect("complaint should be present");
+    assert_eq!(
+        complaint.complaint_feedback_type,
+        Some(notification::ComplaintFeedbackType::AuthFailure)
+    );
+
+    let notification = notification::parse(&sns_wrap(
+        &COMPLAINT_NOTIFICATION.replace("\"abuse\"", "\"wibble\""),
+    )).expect("parse error")
+        .expect("notification should be present");
+    let complaint = notification.complaint.expect("complaint should be present");
+    assert_eq!(
+        complaint.complaint_feedback_type,
+        Some(notification::ComplaintFeedbackType::Unknown)
+    );
+
+    let notification = notification::parse(&sns_wrap(
+        &COMPLAINT_NOTIFICATION.replace("\"complaintFeedbackType\": \"abuse\",", ""),
+    )).expect("parse error")
+        .expect("notification should be present");
+    let complaint = notification.complaint.expect("complaint should be present");
+    assert_eq!(complaint.complaint_feedback_type, None);
+}
+
+#[test]
+fn parse_subscription_confirmation() {
+    let body = r#"{
+        "Type": "SubscriptionConfirmation",
+        "MessageId": "165545c9-2a5c-472c-8df2-7ff2be2b3b1b",
+        "Message": "You have chosen to subscribe to the topic"
+    }"#;
+    match notification::parse(body) {
+        Ok(None) => (),
+        result => assert!(false, "unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn parse_invalid_notification() {
+    assert!(notification::parse("wibble").is_err());
+    assert!(notification::parse(&sns_wrap("{}")).is_err());
+    let invalid_subtype = BOUNCE_NOTIFICATION.replace("\"General\"", "42");
+    assert!(notification::parse(&sns_wrap(&invalid_subtype)).is_err());
+}
+
+#[test]
+fn parse_unknown_bounce_types() {
+    let unknown_types = BOUNCE_NOTIFICATION
+        .replace("\"Permanent\"", "\"Wibble\"")
+        .replace("\"General\"", "\"Blee\"");
+    let notification = notification::parse(&sns_wrap(&unknown_types))
+        .expect("parse error")
+        .expect("notification should be present");
+    let bounce = notification.bounce.expect("bounce should be present");
+    assert_eq!(bounce.bounce_type, notification::BounceType::Undetermined);
+    assert_eq!(
+        bounce.bounce_sub_type,
+        notification::BounceSubtype::Undetermined
+    );
+    assert_eq!(bounce.bounced_recipients.len(), 2);
+}
+
+#[test]
+fn map_bounce_type() {
+    let bounce_type: BounceType = From::from(notification::BounceType::Permanent);
+    assert_eq!(bounce_type, BounceType::Hard);
+    let bounce_type: BounceType = From::from(notification::BounceType::Transient);
+    assert_eq!(bounce_type, BounceType::Soft);
+    let bounce_type: BounceType = From::from(notification::BounceType::Undetermined);
+    assert_eq!(bounce_type, BounceType::Soft);
+}
+
+#[test]
+fn map_bounce_subtype() {
+    let subtypes = vec![
+        (
+            notification::BounceSubtype::Undetermined,
+            BounceSubtype::Undetermined,
+        ),
+        (notification::BounceSubtype::General, BounceSubtype::General),
+        (notification::BounceSubtype::NoEmail, BounceSubtype::NoEmail),
+        (
+            notification::BounceSubtype::Suppressed,
+            BounceSubtype::Suppressed,
+        ),
+        (
+            notification::BounceSubtype::MailboxFull,
+            BounceSubtype::MailboxFull,
+        ),
+        (
+            notification::BounceSubtype::MessageTooLarge,
+            BounceSubtype::MessageTooLarge,
+        ),
+        (
+            notification::BounceSubtype::ContentRejected,
+            BounceSubtype::ContentRejected,
+        ),
+        (
+            notification::BounceSubtype::AttachmentRejected,
+            BounceSubtype::AttachmentRejected,
+        ),
+    ];
+    for (ses_subtype, expected) in subtypes {
+        let subtype: BounceSubtype = From::from(ses_subtype);
+        assert_eq!(subtype, expected);
+    }
+}
+
+#[test]
+fn map_complaint_feedback_type() {
+    let feedback_types = vec![
+        (notification::ComplaintFeedbackType::Abuse, BounceSubtype::Abuse),
+        (
+            notification::ComplaintFeedbackType::AuthFailure,
+            BounceSubtype::AuthFailure,
+        ),
+        (notification::ComplaintFeedbackType::Fraud, BounceSubtype::Fraud),
+        (
+            notification::ComplaintFeedbackType::NotSpam,
+            BounceSubtype::NotSpam,
+        ),
+        (notification::ComplaintFeedbackType::Other, BounceSubtype::Other),
+        (notification::ComplaintFeedbackType::Virus, BounceSubtype::Virus),
+        (
+            notification::ComplaintFeedbackType::Unknown,
+            BounceSubtype::Unmapped,
+        ),
+    ];
+    for (feedback_type, expected) in feedback_types {
+        let subtype: BounceSubtype = From::from(feedback_type);
+        assert_eq!(subtype, expected);
+    }
+}
+
+#[test]
+fn queue_origin() {
+    assert_eq!(
+        sqs::origin("https://sqs.us-east-1.amazonaws.com/123456789012/fxa-email-bounce"),
+        "https://sqs.us-east-1.amazonaws.com"
+    );
+    assert_eq!(
+        sqs::origin("http://127.0.0.1:4100/100010001000/fxa-email-bounce"),
+        "http://127.0.0.1:4100"
+    );
+}
+
+#[test]
+fn process_bounces() {
+    let (port, requests) = sqs_stand_in(vec![
+        receive_response(&[("foo", &sns_wrap(BOUNCE_NOTIFICATION)), ("bar", "wibble")]),
+        delete_response(),
+        delete_response(),
+    ]);
+    let settings = create_settings(port);
+    let db = DbMock::new();
+    let queues = Queues::new(&settings, Box::new(&db));
+
+    assert_eq!(
+        queues
+            .process(NotificationType::Bounce)
+            .expect("process error"),
+        2
+    );
+
+    let bounces = db.bounces.lock().expect("lock error");
+    assert_eq!(bounces.len(), 2);
+    assert_eq!(
+        bounces[0],
+        (
+            String::from("foo@example.com"),
+            BounceType::Hard,
+            BounceSubtype::General
+        )
+    );
+    assert_eq!(bounces[1].0, "bar@example.com");
+
+    let receive = requests.recv().expect("receive error");
+    assert!(receive.contains("Action=ReceiveMessage"));
+    assert!(receive.contains("fxa-email-bounce"));
+    assert!(receive.contains("WaitTimeSeconds=20"));
+
+    // Unparseable messages are deleted too, so they don't get redelivered forever
+    let delete = requests.recv().expect("receive error");
+    assert!(delete.contains("Action=DeleteMessage"));
+    assert!(delete.contains("ReceiptHandle=foo"));
+    let delete = requests.recv().expect("receive error");
+    assert!(delete.contains("ReceiptHandle=bar"));
+
+    // The other queues are left to their own pollers
+    assert!(requests.try_recv().is_err());
+}
+
+#[test]
+fn process_complaints() {
+    let (port, requests) = sqs_stand_in(vec![
+        receive_response(&[
+            ("foo", &sns_wrap(COMPLAINT_NOTIFICATION)),
+            (
+                "bar",
+                &sns_wrap(&COMPLAINT_NOTIFICATION.replace(
+                    "\"complaintFeedbackType\": \"abuse\",",
+                    "",
+                )),
+            ),
+        ]),
+        delete_response(),
+        delete_response(),
+    ]);
+    let settings = create_settings(port);
+    let db = DbMock::new();
+    let queues = Queues::new(&settings, Box::new(&db));
+
+    assert_eq!(
+        queues
+            .process(NotificationType::Complaint)
+            .expect("process error"),
+        2
+    );
+
+    let bounces = db.bounces.lock().expect("lock error");
+    assert_eq!(bounces.len(), 4);
+    assert_eq!(
+        bounces[0],
+        (
+            String::from("foo@example.com"),
+            BounceType::Complaint,
+            BounceSubtype::Abuse
+        )
+    );
+    assert_eq!(
+        bounces[1],
+        (
+            String::from("bar@example.com"),
+            BounceType::Complaint,
+            BounceSubtype::Abuse
+        )
+    );
+
+    // A missing feedback type falls back to unmapped
+    assert_eq!(
+        bounces[2],
+        (
+            String::from("foo@example.com"),
+            BounceType::Complaint,
+            BounceSubtype::Unmapped
+        )
+    );
+    assert_eq!(bounces[3].0, "bar@example.com");
+    assert_eq!(bounces[3].2, BounceSubtype::Unmapped);
+
+    let receive = requests.recv().expect("receive error");
+    assert!(receive.contains("fxa-email-complaint"));
+    let delete = requests.recv().expect("receive error");
+    assert!(delete.contains("ReceiptHandle=foo"));
+    assert!(delete.contains("fxa-email-complaint"));
+    let delete = requests.recv().expect("receive error");
+    assert!(delete.contains("ReceiptHandle=bar"));
+}
+
+#[test]
+fn process_deliveries() {
+    let (port, requests) = sqs_stand_in(vec![
+        receive_response(&[("foo", &sns_wrap(BOUNCE_NOTIFICATION))]),
+        delete_response(),
+        receive_response(&[("bar", &sns_wrap(DELIVERY_NOTIFICATION))]),
+        delete_response(),
+    ]);
+    let mut settings = create_settings(port);
+    let status_directory = TemporaryDirectory::new("queues-status");
+    settings.status = Some(StatusSettings {
+        directory: status_directory.to_string_lossy().into_owned(),
+    });
+    let db = DbMock::new();
+    let queues = Queues::new(&settings, Box::new(&db));
+
+    assert_eq!(
+        queues
+            .process(NotificationType::Bounce)
+            .expect("process error"),
+        1
+    );
+    assert_eq!(
+        queues
+            .process(NotificationType::Delivery)
+            .expect("process error"),
+        1
+    );
+
+    // Deliveries aren't bounces
+    assert_eq!(db.bounces.lock().expect("lock error").len(), 2);
+
+    let store = StatusStore::new(settings.status.as_ref().unwrap());
+    let status = store
+        .get("ses:0000014644fe5ef6-9a483358-9170-4cb4-a269-f5dcdf415321-000000")
+        .expect("status error")
+        .expect("status should be present");
+    assert_eq!(status.state(), State::Delivered);
+    assert_eq!(
+        status.delivered_at,
+        Some(String::from("2018-06-01T00:00:01.000Z"))
+    );
+    assert_eq!(
+        status.reporting_mta,
+        Some(String::from("a8-70.smtp-out.amazonses.com"))
+    );
+    assert_eq!(
+        status.smtp_response,
+        Some(String::from("250 ok:  Message 64111812 accepted"))
+    );
+
+    let status = store
+        .get("ses:00000137860315fd-34208509-5b74-41f3-95c5-22c1edc3c924-000000")
+        .expect("status error")
+        .expect("status should be present");
+    assert_eq!(status.state(), State::Bounced);
+    assert_eq!(
+        status.bounced_at,
+        Some(String::from("2018-06-01T00:00:00.000Z"))
+    );
+
+    let receive = requests.recv().expect("receive error");
+    assert!(receive.contains("fxa-email-bounce"));
+    requests.recv().expect("receive error");
+    let receive = requests.recv().expect("receive error");
+    assert!(receive.contains("fxa-email-delivery"));
+    let delete = requests.recv().expect("receive error");
+    assert!(delete.contains("ReceiptHandle=bar"));
+    assert!(delete.contains("fxa-email-delivery"));
+}
+
+#[test]
+fn process_db_error() {
+    let (port, requests) = sqs_stand_in(vec![
+        receive_response(&[
+            ("foo", &sns_wrap(BOUNCE_NOTIFICATION)),
+            ("bar", &sns_wrap(BOUNCE_NOTIFICATION)),
+        ]),
+        change_visibility_response(),
+        change_visibility_response(),
+    ]);
+    let settings = create_settings(port);
+    let db = DbMockError;
+    let queues = Queues::new(&settings, Box::new(&db));
+
+    match queues.process(NotificationType::Bounce) {
+        Ok(_) => assert!(false, "Queues::process should have failed"),
+        Err(error) => assert_eq!(error.description(), "database error: wibble"),
+    }
+
+    // The messages must stay on the queue so that they're redelivered,
+    // the one that failed after a delay and the rest straight away
+    let receive = requests.recv().expect("receive error");
+    assert!(receive.contains("Action=ReceiveMessage"));
+    let change_visibility = requests.recv().expect("receive error");
+    assert!(change_visibility.contains("Action=ChangeMessageVisibility"));
+    assert!(change_visibility.contains("ReceiptHandle=foo"));
+    assert!(change_visibility.contains("VisibilityTimeout=60"));
+    let change_visibility = requests.recv().expect("receive error");
+    assert!(change_visibility.contains("ReceiptHandle=bar"));
+    assert!(change_visibility.contains("VisibilityTimeout=0"));
+    assert!(requests.try_recv().is_err());
+}
+
+#[test]
+fn local_queue() {
+    let directory = TemporaryDirectory::new("queues");
+    let queue = LocalQueue::new(directory.to_path_buf());
+    write_message(&directory, "foo", "wibble");
+    write_message(&directory, "bar", "blee");
+    File::create(directory.join("baz.tmp")).expect("create error");
+
+    let messages = queue.receive().expect("receive error");
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].id, "foo");
+    assert_eq!(messages[0].body, "wibble");
+    assert_eq!(messages[1].id, "bar");
+
+    // Received messages are hidden until they're deleted or become visible again
+    write_message(&directory, "qux", "quux");
+    let messages_after = queue.receive().expect("receive error");
+    assert_eq!(messages_after.len(), 1);
+    assert_eq!(messages_after[0].id, "qux");
+
+    queue.delete(&messages[0]).expect("delete error");
+    assert!(!directory.join("foo.json").exists());
+    queue
+        .change_visibility(&messages[1], 0)
+        .expect("change visibility error");
+
+    let messages = queue.receive().expect("receive error");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id, "bar");
+}
+
+#[test]
+fn process_local_queues() {
+    let directory = TemporaryDirectory::new("queues");
+    write_message(
+        &directory.join("bounce"),
+        "foo",
+        &sns_wrap(BOUNCE_NOTIFICATION),
+    );
+    write_message(
+        &directory.join("complaint"),
+        "bar",
+        &sns_wrap(COMPLAINT_NOTIFICATION),
+    );
+    write_message(
+        &directory.join("delivery"),
+        "baz",
+        &sns_wrap(DELIVERY_NOTIFICATION),
+    );
+
+    let mut settings = Settings::new().expect("config error");
+    settings.queue = String::from("local");
+    settings.localqueues = Some(LocalQueuesSettings {
+        directory: directory.to_string_lossy().into_owned(),
+    });
+    let db = DbMock::new();
+    let queues = Queues::new(&settings, Box::new(&db));
+
+    for &notification_type in &[
+        NotificationType::Bounce,
+        NotificationType::Complaint,
+        NotificationType::Delivery,
+    ] {
+        assert_eq!(
+            queues.process(notification_type).expect("process error"),
+            1
+        );
+    }
+
+    let bounces = db.bounces.lock().expect("lock error");
+    assert_eq!(bounces.len(), 4);
+    assert_eq!(bounces[0].1, BounceType::Hard);
+    assert_eq!(bounces[2].1, BounceType::Complaint);
+    assert!(!directory.join("bounce/foo.json").exists());
+    assert!(!directory.join("complaint/bar.json").exists());
+    assert!(!directory.join("delivery/baz.json").exists());
+}
+
+#[test]
+fn process_delivery_for_sent_message() {
+    let directory = TemporaryDirectory::new("queues");
+    let mut settings = Settings::new().expect("config error");
+    settings.queue = String::from("local");
+    settings.localqueues = Some(LocalQueuesSettings {
+        directory: directory.to_string_lossy().into_owned(),
+    });
+    settings.status = Some(StatusSettings {
+        directory: directory.join("status").to_string_lossy().into_owned(),
+    });
+
+    // This is the id that `/send` returns for a message sent via SES,
+    // which is what it records the status under
+    let message_id = status::message_id(
+        "ses",
+        "0000014644fe5ef6-9a483358-9170-4cb4-a269-f5dcdf415321-000000",
+    );
+    let store = StatusStore::new(settings.status.as_ref().unwrap());
+    store
+        .update(&message_id, |status| {
+            status.sent_at = Some(String::from("2018-06-01T00:00:00.000Z"))
+        })
+        .expect("update error");
+
+    let db = DbMock::new();
+    let queues = Queues::new(&settings, Box::new(&db));
+    write_message(
+        &directory.join("delivery"),
+        "foo",
+        &sns_wrap(DELIVERY_NOTIFICATION),
+    );
+
+    assert_eq!(
+        queues
+            .process(NotificationType::Delivery)
+            .expect("process error"),
+        1
+    );
+
+    let status = store
+        .get(&message_id)
+        .expect("status error")
+        .expect("status should be present");
+    assert_eq!(status.state(), State::Delivered);
+    assert_eq!(
+        status.sent_at,
+        Some(String::from("2018-06-01T00:00:00.000Z"))
+    );
+    assert_eq!(
+        status.delivered_at,
+        Some(String::from("2018-06-01T00:00:01.000Z"))
+    );
+}
+
+fn create_settings(port: u16) -> Settings {
+    let mut settings = Settings::new().expect("config error");
+    settings.sqs.keys = Some(AwsKeys {
+        access: String::from("A"),
+        secret: String::from("s"),
+    });
+    settings.sqs.urls.bounce = format!("http://127.0.0.1:{}/100010001000/fxa-email-bounce", port);
+    settings.sqs.urls.complaint = format!(
+        "http://127.0.0.1:{}/100010001000/fxa-email-complaint",
+        port
+    );
+    settings.sqs.urls.delivery = format!(
+        "http://127.0.0.1:{}/100010001000/fxa-email-delivery",
+        port
+    );
+    settings
+}
+
+fn sns_wrap(message: &str) -> String {
+    format!(
+        r#"{{
+            "Type": "Notification",
+            "MessageId": "165545c9-2a5c-472c-8df2-7ff2be2b3b1b",
+            "TopicArn": "arn:aws:sns:us-east-1:123456789012:fxa-email-bounce",
+            "Message": {},
+            "Timestamp": "2018-06-01T00:00:00.000Z"
+        }}"#,
+        serde_json::to_string(message).expect("JSON error")
+    )
+}
+
+fn receive_response(messages: &[(&str, &str)]) -> String {
+    let mut body = String::from("<ReceiveMessageResponse><ReceiveMessageResult>");
+    for &(receipt_handle, message_body) in messages.iter() {
+        body.push_str(&format!(
+            "<Message><MessageId>{0}</MessageId><ReceiptHandle>{0}</ReceiptHandle>\
+             <Body>{1}</Body></Message>",
+            receipt_handle,
+            escape(message_body)
+        ));
+    }
+    body.push_str(
+        "</ReceiveMessageResult><ResponseMetadata><RequestId>0</RequestId>\
+         </ResponseMetadata></ReceiveMessageResponse>",
+    );
+    body
+}
+
+fn change_visibility_response() -> String {
+    String::from(
+        "<ChangeMessageVisibilityResponse><ResponseMetadata><RequestId>0</RequestId>\
+         </ResponseMetadata></ChangeMessageVisibilityResponse>",
+    )
+}
+
+fn delete_response() -> String {
+    String::from(
+        "<DeleteMessageResponse><ResponseMetadata><RequestId>0</RequestId>\
+         </ResponseMetadata></DeleteMessageResponse>",
+    )
+}
+
+// Writes are staggered so that modification times, and therefore order, are distinct
+fn write_message(directory: &Path, id: &str, body: &str) {
+    fs::create_dir_all(directory).expect("create error");
+    File::create(directory.join(format!("{}.json", id)))
+        .and_then(|mut file| file.write_all(body.as_bytes()))
+        .expect("write error");
+    thread::sleep(Duration::from_millis(10));
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Serves each response in turn, to whichever connection asks next,
+// and reports the requests. Each queue has its own client,
+// so there can be more than one connection open at a time.
+fn sqs_stand_in(responses: Vec<String>) -> (u16, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind error");
+    let port = listener.local_addr().expect("address error").port();
+    let (sender, receiver) = mpsc::channel();
+    let responses = Arc::new(Mutex::new(responses.into_iter()));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.expect("accept error");
+            let sender = sender.clone();
+            let responses = responses.clone();
+            thread::spawn(move || serve(stream, &sender, &responses));
+        }
+    });
+
+    (port, receiver)
+}
+
+fn serve(
+    stream: TcpStream,
+    sender: &mpsc::Sender<String>,
+    responses: &Mutex<vec::IntoIter<String>>,
+) {
+    let mut writer = stream.try_clone().expect("clone error");
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        let mut request = String::new();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                // The client closed the connection
+                return;
+            }
+            let lowercase = line.to_lowercase();
+            if lowercase.starts_with("content-length:") {
+                content_length = lowercase[15..].trim().parse().expect("content-length error");
+            }
+            request.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+
+        let mut content = vec![0; content_length];
+        reader.read_exact(&mut content).expect("read error");
+        request.push_str(&String::from_utf8_lossy(&content));
+
+        let response = match responses.lock().expect("lock error").next() {
+            Some(response) => response,
+            None => return,
+        };
+        sender.send(request).expect("send error");
+
+        write!(
+            writer,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        ).expect("write error");
+    }
+}
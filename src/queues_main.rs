@@ -6,24 +6,83 @@
 #![feature(try_from)]
 #![feature(type_ascription)]
 
+extern crate base64;
+extern crate chrono;
 extern crate config;
 extern crate hex;
 #[macro_use]
 extern crate lazy_static;
+extern crate rand;
 extern crate regex;
 extern crate reqwest;
 extern crate rusoto_core;
+extern crate rusoto_credential;
+extern crate rusoto_sqs;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
 
 mod auth_db;
+// These modules are shared with the service binary, which uses more of them
+#[allow(dead_code)]
 mod deserialize;
 mod duration;
+mod queues;
 mod settings;
+#[allow(dead_code)]
+mod status;
+#[allow(dead_code)]
 mod validate;
 
+use std::{panic, sync::mpsc, thread, time::Duration};
+
+use auth_db::DbClient;
+use queues::{notification::NotificationType, Queues};
+use settings::Settings;
+
+// How long to back off when SQS or the auth db can't be reached
+const ERROR_DELAY: u64 = 5;
+
+lazy_static! {
+    static ref SETTINGS: Settings = Settings::new().expect("config error");
+    static ref DB: DbClient = DbClient::new(&SETTINGS);
+    static ref QUEUES: Queues<'static> = Queues::new(&SETTINGS, Box::new(&*DB));
+}
+
 fn main() {
-    println!("Not implemented");
+    // Each queue is long-polled on its own thread,
+    // so that a notification doesn't wait on the other queues' polls.
+    // If any of them dies the whole process exits, rather than carrying on without it.
+    let (sender, receiver) = mpsc::channel();
+    for &notification_type in &[
+        NotificationType::Bounce,
+        NotificationType::Complaint,
+        NotificationType::Delivery,
+    ] {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let _ = panic::catch_unwind(|| poll(notification_type));
+            sender.send(notification_type).expect("channel error");
+        });
+    }
+
+    let notification_type = receiver.recv().expect("channel error");
+    panic!("stopped polling the {:?} queue", notification_type);
+}
+
+fn poll(notification_type: NotificationType) {
+    loop {
+        match QUEUES.process(notification_type) {
+            Ok(count) => if count > 0 {
+                // TODO: replace this with proper logging when we have it
+                println!("processed {} {:?} notifications", count, notification_type);
+            },
+            Err(error) => {
+                // TODO: replace this with proper logging when we have it
+                println!("{}", error);
+                thread::sleep(Duration::from_secs(ERROR_DELAY));
+            }
+        }
+    }
 }
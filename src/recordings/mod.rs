@@ -0,0 +1,208 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Test-only routes for inspecting the messages
+//! captured by the `recorder` provider.
+//! They're only mounted when `recorder.enabled` is set.
+
+// Rocket's generated code for dynamic path segments trips this lint
+#![cfg_attr(feature = "cargo-clippy", allow(print_literal))]
+
+use std::sync::Arc;
+
+use rocket::{response::{content::Html, status::NoContent}, State};
+use rocket_contrib::{Json, Value};
+
+use app_errors::HttpError;
+use mime;
+use providers::{Recorder, RecorderError, Recording};
+
+#[cfg(test)]
+mod test;
+
+/// Every recorded message, oldest first.
+#[get("/messages")]
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn messages(recorder: State<Arc<Recorder>>) -> Result<Json<Value>, HttpError> {
+    let recordings = recorder.recordings().map_err(fail)?;
+    Ok(Json(json!(recordings)))
+}
+
+#[delete("/messages")]
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn clear_messages(recorder: State<Arc<Recorder>>) -> Result<NoContent, HttpError> {
+    recorder.clear(None).map(|_| NoContent).map_err(fail)
+}
+
+/// Messages for a recipient, in the same format as restmail,
+/// so that existing functional tests can point at the service instead.
+#[get("/mail/<user>")]
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn mail(user: String, recorder: State<Arc<Recorder>>) -> Result<Json<Value>, HttpError> {
+    let recordings = recorder.recordings().map_err(fail)?;
+    Ok(Json(Value::Array(
+        recordings
+            .iter()
+            .filter(|recording| recording.is_for(&user))
+            .map(restmail)
+            .collect(),
+    )))
+}
+
+#[delete("/mail/<user>")]
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn clear_mail(user: String, recorder: State<Arc<Recorder>>) -> Result<NoContent, HttpError> {
+    recorder.clear(Some(&user)).map(|_| NoContent).map_err(fail)
+}
+
+/// A web page showing every recorded message, newest first.
+#[get("/")]
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn view(recorder: State<Arc<Recorder>>) -> Result<Html<String>, HttpError> {
+    let recordings = recorder.recordings().map_err(fail)?;
+    Ok(Html(render(&recordings)))
+}
+
+fn fail(error: RecorderError) -> HttpError {
+    // TODO: replace this with proper logging when we have it
+    println!("{}", error);
+    From::from(error)
+}
+
+fn restmail(recording: &Recording) -> Value {
+    let message = &recording.message;
+    let mut headers = json!({});
+    for (name, value) in &message.headers {
+        headers[name.to_lowercase()] = Value::String(value.to_string());
+    }
+    headers["from"] = json!(mime::format_address(&message.from.name, &message.from.address));
+    headers["to"] = json!(message.to.join(", "));
+    if !message.cc.is_empty() {
+        headers["cc"] = json!(message.cc.join(", "));
+    }
+    if let Some(ref reply_to) = message.reply_to {
+        headers["reply-to"] = json!(reply_to);
+    }
+    headers["subject"] = json!(message.subject);
+
+    json!({
+        "messageId": recording.id,
+        "subject": message.subject,
+        "text": message.body_text,
+        "html": message.body_html,
+        "headers": headers,
+        "from": [{ "address": message.from.address, "name": message.from.name }],
+        "to": addresses(&message.to),
+        "cc": addresses(&message.cc),
+        "date": recording.received_at,
+        "receivedAt": recording.received_at,
+    })
+}
+
+fn addresses(addresses: &[String]) -> Value {
+    Value::Array(
+        addresses
+            .iter()
+            .map(|address| json!({ "address": address, "name": "" }))
+            .collect(),
+    )
+}
+
+fn render(recordings: &[Recording]) -> String {
+    let mut page = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Recorded messages</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         article { border: 1px solid #ccc; margin-bottom: 1em; padding: 0 1em 1em; }\n\
+         dt { float: left; font-weight: bold; width: 8em; }\n\
+         pre { background: #f7f7f7; padding: 1em; white-space: pre-wrap; }\n\
+         iframe { border: 1px solid #eee; height: 20em; width: 100%; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    page.push_str(&format!(
+        "<h1>Recorded messages ({})</h1>\n",
+        recordings.len()
+    ));
+
+    if recordings.is_empty() {
+        page.push_str("<p>No messages have been recorded.</p>\n");
+    }
+
+    for recording in recordings.iter().rev() {
+        let message = &recording.message;
+        page.push_str(&format!(
+            "<article>\n<h2>{}</h2>\n<dl>\n",
+            escape(&message.subject)
+        ));
+        push_field(
+            &mut page,
+            "From",
+            &mime::format_address(&message.from.name, &message.from.address),
+        );
+        push_field(&mut page, "To", &message.to.join(", "));
+        if !message.cc.is_empty() {
+            push_field(&mut page, "Cc", &message.cc.join(", "));
+        }
+        if !message.bcc.is_empty() {
+            push_field(&mut page, "Bcc", &message.bcc.join(", "));
+        }
+        if let Some(ref reply_to) = message.reply_to {
+            push_field(&mut page, "Reply-To", reply_to);
+        }
+        for (name, value) in &message.headers {
+            push_field(&mut page, name, value);
+        }
+        push_field(&mut page, "Received", &recording.received_at);
+        push_field(&mut page, "Message id", &recording.id);
+        page.push_str("</dl>\n");
+
+        page.push_str(&format!(
+            "<h3>Text</h3>\n<pre>{}</pre>\n",
+            escape(&message.body_text)
+        ));
+
+        // Sandboxed, so that scripts in the message can't reach this page
+        if let Some(ref body_html) = message.body_html {
+            page.push_str(&format!(
+                "<h3>HTML</h3>\n<iframe sandbox srcdoc=\"{}\"></iframe>\n",
+                escape(body_html)
+            ));
+        }
+
+        if !message.attachments.is_empty() {
+            page.push_str("<h3>Attachments</h3>\n<ul>\n");
+            for attachment in &message.attachments {
+                page.push_str(&format!(
+                    "<li>{} ({}, {} bytes)</li>\n",
+                    escape(&attachment.filename),
+                    escape(&attachment.content_type),
+                    attachment.content.len()
+                ));
+            }
+            page.push_str("</ul>\n");
+        }
+
+        page.push_str("</article>\n");
+    }
+
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+fn push_field(page: &mut String, name: &str, value: &str) {
+    page.push_str(&format!(
+        "<dt>{}</dt><dd>{}</dd>\n",
+        escape(name),
+        escape(value)
+    ));
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
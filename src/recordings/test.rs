@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashMap, sync::Arc};
+
+use rocket::{self, http::Status, local::Client};
+use serde_json::{self, Value};
+
+use providers::{Message, Recorder, Recording};
+use settings::{Recorder as RecorderSettings, Sender};
+
+fn setup() -> (Client, Arc<Recorder>) {
+    let recorder = Arc::new(Recorder::new(&RecorderSettings {
+        enabled: true,
+        directory: None,
+    }));
+    recorder
+        .record(create_recording("0", "foo@example.com", "<b>wibble</b>"))
+        .expect("record error");
+    recorder
+        .record(create_recording("1", "bar@example.com", "blee"))
+        .expect("record error");
+
+    let server = rocket::ignite().manage(recorder.clone()).mount(
+        "/recorder",
+        routes![
+            super::messages,
+            super::clear_messages,
+            super::mail,
+            super::clear_mail,
+            super::view
+        ],
+    );
+
+    (Client::new(server).unwrap(), recorder)
+}
+
+#[test]
+fn messages() {
+    let (client, _recorder) = setup();
+
+    let mut response = client.get("/recorder/messages").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+    let messages = body.as_array().expect("body should be an array");
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["id"], "0");
+    assert_eq!(messages[0]["subject"], "<b>wibble</b>");
+    assert_eq!(messages[0]["bodyText"], "baz");
+    assert_eq!(messages[0]["replyTo"], "qux@example.com");
+    assert_eq!(messages[1]["id"], "1");
+}
+
+#[test]
+fn mail() {
+    let (client, _recorder) = setup();
+
+    let mut response = client.get("/recorder/mail/foo").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+    let messages = body.as_array().expect("body should be an array");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["messageId"], "0");
+    assert_eq!(messages[0]["subject"], "<b>wibble</b>");
+    assert_eq!(messages[0]["text"], "baz");
+    assert_eq!(messages[0]["html"], "<a>baz</a>");
+    assert_eq!(messages[0]["headers"]["x-qux"], "quux");
+    assert_eq!(messages[0]["headers"]["reply-to"], "qux@example.com");
+    assert_eq!(messages[0]["from"][0]["address"], "accounts@firefox.com");
+    assert_eq!(messages[0]["to"][0]["address"], "foo@example.com");
+
+    let mut response = client.get("/recorder/mail/bar@example.com").dispatch();
+    let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["messageId"], "1");
+
+    let mut response = client.get("/recorder/mail/wibble").dispatch();
+    let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn clear_mail() {
+    let (client, recorder) = setup();
+
+    let response = client.delete("/recorder/mail/foo").dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    let recordings = recorder.recordings().unwrap();
+    assert_eq!(recordings.len(), 1);
+    assert_eq!(recordings[0].id, "1");
+}
+
+#[test]
+fn clear_messages() {
+    let (client, recorder) = setup();
+
+    let response = client.delete("/recorder/messages").dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(recorder.recordings().unwrap().len(), 0);
+}
+
+#[test]
+fn view() {
+    let (client, _recorder) = setup();
+
+    let mut response = client.get("/recorder").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body_string().unwrap();
+    assert!(body.contains("<h1>Recorded messages (2)</h1>"));
+    assert!(body.contains("<h2>&lt;b&gt;wibble&lt;/b&gt;</h2>"));
+    assert!(body.contains("<iframe sandbox srcdoc=\"&lt;a&gt;baz&lt;/a&gt;\"></iframe>"));
+    assert!(!body.contains("<b>wibble</b>"));
+
+    // Newest first
+    assert!(body.find("<h2>blee</h2>").unwrap() < body.find("<h2>&lt;b&gt;").unwrap());
+}
+
+fn create_recording(id: &str, to: &str, subject: &str) -> Recording {
+    let mut headers = HashMap::new();
+    headers.insert(String::from("X-Qux"), String::from("quux"));
+
+    Recording {
+        id: id.to_string(),
+        message: Message {
+            from: Sender {
+                address: String::from("accounts@firefox.com"),
+                name: String::from("Firefox Accounts"),
+            },
+            to: vec![to.to_string()],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            reply_to: Some(String::from("qux@example.com")),
+            headers,
+            subject: subject.to_string(),
+            body_text: String::from("baz"),
+            body_html: Some(String::from("<a>baz</a>")),
+            attachments: Vec::new(),
+            tags: HashMap::new(),
+            categories: Vec::new(),
+            metadata: HashMap::new(),
+        },
+        received_at: format!("2018-06-01T00:00:0{}.000000Z", id),
+    }
+}
@@ -2,28 +2,52 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{borrow::Cow, collections::HashMap};
+// Rocket's generated code for dynamic path segments trips this lint
+#![cfg_attr(feature = "cargo-clippy", allow(print_literal))]
 
+use std::{borrow::Cow, cmp, collections::HashMap, sync::Arc, thread, time::Duration};
+
+use chrono::Utc;
+use rand::{thread_rng, Rng};
 use rocket::{
-    data::{self, FromData}, http::Status, response::Failure, Data, Outcome, Request,
+    data::{self, FromData}, http::Status, response::status::Custom, Data, Outcome, Request, State,
 };
 use rocket_contrib::{Json, Value};
-use validator::{self, Validate, ValidationError};
+use validator::{self, ValidationError};
 
+use app_errors::HttpError;
 use auth_db::DbClient;
-use bounces::Bounces;
-use providers::Providers;
-use settings::Settings;
+use bounces::{BounceError, Bounces};
+use deserialize;
+use providers::{
+    Attachment, Delivery, Disposition, Message, ProviderError, Providers, Recorder,
+};
+use settings::{Sender, Settings};
+use status::StatusStore;
 use validate;
+use workers::{QueueError, WorkerPool};
 
 #[cfg(test)]
 mod test;
 
+// Sendgrid's limits, which are the strictest of the providers that support these
+const MAX_CATEGORIES: usize = 10;
+const MAX_METADATA_SIZE: usize = 10000;
+
+// How long clients should wait before retrying when the queue is full, in seconds
+const RETRY_AFTER: u64 = 5;
+
+// How long to wait between replays of the spool, in seconds,
+// which doubles up to the maximum while messages keep failing
+const REPLAY_DELAY: u64 = 30;
+const MAX_REPLAY_DELAY: u64 = 960;
+
 lazy_static! {
     static ref SETTINGS: Settings = Settings::new().expect("config error");
     static ref DB: DbClient = DbClient::new(&SETTINGS);
     static ref BOUNCES: Bounces<'static> = Bounces::new(&SETTINGS, Box::new(&*DB));
     static ref PROVIDERS: Providers<'static> = Providers::new(&SETTINGS);
+    static ref STATUS: Option<StatusStore> = SETTINGS.status.as_ref().map(StatusStore::new);
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,16 +56,60 @@ struct Body {
     html: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize)]
 struct Email {
-    #[validate(email)]
-    to: String,
+    from: Option<String>,
+    #[serde(deserialize_with = "deserialize::one_or_many")]
+    to: Vec<String>,
     cc: Option<Vec<String>>,
+    bcc: Option<Vec<String>>,
+    #[serde(rename = "replyTo")]
+    reply_to: Option<String>,
+    headers: Option<HashMap<String, String>>,
     subject: String,
     body: Body,
+    attachments: Option<Vec<Attachment>>,
+    /// Sent as message tags by providers that support them.
+    tags: Option<HashMap<String, String>>,
+    /// Sent as categories by providers that support them.
+    categories: Option<Vec<String>>,
+    /// Arbitrary key/value pairs, which providers that support them
+    /// attach to the message and return in their events.
+    metadata: Option<HashMap<String, String>>,
     provider: Option<String>,
 }
 
+impl Email {
+    /// Split the request into the message itself
+    /// and the provider it should be sent with, if any.
+    fn into_message(self) -> (Message, Option<String>) {
+        let from = self.from
+            .as_ref()
+            .and_then(|from| resolve_sender(from))
+            .unwrap_or(&SETTINGS.sender)
+            .clone();
+
+        (
+            Message {
+                from,
+                to: self.to,
+                cc: self.cc.unwrap_or_default(),
+                bcc: self.bcc.unwrap_or_default(),
+                reply_to: self.reply_to,
+                headers: self.headers.unwrap_or_default(),
+                subject: self.subject,
+                body_text: self.body.text,
+                body_html: self.body.html,
+                attachments: self.attachments.unwrap_or_default(),
+                tags: self.tags.unwrap_or_default(),
+                categories: self.categories.unwrap_or_default(),
+                metadata: self.metadata.unwrap_or_default(),
+            },
+            self.provider,
+        )
+    }
+}
+
 impl FromData for Email {
     type Error = ValidationError;
 
@@ -63,6 +131,22 @@ impl FromData for Email {
 }
 
 fn validate(email: &Email) -> bool {
+    if let Some(ref from) = email.from {
+        if resolve_sender(from).is_none() {
+            return false;
+        }
+    }
+
+    if email.to.is_empty() {
+        return false;
+    }
+
+    for address in &email.to {
+        if !validator::validate_email(&address) {
+            return false;
+        }
+    }
+
     if let Some(ref cc) = email.cc {
         for address in cc {
             if !validator::validate_email(&address) {
@@ -71,6 +155,64 @@ fn validate(email: &Email) -> bool {
         }
     }
 
+    if let Some(ref bcc) = email.bcc {
+        for address in bcc {
+            if !validator::validate_email(&address) {
+                return false;
+            }
+        }
+    }
+
+    if let Some(ref reply_to) = email.reply_to {
+        if !validator::validate_email(reply_to) {
+            return false;
+        }
+    }
+
+    if let Some(ref headers) = email.headers {
+        for (name, value) in headers.iter() {
+            if !validate::header_name(name) || !validate::header_value(value) {
+                return false;
+            }
+        }
+    }
+
+    if !validate::header_value(&email.subject) {
+        return false;
+    }
+
+    if let Some(ref attachments) = email.attachments {
+        if !validate_attachments(attachments) {
+            return false;
+        }
+    }
+
+    if let Some(ref tags) = email.tags {
+        for (name, value) in tags.iter() {
+            if !validate::ses_tag(name) || !validate::ses_tag(value) {
+                return false;
+            }
+        }
+    }
+
+    if let Some(ref categories) = email.categories {
+        if categories.len() > MAX_CATEGORIES {
+            return false;
+        }
+
+        for category in categories {
+            if !validate::category(category) {
+                return false;
+            }
+        }
+    }
+
+    if let Some(ref metadata) = email.metadata {
+        if !validate_metadata(metadata) {
+            return false;
+        }
+    }
+
     if let Some(ref provider) = email.provider {
         if !validate::provider(provider) {
             return false;
@@ -80,6 +222,43 @@ fn validate(email: &Email) -> bool {
     true
 }
 
+fn validate_attachments(attachments: &[Attachment]) -> bool {
+    let mut size = 0;
+    for attachment in attachments {
+        if !validate::filename(&attachment.filename)
+            || !validate::content_type(&attachment.content_type)
+        {
+            return false;
+        }
+
+        match attachment.content_id {
+            Some(ref content_id) => if !validate::content_id(content_id) {
+                return false;
+            },
+            None => if attachment.disposition == Disposition::Inline {
+                return false;
+            },
+        }
+
+        size += attachment.content.len() as u64;
+    }
+
+    size <= SETTINGS.attachments.maxsize
+}
+
+fn validate_metadata(metadata: &HashMap<String, String>) -> bool {
+    let mut size = 0;
+    for (key, value) in metadata {
+        if !validate::metadata_key(key) || !validate::header_value(value) {
+            return false;
+        }
+
+        size += key.len() + value.len();
+    }
+
+    size <= MAX_METADATA_SIZE
+}
+
 fn fail() -> data::Outcome<Email, ValidationError> {
     Outcome::Failure((
         Status::BadRequest,
@@ -92,33 +271,243 @@ fn fail() -> data::Outcome<Email, ValidationError> {
 }
 
 #[post("/send", format = "application/json", data = "<email>")]
-fn handler(email: Email) -> Result<Json<Value>, Failure> {
-    let to = email.to.as_ref();
-    BOUNCES.check(to)?;
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn handler(email: Email, workers: State<Workers>) -> Result<Custom<Json<Value>>, HttpError> {
+    check_bounces(&email.to)?;
+    check_bounces(email.cc.as_ref().map(|cc| cc.as_slice()).unwrap_or(&[]))?;
+    check_bounces(email.bcc.as_ref().map(|bcc| bcc.as_slice()).unwrap_or(&[]))?;
 
-    let cc = if let Some(ref cc) = email.cc {
-        let mut refs = Vec::new();
-        for address in cc.iter() {
-            BOUNCES.check(address)?;
-            refs.push(address.as_ref());
-        }
-        refs
-    } else {
-        Vec::new()
-    };
+    let (message, provider) = email.into_message();
 
-    PROVIDERS
-        .send(
-            email.to.as_ref(),
-            cc.as_ref(),
-            email.subject.as_ref(),
-            email.body.text.as_ref(),
-            email.body.html.as_ref().map(|html| html.as_ref()),
-            email.provider.as_ref().map(|provider| provider.as_ref()),
-        )
-        .map(|message_id| Json(json!({ "messageId": message_id })))
+    // Spooling first means an accepted message survives a restart
+    let spool_id = PROVIDERS
+        .spool(&message, provider.as_ref().map(|provider| provider.as_ref()))
         .map_err(|error| {
             println!("{}", error);
-            Failure(Status::InternalServerError)
+            HttpError::from(error)
+        })?;
+
+    if let Some(ref workers) = workers.0 {
+        let id = generate_id();
+        return workers
+            .submit(Job {
+                id: id.clone(),
+                message,
+                provider,
+                spool_id: spool_id.clone(),
+            })
+            .map(|_| Custom(Status::Accepted, Json(json!({ "messageId": id }))))
+            .map_err(|error| {
+                println!("{}", error);
+                PROVIDERS.unspool(spool_id);
+                match error {
+                    QueueError::Full => {
+                        HttpError::with_retry_after(Status::ServiceUnavailable, RETRY_AFTER)
+                    }
+                    QueueError::Disconnected => HttpError::new(Status::InternalServerError),
+                }
+            });
+    }
+
+    deliver(
+        spool_id,
+        &message,
+        provider.as_ref().map(|provider| provider.as_ref()),
+    ).map(|delivery| {
+            // Spooled messages are accepted but haven't been sent yet
+            let status = if delivery.spooled {
+                Status::Accepted
+            } else {
+                Status::Ok
+            };
+            Custom(
+                status,
+                Json(json!({
+                    "messageId": delivery.message_id,
+                    "attempts": delivery.attempts,
+                })),
+            )
         })
+        .map_err(|error| {
+            println!("{}", error);
+            From::from(error)
+        })
+}
+
+/// The worker pool that `/send` hands messages to,
+/// if `workerpool.enabled` is set.
+/// It's managed by Rocket so that tests can provide their own.
+pub struct Workers(Option<WorkerPool<Job>>);
+
+pub fn workers() -> Workers {
+    Workers(if SETTINGS.workerpool.enabled {
+        Some(WorkerPool::new(
+            SETTINGS.workerpool.size,
+            SETTINGS.workerpool.queuesize,
+            process,
+        ))
+    } else {
+        None
+    })
+}
+
+#[derive(Debug)]
+struct Job {
+    id: String,
+    message: Message,
+    provider: Option<String>,
+    spool_id: Option<String>,
+}
+
+fn process(job: Job) {
+    // TODO: replace this with proper logging when we have it
+    match deliver(
+        job.spool_id,
+        &job.message,
+        job.provider.as_ref().map(|provider| provider.as_ref()),
+    ) {
+        Ok(delivery) => {
+            println!(
+                "message {} sent as {} after {} attempts",
+                job.id, delivery.message_id, delivery.attempts
+            );
+            link_status(&job.id, &delivery.message_id);
+        }
+        Err(error) => println!("message {} failed: {}", job.id, error),
+    }
+}
+
+fn deliver(
+    spool_id: Option<String>,
+    message: &Message,
+    provider: Option<&str>,
+) -> Result<Delivery, ProviderError> {
+    let delivery = PROVIDERS.send_spooled(spool_id, message, provider)?;
+
+    if !delivery.spooled {
+        record_sent(&delivery.message_id);
+    }
+
+    Ok(delivery)
+}
+
+// The message has gone, so failing to record that shouldn't fail the request
+fn record_sent(message_id: &str) {
+    if let Some(ref status) = *STATUS {
+        let sent_at = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+        if let Err(error) = status.update(message_id, |status| status.sent_at = Some(sent_at)) {
+            // TODO: replace this with proper logging when we have it
+            println!("{}", error);
+        }
+    }
+}
+
+// Lets the status of a message be looked up by an id
+// that `/send` returned before the message was sent
+fn link_status(id: &str, message_id: &str) {
+    if let Some(ref status) = *STATUS {
+        if let Err(error) = status.link(id, message_id) {
+            // TODO: replace this with proper logging when we have it
+            println!("{}", error);
+        }
+    }
+}
+
+/// Keep sending messages from the spool in the background,
+/// both the ones left by a previous process
+/// and the ones that failed transiently in this one.
+/// While messages keep failing, the delay between replays doubles.
+pub fn replay_spool() {
+    if SETTINGS.spool.is_none() {
+        return;
+    }
+
+    thread::spawn(|| {
+        let mut delay = REPLAY_DELAY;
+        loop {
+            let remaining = PROVIDERS.replay(|spool_message_id, message_id| {
+                record_sent(message_id);
+                link_status(spool_message_id, message_id);
+            });
+            thread::sleep(Duration::from_secs(delay));
+            delay = if remaining > 0 {
+                cmp::min(delay * 2, MAX_REPLAY_DELAY)
+            } else {
+                REPLAY_DELAY
+            };
+        }
+    });
+}
+
+/// The recorder, if the `recorder` provider is enabled,
+/// so that its inspection routes can be mounted.
+pub fn recorder() -> Option<Arc<Recorder>> {
+    PROVIDERS.recorder()
+}
+
+/// Reports the circuit state of each provider,
+/// so that operators can see which ones are failing.
+#[get("/health")]
+fn health() -> Json<Value> {
+    Json(json!({ "providers": PROVIDERS.health() }))
+}
+
+/// What has happened to a message since it was sent,
+/// according to the notifications processed by the queues binary.
+/// `message_id` is the id returned by `/send`,
+/// including the prefix that names the provider.
+/// When the worker pool is enabled `/send` returns a job id instead,
+/// which is found once a worker has sent the message,
+/// and messages left in the spool are found once they've been replayed.
+#[get("/status/<message_id>")]
+#[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+fn message_status(message_id: String) -> Result<Json<Value>, HttpError> {
+    let status = match *STATUS {
+        Some(ref store) => store.get(&message_id).map_err(|error| {
+            // TODO: replace this with proper logging when we have it
+            println!("{}", error);
+            HttpError::new(Status::InternalServerError)
+        })?,
+        None => None,
+    };
+
+    status
+        .map(|status| {
+            Json(json!({
+                "messageId": status.message_id,
+                "status": status.state(),
+                "sentAt": status.sent_at,
+                "deliveredAt": status.delivered_at,
+                "reportingMta": status.reporting_mta,
+                "smtpResponse": status.smtp_response,
+                "bouncedAt": status.bounced_at,
+                "complainedAt": status.complained_at,
+            }))
+        })
+        .ok_or_else(|| HttpError::new(Status::NotFound))
+}
+
+fn check_bounces(addresses: &[String]) -> Result<(), BounceError> {
+    for address in addresses.iter() {
+        BOUNCES.check(address)?;
+    }
+    Ok(())
+}
+
+fn generate_id() -> String {
+    let mut rng = thread_rng();
+    format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>())
+}
+
+/// Only the default sender and the configured `senders`
+/// are allowed in the `from` field of a request.
+fn resolve_sender(address: &str) -> Option<&'static Sender> {
+    if SETTINGS.sender.address.eq_ignore_ascii_case(address) {
+        return Some(&SETTINGS.sender);
+    }
+
+    SETTINGS
+        .senders
+        .iter()
+        .find(|sender| sender.address.eq_ignore_ascii_case(address))
 }
@@ -2,23 +2,38 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::sync::{mpsc, Mutex};
+
+use base64;
 use rocket::{
     self, http::{ContentType, Status}, local::Client,
 };
-use serde_json;
+use serde_json::{self, Value};
 
+use super::{Job, Workers};
 use app_errors::{self, ApplicationError};
+use workers::WorkerPool;
 
 fn setup() -> Client {
+    setup_with_workers(Workers(None))
+}
+
+fn setup_with_workers(workers: Workers) -> Client {
     let server = rocket::ignite()
-        .mount("/", routes![super::handler])
+        .manage(workers)
+        .mount(
+            "/",
+            routes![super::handler, super::health, super::message_status],
+        )
         .catch(errors![
             app_errors::bad_request,
+            app_errors::unauthorized,
             app_errors::not_found,
             app_errors::method_not_allowed,
             app_errors::unprocessable_entity,
             app_errors::too_many_requests,
-            app_errors::internal_server_error
+            app_errors::internal_server_error,
+            app_errors::service_unavailable
         ]);
 
     Client::new(server).unwrap()
@@ -48,7 +63,7 @@ fn single_recipient() {
     assert_eq!(response.status(), Status::Ok);
 
     let body = response.body().unwrap().into_string().unwrap();
-    assert_eq!(body, json!({ "messageId": "mock:deadbeef" }).to_string());
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
 }
 
 #[test]
@@ -75,7 +90,7 @@ fn multiple_recipients() {
     assert_eq!(response.status(), Status::Ok);
 
     let body = response.body().unwrap().into_string().unwrap();
-    assert_eq!(body, json!({ "messageId": "mock:deadbeef" }).to_string());
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
 }
 
 #[test]
@@ -100,7 +115,7 @@ fn without_optional_data() {
     assert_eq!(response.status(), Status::Ok);
 
     let body = response.body().unwrap().into_string().unwrap();
-    assert_eq!(body, json!({ "messageId": "mock:deadbeef" }).to_string());
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
 }
 
 #[test]
@@ -231,3 +246,703 @@ fn invalid_cc_field() {
     let error: ApplicationError = serde_json::from_str(&body).unwrap();
     assert_eq!(error, ApplicationError::new(400, "Bad Request"));
 }
+
+#[test]
+fn attachments() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "subject": "bar",
+      "body": {
+        "text": "baz",
+        "html": "<img src=\"cid:logo\">"
+      },
+      "attachments": [
+        {
+          "filename": "qux.txt",
+          "contentType": "text/plain",
+          "content": "cXV4"
+        },
+        {
+          "filename": "logo.png",
+          "contentType": "image/png",
+          "content": "iVBORw0KGgo=",
+          "disposition": "inline",
+          "contentId": "logo"
+        }
+      ],
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn large_attachment() {
+    let client = setup();
+
+    // Well over the 1 MiB that Rocket accepts for JSON by default
+    let content = base64::encode(&vec![0u8; 5 * 1024 * 1024]);
+    let request = json!({
+        "to": "foo@example.com",
+        "subject": "bar",
+        "body": {
+            "text": "baz"
+        },
+        "attachments": [
+            {
+                "filename": "qux.bin",
+                "contentType": "application/octet-stream",
+                "content": content
+            }
+        ],
+        "provider": "mock"
+    }).to_string();
+    assert!(request.len() > 1024 * 1024);
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(request)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn invalid_attachment_content() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "attachments": [
+        {
+          "filename": "qux.txt",
+          "contentType": "text/plain",
+          "content": "not base64!"
+        }
+      ],
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn inline_attachment_without_content_id() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "subject": "bar",
+      "body": {
+        "text": "baz",
+        "html": "<img src=\"cid:logo\">"
+      },
+      "attachments": [
+        {
+          "filename": "logo.png",
+          "contentType": "image/png",
+          "content": "iVBORw0KGgo=",
+          "disposition": "inline"
+        }
+      ],
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn headers() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "replyTo": "bar@example.com",
+      "headers": {
+        "In-Reply-To": "<baz@example.com>",
+        "References": "<baz@example.com>",
+        "X-Qux": "quux"
+      },
+      "subject": "wibble",
+      "body": {
+        "text": "blee"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn tags() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "tags": {
+        "template": "verify-email",
+        "flow_id": "deadbeef"
+      },
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn invalid_tag() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "tags": {
+        "template": "verify email"
+      },
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn categories_and_metadata() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "categories": [ "verify-email" ],
+      "metadata": {
+        "flow_id": "deadbeef",
+        "uid": "abad1dea"
+      },
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn too_many_categories() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "categories": [ "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k" ],
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn invalid_metadata_key() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "metadata": {
+        "flow id": "deadbeef"
+      },
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn reserved_header() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "headers": {
+        "Bcc": "bar@example.com"
+      },
+      "subject": "baz",
+      "body": {
+        "text": "qux"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn invalid_header_value() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "headers": {
+        "X-Foo": "bar\r\nBcc: baz@example.com"
+      },
+      "subject": "qux",
+      "body": {
+        "text": "wibble"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn invalid_subject() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "subject": "qux\r\nBcc: baz@example.com",
+      "body": {
+        "text": "wibble"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn invalid_reply_to_field() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "replyTo": "bar",
+      "subject": "baz",
+      "body": {
+        "text": "qux"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn multiple_to_and_bcc_recipients() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": [ "foo@example.com", "bar@example.com" ],
+      "cc": [ "baz@example.com" ],
+      "bcc": [ "qux@example.com", "wibble@example.com" ],
+      "subject": "blee",
+      "body": {
+        "text": "quux"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn empty_to_field() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": [],
+      "subject": "foo",
+      "body": {
+        "text": "bar"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn invalid_bcc_field() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": [ "foo@example.com" ],
+      "bcc": [ "bar" ],
+      "subject": "baz",
+      "body": {
+        "text": "qux"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn default_sender() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "from": "accounts@firefox.com",
+      "to": "foo@example.com",
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn sender_not_allowed() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "from": "mallory@example.com",
+      "to": "foo@example.com",
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(400, "Bad Request"));
+}
+
+#[test]
+fn magic_addresses_disabled() {
+    let client = setup();
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "permanent@mock.invalid",
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    // `mock.magicaddresses` is off by default
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    assert_eq!(body, json!({ "messageId": "mock:deadbeef", "attempts": 1 }).to_string());
+}
+
+#[test]
+fn worker_pool_accepts_message() {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    let client = setup_with_workers(Workers(Some(WorkerPool::new(1, 1, move |job: Job| {
+        sender
+            .lock()
+            .expect("sender lock error")
+            .send(job.id)
+            .expect("channel error")
+    }))));
+
+    let mut response = client
+        .post("/send")
+        .header(ContentType::JSON)
+        .body(
+            r#"{
+      "to": "foo@example.com",
+      "subject": "bar",
+      "body": {
+        "text": "baz"
+      },
+      "provider": "mock"
+    }"#,
+        )
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Accepted);
+
+    let body: Value =
+        serde_json::from_str(&response.body().unwrap().into_string().unwrap()).unwrap();
+    let message_id = body["messageId"].as_str().expect("messageId should be a string");
+    assert_eq!(message_id.len(), 32);
+    assert_eq!(receiver.recv().expect("channel error"), message_id);
+}
+
+#[test]
+fn worker_pool_full() {
+    let (started_sender, started_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let started_sender = Mutex::new(started_sender);
+    let release_receiver = Mutex::new(release_receiver);
+    let client = setup_with_workers(Workers(Some(WorkerPool::new(1, 1, move |_job: Job| {
+        started_sender
+            .lock()
+            .expect("sender lock error")
+            .send(())
+            .expect("channel error");
+        // Keep the only worker busy until the test is done
+        let _ = release_receiver.lock().expect("receiver lock error").recv();
+    }))));
+
+    let send = || {
+        client
+            .post("/send")
+            .header(ContentType::JSON)
+            .body(
+                r#"{
+          "to": "foo@example.com",
+          "subject": "bar",
+          "body": {
+            "text": "baz"
+          },
+          "provider": "mock"
+        }"#,
+            )
+            .dispatch()
+    };
+
+    // The first message is taken by the worker and the second is queued
+    assert_eq!(send().status(), Status::Accepted);
+    started_receiver.recv().expect("channel error");
+    assert_eq!(send().status(), Status::Accepted);
+
+    let mut response = send();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("5"));
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(503, "Service Unavailable"));
+
+    drop(release_sender);
+}
+
+#[test]
+fn health() {
+    let client = setup();
+
+    let mut response = client.get("/health").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let health: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(health["providers"]["mock"], json!("closed"));
+    assert_eq!(health["providers"]["ses"], json!("closed"));
+}
+
+#[test]
+fn unknown_message_status() {
+    let client = setup();
+
+    let mut response = client.get("/status/deadbeef").dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let body = response.body().unwrap().into_string().unwrap();
+    let error: ApplicationError = serde_json::from_str(&body).unwrap();
+    assert_eq!(error, ApplicationError::new(404, "Not Found"));
+}
@@ -8,12 +8,18 @@
 #![feature(type_ascription)]
 #![plugin(rocket_codegen)]
 
+extern crate base64;
+extern crate chrono;
 extern crate config;
 extern crate hex;
 #[macro_use]
 extern crate lazy_static;
+extern crate lettre;
+extern crate native_tls;
+extern crate rand;
 extern crate regex;
 extern crate reqwest;
+extern crate ring;
 extern crate rocket;
 #[macro_use]
 extern crate rocket_contrib;
@@ -25,30 +31,63 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate untrusted;
 extern crate validator;
-#[macro_use]
-extern crate validator_derive;
 
 mod app_errors;
 mod auth_db;
 mod bounces;
 mod deserialize;
+mod dkim;
 mod duration;
+mod events;
+mod mime;
 mod providers;
+mod recordings;
 mod send;
+mod serialize;
 mod settings;
+mod spool;
+mod status;
 mod validate;
+mod workers;
 
 fn main() {
-    rocket::ignite()
-        .mount("/", routes![send::handler])
+    send::replay_spool();
+
+    let mut server = rocket::ignite().manage(send::workers()).mount(
+        "/",
+        routes![
+            send::handler,
+            send::health,
+            send::message_status,
+            events::sendgrid
+        ],
+    );
+
+    if let Some(recorder) = send::recorder() {
+        server = server.manage(recorder).mount(
+            "/recorder",
+            routes![
+                recordings::messages,
+                recordings::clear_messages,
+                recordings::mail,
+                recordings::clear_mail,
+                recordings::view
+            ],
+        );
+    }
+
+    server
         .catch(errors![
             app_errors::bad_request,
+            app_errors::unauthorized,
             app_errors::not_found,
             app_errors::method_not_allowed,
             app_errors::unprocessable_entity,
             app_errors::too_many_requests,
-            app_errors::internal_server_error
+            app_errors::internal_server_error,
+            app_errors::service_unavailable
         ])
         .launch();
 }
@@ -2,14 +2,24 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::env;
+use std::{env, fmt::{self, Debug, Formatter}};
 
 use config::{Config, ConfigError, Environment, File};
 
 use deserialize;
 
 #[cfg(test)]
-mod test;
+pub mod test;
+
+// Logged in place of secrets when the settings are printed
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Attachments {
+    /// The maximum combined size of all attachments on a message, in bytes
+    /// (before base64 encoding).
+    pub maxsize: u64,
+}
 
 #[derive(Debug, Default, Deserialize)]
 pub struct AuthDb {
@@ -41,6 +51,157 @@ pub struct BounceLimits {
 }
 
 #[derive(Debug, Default, Deserialize)]
+pub struct CircuitBreaker {
+    /// The number of consecutive failures that opens a provider's circuit,
+    /// or zero to disable the circuit breaker.
+    pub threshold: u32,
+    /// How long an open circuit waits before letting a probe request through.
+    #[serde(deserialize_with = "deserialize::duration")]
+    pub cooldown: u64,
+}
+
+/// Signs the messages that we compose ourselves,
+/// i.e. the ones sent through SES or SMTP.
+#[derive(Default, Deserialize)]
+pub struct Dkim {
+    #[serde(deserialize_with = "deserialize::dkim_algorithm")]
+    pub algorithm: String,
+    #[serde(deserialize_with = "deserialize::host")]
+    pub domain: String,
+    #[serde(deserialize_with = "deserialize::dkim_selector")]
+    pub selector: String,
+    /// A base64-encoded PKCS#8 private key.
+    #[serde(deserialize_with = "deserialize::base64")]
+    pub privatekey: Vec<u8>,
+}
+
+impl Debug for Dkim {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Dkim")
+            .field("algorithm", &self.algorithm)
+            .field("domain", &self.domain)
+            .field("selector", &self.selector)
+            .field("privatekey", &REDACTED)
+            .finish()
+    }
+}
+
+/// Used instead of SQS when `queue` is `local`.
+#[derive(Debug, Default, Deserialize)]
+pub struct LocalQueues {
+    /// Notifications are read from JSON files
+    /// in its `bounce`, `complaint` and `delivery` subdirectories.
+    pub directory: String,
+}
+
+#[derive(Default, Deserialize)]
+pub struct Mailgun {
+    #[serde(deserialize_with = "deserialize::mailgun_api_key")]
+    pub key: String,
+    #[serde(deserialize_with = "deserialize::host")]
+    pub domain: String,
+    /// Differs by region, e.g. `https://api.mailgun.net/v3/`
+    /// or `https://api.eu.mailgun.net/v3/`.
+    #[serde(deserialize_with = "deserialize::base_uri")]
+    pub baseuri: String,
+}
+
+impl Debug for Mailgun {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Mailgun")
+            .field("key", &REDACTED)
+            .field("domain", &self.domain)
+            .field("baseuri", &self.baseuri)
+            .finish()
+    }
+}
+
+/// Fault injection for the `mock` provider,
+/// so that error paths can be exercised end to end.
+#[derive(Debug, Default, Deserialize)]
+pub struct Mock {
+    /// An artificial delay added to every send.
+    #[serde(deserialize_with = "deserialize::duration")]
+    pub latency: u64,
+    /// The percentage of sends that fail with a transient error.
+    #[serde(deserialize_with = "deserialize::percentage")]
+    pub failurerate: u8,
+    pub faults: Vec<MockFault>,
+    /// Whether recipients at `mock.invalid` trigger faults and delays.
+    /// Leave this off in production,
+    /// where anyone who can call `/send` could use them.
+    #[serde(default)]
+    pub magicaddresses: bool,
+    /// The longest delay that a magic address can ask for.
+    #[serde(deserialize_with = "deserialize::duration")]
+    pub maxdelay: u64,
+}
+
+/// Sends to any recipient matching `pattern`
+/// fail with a `transient`, `permanent` or `configuration` error.
+#[derive(Debug, Default, Deserialize)]
+pub struct MockFault {
+    #[serde(deserialize_with = "deserialize::regex")]
+    pub pattern: String,
+    #[serde(deserialize_with = "deserialize::provider_error_kind")]
+    pub kind: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ProviderWeight {
+    #[serde(deserialize_with = "deserialize::provider")]
+    pub provider: String,
+    pub weight: u8,
+    pub ramp: Option<Ramp>,
+}
+
+/// Gradually moves a provider's weight from `from` to its configured `weight`,
+/// linearly over `period` milliseconds, beginning at `start`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Ramp {
+    #[serde(deserialize_with = "deserialize::timestamp")]
+    pub start: u64,
+    #[serde(deserialize_with = "deserialize::duration")]
+    pub period: u64,
+    pub from: u8,
+}
+
+/// The `recorder` provider captures messages instead of sending them,
+/// so that functional tests can inspect them.
+#[derive(Debug, Default, Deserialize)]
+pub struct Recorder {
+    pub enabled: bool,
+    /// If set, messages are kept here rather than in memory,
+    /// so they survive restarts and can be shared between processes.
+    pub directory: Option<String>,
+}
+
+/// Retries of transient provider failures, with exponential backoff.
+#[derive(Debug, Default, Deserialize)]
+pub struct Retry {
+    /// The maximum number of attempts per provider, including the first one.
+    pub attempts: u32,
+    /// The delay before the first retry, which doubles for each retry after that.
+    #[serde(deserialize_with = "deserialize::duration")]
+    pub basedelay: u64,
+    /// The maximum random delay added to each retry,
+    /// so that requests which failed together don't retry in lockstep.
+    #[serde(deserialize_with = "deserialize::duration")]
+    pub jitter: u64,
+    /// No more retries are made once a send has taken this long.
+    #[serde(deserialize_with = "deserialize::duration")]
+    pub deadline: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Routing {
+    pub sticky: bool,
+    pub weights: Vec<ProviderWeight>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Sender {
     #[serde(deserialize_with = "deserialize::email_address")]
     pub address: String,
@@ -48,10 +209,38 @@ pub struct Sender {
     pub name: String,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct Sendgrid {
     #[serde(deserialize_with = "deserialize::sendgrid_api_key")]
     pub key: String,
+    /// Sandbox mode exercises the full API without delivering any mail.
+    #[serde(default)]
+    pub sandbox: bool,
+    #[serde(
+        default = "default_sendgrid_base_uri", deserialize_with = "deserialize::base_uri"
+    )]
+    pub baseuri: String,
+    /// The public key that Sendgrid signs Event Webhook requests with,
+    /// base64-encoded as shown in its mail settings.
+    /// `/events/sendgrid` isn't available unless this is set.
+    #[serde(default, deserialize_with = "deserialize::sendgrid_webhook_key")]
+    pub webhookkey: Option<String>,
+}
+
+impl Debug for Sendgrid {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Sendgrid")
+            .field("key", &REDACTED)
+            .field("sandbox", &self.sandbox)
+            .field("baseuri", &self.baseuri)
+            .field("webhookkey", &self.webhookkey.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+fn default_sendgrid_base_uri() -> String {
+    String::from("https://api.sendgrid.com/")
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -59,27 +248,125 @@ pub struct Ses {
     #[serde(deserialize_with = "deserialize::aws_region")]
     pub region: String,
     pub keys: Option<AwsKeys>,
+    /// The configuration set that messages are sent with,
+    /// for event publishing.
+    #[serde(default, deserialize_with = "deserialize::ses_configuration_set")]
+    pub configurationset: Option<String>,
+    /// Overrides the endpoint for `region`,
+    /// e.g. to point at a local SES stand-in.
+    #[serde(default, deserialize_with = "deserialize::aws_endpoint")]
+    pub endpoint: Option<String>,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct Smtp {
     #[serde(deserialize_with = "deserialize::host")]
     pub host: String,
     pub port: u16,
     pub user: Option<String>,
     pub password: Option<String>,
+    /// How the connection is secured:
+    /// `none`, `opportunistic` or `required` STARTTLS,
+    /// or `tls` for implicit TLS.
+    /// Defaults to `required` if `user` and `password` are set
+    /// and `none` otherwise.
+    #[serde(default, deserialize_with = "deserialize::smtp_security")]
+    pub security: Option<String>,
+}
+
+impl Debug for Smtp {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Smtp")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("user", &self.user)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("security", &self.security)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Spool {
+    /// Where messages are written before they're sent,
+    /// so that they survive the service dying or every provider being down.
+    pub directory: String,
+}
+
+/// The queues that SES publishes notifications to, via SNS.
+/// The queues binary reads them, the service doesn't.
+#[derive(Debug, Default, Deserialize)]
+pub struct Sqs {
+    #[serde(deserialize_with = "deserialize::aws_region")]
+    pub region: String,
+    pub keys: Option<AwsKeys>,
+    pub urls: SqsUrls,
+}
+
+/// Requests are sent to the host in each URL,
+/// so they can point at a local SQS stand-in.
+#[derive(Debug, Default, Deserialize)]
+pub struct SqsUrls {
+    #[serde(deserialize_with = "deserialize::sqs_url")]
+    pub bounce: String,
+    #[serde(deserialize_with = "deserialize::sqs_url")]
+    pub complaint: String,
+    #[serde(deserialize_with = "deserialize::sqs_url")]
+    pub delivery: String,
+}
+
+/// Where the status of each sent message is kept.
+/// The service and the queues binary must share it.
+#[derive(Debug, Default, Deserialize)]
+pub struct Status {
+    pub directory: String,
+}
+
+/// When enabled, `/send` responds as soon as a message is queued
+/// and a pool of worker threads sends it in the background.
+#[derive(Debug, Default, Deserialize)]
+pub struct WorkerPool {
+    pub enabled: bool,
+    /// The number of worker threads.
+    pub size: usize,
+    /// The number of messages that can wait for a worker
+    /// before requests are rejected.
+    pub queuesize: usize,
 }
 
 #[derive(Debug, Default, Deserialize)]
 pub struct Settings {
+    pub attachments: Attachments,
     pub authdb: AuthDb,
     pub bouncelimits: BounceLimits,
+    pub circuitbreaker: CircuitBreaker,
+    pub dkim: Option<Dkim>,
+    #[serde(deserialize_with = "deserialize::providers")]
+    pub failover: Vec<String>,
+    pub localqueues: Option<LocalQueues>,
+    pub mailgun: Option<Mailgun>,
+    pub mock: Mock,
     #[serde(deserialize_with = "deserialize::provider")]
     pub provider: String,
+    /// Where the queues binary reads notifications from,
+    /// either `sqs` or `local`.
+    #[serde(deserialize_with = "deserialize::queue")]
+    pub queue: String,
+    pub recorder: Recorder,
+    pub retry: Retry,
+    pub routing: Routing,
     pub sender: Sender,
+    /// Additional identities that callers may send as,
+    /// by setting `from` in the request payload.
+    pub senders: Vec<Sender>,
     pub sendgrid: Option<Sendgrid>,
     pub ses: Ses,
     pub smtp: Smtp,
+    pub spool: Option<Spool>,
+    pub sqs: Sqs,
+    pub status: Option<Status>,
+    pub workerpool: WorkerPool,
 }
 
 impl Settings {
@@ -3,9 +3,11 @@
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::{
-    collections::{HashMap, HashSet}, env, error::Error,
+    collections::{HashMap, HashSet}, env, error::Error, fs, ops::Deref, path::{Path, PathBuf},
 };
 
+use rand::{thread_rng, Rng};
+
 use super::*;
 
 struct CleanEnvironment {
@@ -49,15 +51,71 @@ impl Drop for CleanEnvironment {
     }
 }
 
+/// A uniquely-named directory for tests to point settings at,
+/// which is removed with everything in it when it's dropped,
+/// even if the test fails.
+/// It isn't created, so that tests can check that it gets created.
+pub struct TemporaryDirectory {
+    path: PathBuf,
+}
+
+impl TemporaryDirectory {
+    pub fn new(name: &str) -> TemporaryDirectory {
+        let mut rng = thread_rng();
+        TemporaryDirectory {
+            path: env::temp_dir().join(format!("fxa-email-{}-{:016x}", name, rng.gen::<u64>())),
+        }
+    }
+}
+
+impl Deref for TemporaryDirectory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TemporaryDirectory {
+    fn drop(&mut self) {
+        // It may never have been created
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
 #[test]
 fn env_vars_take_precedence() {
     let _clean_env = CleanEnvironment::new(vec![
+        "FXA_EMAIL_ATTACHMENTS_MAXSIZE",
         "FXA_EMAIL_AUTHDB_BASEURI",
         "FXA_EMAIL_BOUNCELIMITS_ENABLED",
+        "FXA_EMAIL_CIRCUITBREAKER_THRESHOLD",
+        "FXA_EMAIL_DKIM_ALGORITHM",
+        "FXA_EMAIL_DKIM_DOMAIN",
+        "FXA_EMAIL_DKIM_PRIVATEKEY",
+        "FXA_EMAIL_DKIM_SELECTOR",
+        "FXA_EMAIL_LOCALQUEUES_DIRECTORY",
+        "FXA_EMAIL_MAILGUN_BASEURI",
+        "FXA_EMAIL_MAILGUN_DOMAIN",
+        "FXA_EMAIL_MAILGUN_KEY",
+        "FXA_EMAIL_MOCK_FAILURERATE",
+        "FXA_EMAIL_MOCK_LATENCY",
+        "FXA_EMAIL_MOCK_MAXDELAY",
         "FXA_EMAIL_PROVIDER",
+        "FXA_EMAIL_QUEUE",
+        "FXA_EMAIL_RECORDER_DIRECTORY",
+        "FXA_EMAIL_RECORDER_ENABLED",
+        "FXA_EMAIL_RETRY_ATTEMPTS",
+        "FXA_EMAIL_RETRY_BASEDELAY",
+        "FXA_EMAIL_ROUTING_STICKY",
         "FXA_EMAIL_SENDER_ADDRESS",
         "FXA_EMAIL_SENDER_NAME",
+        "FXA_EMAIL_SENDGRID_BASEURI",
         "FXA_EMAIL_SENDGRID_KEY",
+        "FXA_EMAIL_SENDGRID_SANDBOX",
+        "FXA_EMAIL_SENDGRID_WEBHOOKKEY",
+        "FXA_EMAIL_SES_CONFIGURATIONSET",
+        "FXA_EMAIL_SES_ENDPOINT",
         "FXA_EMAIL_SES_REGION",
         "FXA_EMAIL_SES_KEYS_ACCESS",
         "FXA_EMAIL_SES_KEYS_SECRET",
@@ -65,22 +123,51 @@ fn env_vars_take_precedence() {
         "FXA_EMAIL_SMTP_PORT",
         "FXA_EMAIL_SMTP_USER",
         "FXA_EMAIL_SMTP_PASSWORD",
+        "FXA_EMAIL_SMTP_SECURITY",
+        "FXA_EMAIL_SPOOL_DIRECTORY",
+        "FXA_EMAIL_SQS_REGION",
+        "FXA_EMAIL_SQS_URLS_BOUNCE",
+        "FXA_EMAIL_SQS_URLS_COMPLAINT",
+        "FXA_EMAIL_SQS_URLS_DELIVERY",
+        "FXA_EMAIL_STATUS_DIRECTORY",
+        "FXA_EMAIL_WORKERPOOL_ENABLED",
+        "FXA_EMAIL_WORKERPOOL_SIZE",
+        "FXA_EMAIL_WORKERPOOL_QUEUESIZE",
     ]);
 
     match Settings::new() {
         Ok(settings) => {
+            let attachments_max_size = settings.attachments.maxsize + 1;
             let auth_db_base_uri = format!("{}foo/", &settings.authdb.baseuri);
             let bounce_limits_enabled = !settings.bouncelimits.enabled;
+            let circuit_breaker_threshold = settings.circuitbreaker.threshold + 1;
+            let mailgun_base_uri = String::from("https://api.eu.mailgun.net/v3/");
+            let mailgun_domain = String::from("mail.example.com");
+            let mailgun_api_key = String::from("key-0123456789abcdef0123456789abcdef");
+            let mock_failure_rate = (settings.mock.failurerate + 1) % 101;
             let provider = if settings.provider == "ses" {
                 "smtp"
             } else {
                 "ses"
             };
+            let queue = if settings.queue == "sqs" {
+                "local"
+            } else {
+                "sqs"
+            };
+            let recorder_enabled = !settings.recorder.enabled;
+            let retry_attempts = settings.retry.attempts + 1;
+            let routing_sticky = !settings.routing.sticky;
             let sender_address = format!("1{}", &settings.sender.address);
             let sender_name = format!("{}1", &settings.sender.name);
             let sendgrid_api_key = String::from(
                 "000000000000000000000000000000000000000000000000000000000000000000000",
             );
+            let sendgrid_webhook_key = String::from(
+                "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEfuU+70dabBAP2ZU/jQODTgI9QKe2uV8LRuVnrgAWeOZ8PLqWS/SxczSlNqgn6DSwzmKDjXBg2w1mhAlRQrQngg==",
+            );
+            let ses_configuration_set = String::from("fxa-email");
+            let ses_endpoint = String::from("http://127.0.0.1:9000");
             let ses_region = if settings.ses.region == "us-east-1" {
                 "eu-west-1"
             } else {
@@ -109,16 +196,67 @@ fn env_vars_take_precedence() {
             } else {
                 String::from("5")
             };
+            let smtp_security = if settings.smtp.security == Some(String::from("tls")) {
+                "opportunistic"
+            } else {
+                "tls"
+            };
+            let spool_directory = String::from("/var/spool/fxa-email");
+            let sqs_region = if settings.sqs.region == "us-east-1" {
+                "eu-west-1"
+            } else {
+                "us-east-1"
+            };
+            let sqs_bounce_url = format!("{}1", &settings.sqs.urls.bounce);
+            let sqs_complaint_url = format!("{}2", &settings.sqs.urls.complaint);
+            let sqs_delivery_url = format!("{}3", &settings.sqs.urls.delivery);
+            let status_directory = String::from("/var/lib/fxa-email/status");
+            let worker_pool_enabled = !settings.workerpool.enabled;
+            let worker_pool_size = settings.workerpool.size + 1;
+            let worker_pool_queue_size = settings.workerpool.queuesize + 2;
 
+            env::set_var(
+                "FXA_EMAIL_ATTACHMENTS_MAXSIZE",
+                &attachments_max_size.to_string(),
+            );
             env::set_var("FXA_EMAIL_AUTHDB_BASEURI", &auth_db_base_uri);
             env::set_var(
                 "FXA_EMAIL_BOUNCELIMITS_ENABLED",
                 &bounce_limits_enabled.to_string(),
             );
+            env::set_var(
+                "FXA_EMAIL_CIRCUITBREAKER_THRESHOLD",
+                &circuit_breaker_threshold.to_string(),
+            );
+            env::set_var("FXA_EMAIL_DKIM_ALGORITHM", "ed25519-sha256");
+            env::set_var("FXA_EMAIL_DKIM_DOMAIN", "firefox.com");
+            env::set_var("FXA_EMAIL_DKIM_PRIVATEKEY", "AAECAw==");
+            env::set_var("FXA_EMAIL_DKIM_SELECTOR", "fxa");
+            env::set_var("FXA_EMAIL_LOCALQUEUES_DIRECTORY", "/tmp/fxa-email-queues");
+            env::set_var("FXA_EMAIL_MAILGUN_BASEURI", &mailgun_base_uri);
+            env::set_var("FXA_EMAIL_MAILGUN_DOMAIN", &mailgun_domain);
+            env::set_var("FXA_EMAIL_MAILGUN_KEY", &mailgun_api_key);
+            env::set_var(
+                "FXA_EMAIL_MOCK_FAILURERATE",
+                &mock_failure_rate.to_string(),
+            );
+            env::set_var("FXA_EMAIL_MOCK_LATENCY", "50 milliseconds");
+            env::set_var("FXA_EMAIL_MOCK_MAXDELAY", "2 seconds");
             env::set_var("FXA_EMAIL_PROVIDER", &provider);
+            env::set_var("FXA_EMAIL_QUEUE", &queue);
+            env::set_var("FXA_EMAIL_RECORDER_DIRECTORY", "/tmp/fxa-email-recorder");
+            env::set_var("FXA_EMAIL_RECORDER_ENABLED", &recorder_enabled.to_string());
+            env::set_var("FXA_EMAIL_RETRY_ATTEMPTS", &retry_attempts.to_string());
+            env::set_var("FXA_EMAIL_RETRY_BASEDELAY", "500 milliseconds");
+            env::set_var("FXA_EMAIL_ROUTING_STICKY", &routing_sticky.to_string());
             env::set_var("FXA_EMAIL_SENDER_ADDRESS", &sender_address);
             env::set_var("FXA_EMAIL_SENDER_NAME", &sender_name);
+            env::set_var("FXA_EMAIL_SENDGRID_BASEURI", "http://127.0.0.1:9001/");
             env::set_var("FXA_EMAIL_SENDGRID_KEY", &sendgrid_api_key);
+            env::set_var("FXA_EMAIL_SENDGRID_SANDBOX", "true");
+            env::set_var("FXA_EMAIL_SENDGRID_WEBHOOKKEY", &sendgrid_webhook_key);
+            env::set_var("FXA_EMAIL_SES_CONFIGURATIONSET", &ses_configuration_set);
+            env::set_var("FXA_EMAIL_SES_ENDPOINT", &ses_endpoint);
             env::set_var("FXA_EMAIL_SES_REGION", &ses_region);
             env::set_var("FXA_EMAIL_SES_KEYS_ACCESS", &ses_keys.access);
             env::set_var("FXA_EMAIL_SES_KEYS_SECRET", &ses_keys.secret);
@@ -126,20 +264,91 @@ fn env_vars_take_precedence() {
             env::set_var("FXA_EMAIL_SMTP_PORT", &smtp_port.to_string());
             env::set_var("FXA_EMAIL_SMTP_USER", &smtp_user);
             env::set_var("FXA_EMAIL_SMTP_PASSWORD", &smtp_password);
+            env::set_var("FXA_EMAIL_SMTP_SECURITY", &smtp_security);
+            env::set_var("FXA_EMAIL_SPOOL_DIRECTORY", &spool_directory);
+            env::set_var("FXA_EMAIL_SQS_REGION", &sqs_region);
+            env::set_var("FXA_EMAIL_SQS_URLS_BOUNCE", &sqs_bounce_url);
+            env::set_var("FXA_EMAIL_SQS_URLS_COMPLAINT", &sqs_complaint_url);
+            env::set_var("FXA_EMAIL_SQS_URLS_DELIVERY", &sqs_delivery_url);
+            env::set_var("FXA_EMAIL_STATUS_DIRECTORY", &status_directory);
+            env::set_var(
+                "FXA_EMAIL_WORKERPOOL_ENABLED",
+                &worker_pool_enabled.to_string(),
+            );
+            env::set_var("FXA_EMAIL_WORKERPOOL_SIZE", &worker_pool_size.to_string());
+            env::set_var(
+                "FXA_EMAIL_WORKERPOOL_QUEUESIZE",
+                &worker_pool_queue_size.to_string(),
+            );
 
             match Settings::new() {
                 Ok(env_settings) => {
+                    assert_eq!(env_settings.attachments.maxsize, attachments_max_size);
                     assert_eq!(env_settings.authdb.baseuri, auth_db_base_uri);
                     assert_eq!(env_settings.bouncelimits.enabled, bounce_limits_enabled);
+                    assert_eq!(
+                        env_settings.circuitbreaker.threshold,
+                        circuit_breaker_threshold
+                    );
+                    assert_eq!(env_settings.mock.failurerate, mock_failure_rate);
+                    assert_eq!(env_settings.mock.latency, 50);
+                    assert_eq!(env_settings.mock.maxdelay, 2000);
                     assert_eq!(env_settings.provider, provider);
+                    assert_eq!(env_settings.queue, queue);
+                    assert_eq!(env_settings.recorder.enabled, recorder_enabled);
+                    assert_eq!(
+                        env_settings.recorder.directory,
+                        Some(String::from("/tmp/fxa-email-recorder"))
+                    );
+                    assert_eq!(env_settings.retry.attempts, retry_attempts);
+                    assert_eq!(env_settings.retry.basedelay, 500);
+                    assert_eq!(env_settings.routing.sticky, routing_sticky);
                     assert_eq!(env_settings.sender.address, sender_address);
                     assert_eq!(env_settings.sender.name, sender_name);
                     assert_eq!(env_settings.ses.region, ses_region);
+                    assert_eq!(
+                        env_settings.ses.configurationset,
+                        Some(ses_configuration_set)
+                    );
+                    assert_eq!(env_settings.ses.endpoint, Some(ses_endpoint));
                     assert_eq!(env_settings.smtp.host, smtp_host);
                     assert_eq!(env_settings.smtp.port, smtp_port);
+                    assert_eq!(env_settings.sqs.region, sqs_region);
+                    assert_eq!(env_settings.sqs.urls.bounce, sqs_bounce_url);
+                    assert_eq!(env_settings.sqs.urls.complaint, sqs_complaint_url);
+                    assert_eq!(env_settings.sqs.urls.delivery, sqs_delivery_url);
+                    assert_eq!(env_settings.workerpool.enabled, worker_pool_enabled);
+                    assert_eq!(env_settings.workerpool.size, worker_pool_size);
+                    assert_eq!(env_settings.workerpool.queuesize, worker_pool_queue_size);
+
+                    if let Some(env_dkim) = env_settings.dkim {
+                        assert_eq!(env_dkim.algorithm, "ed25519-sha256");
+                        assert_eq!(env_dkim.domain, "firefox.com");
+                        assert_eq!(env_dkim.privatekey, vec![0, 1, 2, 3]);
+                        assert_eq!(env_dkim.selector, "fxa");
+                    } else {
+                        assert!(false, "settings.dkim was not set");
+                    }
+
+                    if let Some(env_local_queues) = env_settings.localqueues {
+                        assert_eq!(env_local_queues.directory, "/tmp/fxa-email-queues");
+                    } else {
+                        assert!(false, "settings.localqueues was not set");
+                    }
+
+                    if let Some(env_mailgun) = env_settings.mailgun {
+                        assert_eq!(env_mailgun.baseuri, mailgun_base_uri);
+                        assert_eq!(env_mailgun.domain, mailgun_domain);
+                        assert_eq!(env_mailgun.key, mailgun_api_key);
+                    } else {
+                        assert!(false, "settings.mailgun was not set");
+                    }
 
                     if let Some(env_sendgrid) = env_settings.sendgrid {
+                        assert_eq!(env_sendgrid.baseuri, "http://127.0.0.1:9001/");
                         assert_eq!(env_sendgrid.key, sendgrid_api_key);
+                        assert!(env_sendgrid.sandbox);
+                        assert_eq!(env_sendgrid.webhookkey, Some(sendgrid_webhook_key));
                     } else {
                         assert!(false, "settings.sendgrid was not set");
                     }
@@ -162,6 +371,20 @@ fn env_vars_take_precedence() {
                     } else {
                         assert!(false, "smtp.password was not set");
                     }
+
+                    assert_eq!(env_settings.smtp.security, Some(smtp_security.to_string()));
+
+                    if let Some(env_spool) = env_settings.spool {
+                        assert_eq!(env_spool.directory, spool_directory);
+                    } else {
+                        assert!(false, "settings.spool was not set");
+                    }
+
+                    if let Some(env_status) = env_settings.status {
+                        assert_eq!(env_status.directory, status_directory);
+                    } else {
+                        assert!(false, "settings.status was not set");
+                    }
                 }
                 Err(error) => {
                     println!("{}", error);
@@ -198,6 +421,129 @@ fn invalid_bouncelimits_enabled() {
     }
 }
 
+#[test]
+fn invalid_circuit_breaker_cooldown() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_CIRCUITBREAKER_COOLDOWN"]);
+    env::set_var("FXA_EMAIL_CIRCUITBREAKER_COOLDOWN", "1 fortnight");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn invalid_mock_failure_rate() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_MOCK_FAILURERATE"]);
+    env::set_var("FXA_EMAIL_MOCK_FAILURERATE", "101");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn invalid_dkim_algorithm() {
+    let _clean_env = CleanEnvironment::new(vec![
+        "FXA_EMAIL_DKIM_ALGORITHM",
+        "FXA_EMAIL_DKIM_DOMAIN",
+        "FXA_EMAIL_DKIM_PRIVATEKEY",
+        "FXA_EMAIL_DKIM_SELECTOR",
+    ]);
+    env::set_var("FXA_EMAIL_DKIM_ALGORITHM", "rsa-sha1");
+    env::set_var("FXA_EMAIL_DKIM_DOMAIN", "firefox.com");
+    env::set_var("FXA_EMAIL_DKIM_PRIVATEKEY", "AAECAw==");
+    env::set_var("FXA_EMAIL_DKIM_SELECTOR", "fxa");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn invalid_dkim_private_key() {
+    let _clean_env = CleanEnvironment::new(vec![
+        "FXA_EMAIL_DKIM_ALGORITHM",
+        "FXA_EMAIL_DKIM_DOMAIN",
+        "FXA_EMAIL_DKIM_PRIVATEKEY",
+        "FXA_EMAIL_DKIM_SELECTOR",
+    ]);
+    env::set_var("FXA_EMAIL_DKIM_ALGORITHM", "rsa-sha256");
+    env::set_var("FXA_EMAIL_DKIM_DOMAIN", "firefox.com");
+    env::set_var("FXA_EMAIL_DKIM_PRIVATEKEY", "not base64!");
+    env::set_var("FXA_EMAIL_DKIM_SELECTOR", "fxa");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn dkim_debug_redacts_private_key() {
+    let dkim = Dkim {
+        algorithm: String::from("ed25519-sha256"),
+        domain: String::from("firefox.com"),
+        selector: String::from("fxa"),
+        privatekey: vec![42; 48],
+    };
+
+    let debug = format!("{:?}", dkim);
+    assert!(!debug.contains("42"));
+    assert!(debug.contains("[redacted]"));
+    assert!(debug.contains("firefox.com"));
+}
+
+#[test]
+fn invalid_mailgun_api_key() {
+    let _clean_env = CleanEnvironment::new(vec![
+        "FXA_EMAIL_MAILGUN_BASEURI",
+        "FXA_EMAIL_MAILGUN_DOMAIN",
+        "FXA_EMAIL_MAILGUN_KEY",
+    ]);
+    env::set_var("FXA_EMAIL_MAILGUN_BASEURI", "https://api.mailgun.net/v3/");
+    env::set_var("FXA_EMAIL_MAILGUN_DOMAIN", "mail.example.com");
+    env::set_var("FXA_EMAIL_MAILGUN_KEY", "foo bar");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn invalid_mailgun_domain() {
+    let _clean_env = CleanEnvironment::new(vec![
+        "FXA_EMAIL_MAILGUN_BASEURI",
+        "FXA_EMAIL_MAILGUN_DOMAIN",
+        "FXA_EMAIL_MAILGUN_KEY",
+    ]);
+    env::set_var("FXA_EMAIL_MAILGUN_BASEURI", "https://api.mailgun.net/v3/");
+    env::set_var("FXA_EMAIL_MAILGUN_DOMAIN", "https://mail.example.com/");
+    env::set_var("FXA_EMAIL_MAILGUN_KEY", "key-0123456789abcdef0123456789abcdef");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn mailgun_debug_redacts_key() {
+    let mailgun = Mailgun {
+        key: String::from("key-0123456789abcdef0123456789abcdef"),
+        domain: String::from("mail.example.com"),
+        baseuri: String::from("https://api.mailgun.net/v3/"),
+    };
+
+    let debug = format!("{:?}", mailgun);
+    assert!(!debug.contains("0123456789abcdef"));
+    assert!(debug.contains("[redacted]"));
+    assert!(debug.contains("mail.example.com"));
+}
+
 #[test]
 fn invalid_provider() {
     let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_PROVIDER"]);
@@ -209,6 +555,17 @@ fn invalid_provider() {
     }
 }
 
+#[test]
+fn invalid_queue() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_QUEUE"]);
+    env::set_var("FXA_EMAIL_QUEUE", "redis");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
 #[test]
 fn invalid_sender_address() {
     let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_SENDER_ADDRESS"]);
@@ -242,6 +599,91 @@ fn invalid_sendgrid_api_key() {
     }
 }
 
+#[test]
+fn invalid_sendgrid_webhook_key() {
+    let _clean_env = CleanEnvironment::new(vec![
+        "FXA_EMAIL_SENDGRID_KEY",
+        "FXA_EMAIL_SENDGRID_WEBHOOKKEY",
+    ]);
+    env::set_var(
+        "FXA_EMAIL_SENDGRID_KEY",
+        "000000000000000000000000000000000000000000000000000000000000000000000",
+    );
+    env::set_var("FXA_EMAIL_SENDGRID_WEBHOOKKEY", "foo bar");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn default_sendgrid_settings() {
+    let _clean_env = CleanEnvironment::new(vec![
+        "FXA_EMAIL_SENDGRID_BASEURI",
+        "FXA_EMAIL_SENDGRID_KEY",
+        "FXA_EMAIL_SENDGRID_SANDBOX",
+    ]);
+    env::set_var(
+        "FXA_EMAIL_SENDGRID_KEY",
+        "000000000000000000000000000000000000000000000000000000000000000000000",
+    );
+
+    match Settings::new() {
+        Ok(settings) => {
+            if let Some(sendgrid) = settings.sendgrid {
+                assert_eq!(sendgrid.baseuri, "https://api.sendgrid.com/");
+                assert!(!sendgrid.sandbox);
+                assert_eq!(sendgrid.webhookkey, None);
+            } else {
+                assert!(false, "settings.sendgrid was not set");
+            }
+        }
+        Err(error) => {
+            println!("{}", error);
+            assert!(false);
+        }
+    }
+}
+
+#[test]
+fn sendgrid_debug_redacts_keys() {
+    let sendgrid = Sendgrid {
+        key: "0".repeat(69),
+        sandbox: false,
+        baseuri: String::from("https://api.sendgrid.com/"),
+        webhookkey: Some(String::from("MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE")),
+    };
+
+    let debug = format!("{:?}", sendgrid);
+    assert!(!debug.contains("000000"));
+    assert!(!debug.contains("MFkwEwYH"));
+    assert!(debug.contains("[redacted]"));
+    assert!(debug.contains("https://api.sendgrid.com/"));
+}
+
+#[test]
+fn invalid_ses_configuration_set() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_SES_CONFIGURATIONSET"]);
+    env::set_var("FXA_EMAIL_SES_CONFIGURATIONSET", "foo bar");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn invalid_ses_endpoint() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_SES_ENDPOINT"]);
+    env::set_var("FXA_EMAIL_SES_ENDPOINT", "localhost:9000");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
 #[test]
 fn invalid_ses_region() {
     let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_SES_REGION"]);
@@ -285,3 +727,52 @@ fn invalid_smtp_host() {
         Err(error) => assert_eq!(error.description(), "configuration error"),
     }
 }
+
+#[test]
+fn invalid_smtp_security() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_SMTP_SECURITY"]);
+    env::set_var("FXA_EMAIL_SMTP_SECURITY", "starttls");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn smtp_debug_redacts_password() {
+    let smtp = Smtp {
+        host: String::from("127.0.0.1"),
+        port: 25,
+        user: Some(String::from("foo")),
+        password: Some(String::from("wibble")),
+        security: None,
+    };
+
+    let debug = format!("{:?}", smtp);
+    assert!(!debug.contains("wibble"));
+    assert!(debug.contains("[redacted]"));
+    assert!(debug.contains("foo"));
+}
+
+#[test]
+fn invalid_sqs_bounce_url() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_SQS_URLS_BOUNCE"]);
+    env::set_var("FXA_EMAIL_SQS_URLS_BOUNCE", "sqs.us-east-1.amazonaws.com/foo");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
+
+#[test]
+fn invalid_sqs_complaint_url() {
+    let _clean_env = CleanEnvironment::new(vec!["FXA_EMAIL_SQS_URLS_COMPLAINT"]);
+    env::set_var("FXA_EMAIL_SQS_URLS_COMPLAINT", "http://127.0.0.1:4100");
+
+    match Settings::new() {
+        Ok(_settings) => assert!(false, "Settings::new should have failed"),
+        Err(error) => assert_eq!(error.description(), "configuration error"),
+    }
+}
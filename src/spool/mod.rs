@@ -0,0 +1,142 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    error::Error, fmt::{self, Display, Formatter}, fs::{self, File},
+    io::{Error as IoError, Write}, path::PathBuf,
+};
+
+use rand::{thread_rng, Rng};
+use serde_json::{self, Error as JsonError};
+
+use providers::Message;
+use settings::Spool as SpoolSettings;
+
+#[cfg(test)]
+mod test;
+
+/// A message that has been accepted but not yet sent.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Entry {
+    pub message: Message,
+    pub provider: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct SpoolError {
+    description: String,
+}
+
+impl SpoolError {
+    pub fn new(description: String) -> SpoolError {
+        SpoolError { description }
+    }
+}
+
+impl Error for SpoolError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Display for SpoolError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description)
+    }
+}
+
+impl From<IoError> for SpoolError {
+    fn from(error: IoError) -> SpoolError {
+        SpoolError::new(format!("spool I/O error: {:?}", error))
+    }
+}
+
+impl From<JsonError> for SpoolError {
+    fn from(error: JsonError) -> SpoolError {
+        SpoolError::new(format!("spool JSON error: {:?}", error))
+    }
+}
+
+/// Ids are generated separately from writing entries,
+/// so that callers can keep track of an entry before it appears.
+pub fn generate_id() -> String {
+    let mut rng = thread_rng();
+    format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>())
+}
+
+/// A write-ahead spool of outbound messages,
+/// stored as one JSON file per message.
+#[derive(Debug)]
+pub struct Spool {
+    directory: PathBuf,
+}
+
+impl Spool {
+    pub fn new(settings: &SpoolSettings) -> Spool {
+        let directory = PathBuf::from(&settings.directory);
+        fs::create_dir_all(&directory).expect("failed to create spool directory");
+        Spool { directory }
+    }
+
+    /// Persist an entry under an id from `generate_id`.
+    /// Entries are written to a temporary file which is then renamed,
+    /// so a crash can never leave a partially-written entry behind.
+    pub fn write(&self, id: &str, entry: &Entry) -> Result<(), SpoolError> {
+        let temporary_path = self.directory.join(format!("{}.tmp", id));
+
+        {
+            let mut file = File::create(&temporary_path)?;
+            file.write_all(&serde_json::to_vec(entry)?)?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&temporary_path, self.path(id))?;
+
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), SpoolError> {
+        fs::remove_file(self.path(id)).map_err(From::from)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.path(id).exists()
+    }
+
+    /// Every entry in the spool, oldest first.
+    pub fn pending(&self) -> Result<Vec<(String, Entry)>, SpoolError> {
+        let mut paths = Vec::new();
+        for dir_entry in fs::read_dir(&self.directory)? {
+            let path = dir_entry?.path();
+            if path.extension().map_or(false, |extension| extension == "json") {
+                let modified = fs::metadata(&path)?.modified()?;
+                paths.push((modified, path));
+            }
+        }
+        paths.sort_by_key(|&(modified, _)| modified);
+
+        let mut entries = Vec::new();
+        for (_, path) in paths {
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+
+            let entry = File::open(&path)
+                .map_err(SpoolError::from)
+                .and_then(|file| serde_json::from_reader(file).map_err(From::from));
+            match entry {
+                Ok(entry) => entries.push((id, entry)),
+                // TODO: replace this with proper logging when we have it
+                Err(error) => println!("skipping spool entry {}: {}", id, error),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", id))
+    }
+}
@@ -0,0 +1,96 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashMap, env};
+
+use super::*;
+use providers::{Attachment, Disposition};
+use settings::Sender;
+
+#[test]
+fn write_and_remove() {
+    let spool = create_spool();
+    let entry = create_entry();
+
+    let id = generate_id();
+    assert!(!spool.contains(&id));
+    spool.write(&id, &entry).expect("write error");
+    assert!(spool.contains(&id));
+
+    let pending = spool.pending().expect("pending error");
+    assert_eq!(pending.len(), 1);
+    let (ref pending_id, ref pending_entry) = pending[0];
+    assert_eq!(pending_id, &id);
+    assert_eq!(pending_entry.message.to, entry.message.to);
+    assert_eq!(pending_entry.message.subject, entry.message.subject);
+    assert_eq!(pending_entry.message.body_html, entry.message.body_html);
+    assert_eq!(
+        pending_entry.message.attachments[0].content,
+        entry.message.attachments[0].content
+    );
+    assert_eq!(pending_entry.message.from.address, "security@firefox.com");
+    assert_eq!(pending_entry.provider, entry.provider);
+
+    spool.remove(&id).expect("remove error");
+    assert!(!spool.contains(&id));
+    assert_eq!(spool.pending().expect("pending error").len(), 0);
+
+    fs::remove_dir_all(&spool.directory).expect("cleanup error");
+}
+
+#[test]
+fn pending_skips_other_files() {
+    let spool = create_spool();
+    let id = generate_id();
+    spool.write(&id, &create_entry()).expect("write error");
+
+    File::create(spool.directory.join("foo.tmp")).expect("create error");
+    File::create(spool.directory.join("bar.json"))
+        .and_then(|mut file| file.write_all(b"{"))
+        .expect("create error");
+
+    let pending = spool.pending().expect("pending error");
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].0, id);
+
+    fs::remove_dir_all(&spool.directory).expect("cleanup error");
+}
+
+fn create_spool() -> Spool {
+    let mut rng = thread_rng();
+    let directory = env::temp_dir().join(format!("fxa-email-spool-{:016x}", rng.gen::<u64>()));
+    Spool::new(&SpoolSettings {
+        directory: directory.to_string_lossy().into_owned(),
+    })
+}
+
+fn create_entry() -> Entry {
+    Entry {
+        message: Message {
+            from: Sender {
+                address: String::from("security@firefox.com"),
+                name: String::from("Firefox Accounts Security"),
+            },
+            to: vec![String::from("foo@example.com")],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            reply_to: None,
+            headers: HashMap::new(),
+            subject: String::from("bar"),
+            body_text: String::from("baz"),
+            body_html: Some(String::from("<a>baz</a>")),
+            attachments: vec![Attachment {
+                filename: String::from("qux.bin"),
+                content_type: String::from("application/octet-stream"),
+                content: vec![0, 1, 2, 255],
+                disposition: Disposition::Attachment,
+                content_id: None,
+            }],
+            tags: HashMap::new(),
+            categories: Vec::new(),
+            metadata: HashMap::new(),
+        },
+        provider: Some(String::from("mock")),
+    }
+}
@@ -0,0 +1,231 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! What happened to each message after it was sent,
+//! keyed by the message id that `/send` returned.
+//! Ids that `/send` returns before a message is sent
+//! are linked to the provider's id once it is.
+//! The service records when messages are sent
+//! and the queues binary records the notifications that follow,
+//! so both of them need to point at the same directory.
+
+use std::{
+    collections::HashMap, error::Error, fmt::{self, Display, Formatter}, fs::{self, File},
+    io::{Error as IoError, ErrorKind, Write}, path::{Path, PathBuf}, process, sync::{Arc, Mutex},
+};
+
+use hex;
+use rand::{thread_rng, Rng};
+use serde_json::{self, Error as JsonError};
+
+use settings::Status as StatusSettings;
+
+#[cfg(test)]
+pub mod test;
+
+/// The message id that `/send` returns and that statuses are keyed by,
+/// which is the provider's own id prefixed with the provider's name.
+/// Notifications and events only carry the provider's id,
+/// so they have to be mapped through this to find their message.
+pub fn message_id(provider: &str, provider_message_id: &str) -> String {
+    format!("{}:{}", provider, provider_message_id)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Sent,
+    Delivered,
+    Bounced,
+    Complained,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStatus {
+    pub message_id: String,
+    pub sent_at: Option<String>,
+    pub delivered_at: Option<String>,
+    pub reporting_mta: Option<String>,
+    pub smtp_response: Option<String>,
+    pub bounced_at: Option<String>,
+    pub complained_at: Option<String>,
+}
+
+impl MessageStatus {
+    /// The most significant thing that has happened to the message.
+    /// Complaints can follow a successful delivery,
+    /// so they take precedence over it.
+    pub fn state(&self) -> State {
+        if self.complained_at.is_some() {
+            State::Complained
+        } else if self.bounced_at.is_some() {
+            State::Bounced
+        } else if self.delivered_at.is_some() {
+            State::Delivered
+        } else {
+            State::Sent
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StatusError {
+    description: String,
+}
+
+impl StatusError {
+    pub fn new(description: String) -> StatusError {
+        StatusError { description }
+    }
+}
+
+impl Error for StatusError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Display for StatusError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description)
+    }
+}
+
+impl From<IoError> for StatusError {
+    fn from(error: IoError) -> StatusError {
+        StatusError::new(format!("status I/O error: {:?}", error))
+    }
+}
+
+impl From<JsonError> for StatusError {
+    fn from(error: JsonError) -> StatusError {
+        StatusError::new(format!("status JSON error: {:?}", error))
+    }
+}
+
+/// Message statuses, stored as one JSON file per message.
+#[derive(Debug)]
+pub struct StatusStore {
+    directory: PathBuf,
+    locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl StatusStore {
+    pub fn new(settings: &StatusSettings) -> StatusStore {
+        let directory = PathBuf::from(&settings.directory);
+        fs::create_dir_all(&directory).expect("failed to create status directory");
+        StatusStore {
+            directory,
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Gets the status of a message,
+    /// following any links from `message_id`.
+    pub fn get(&self, message_id: &str) -> Result<Option<MessageStatus>, StatusError> {
+        // A job id can link to a spool id, which links to the provider's id
+        let mut message_id = message_id.to_string();
+        loop {
+            match fs::read_to_string(self.link_path(&message_id)) {
+                Ok(linked_id) => message_id = linked_id,
+                Err(ref error) if error.kind() == ErrorKind::NotFound => break,
+                Err(error) => return Err(From::from(error)),
+            }
+        }
+
+        match File::open(self.path(&message_id)) {
+            Ok(file) => serde_json::from_reader(file).map(Some).map_err(From::from),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(From::from(error)),
+        }
+    }
+
+    /// Applies `update` to the status of a message,
+    /// creating it if it doesn't exist yet.
+    /// Updates to the same message are serialized,
+    /// so concurrent ones can't overwrite each other.
+    /// Statuses are written to a temporary file which is then renamed,
+    /// so readers never see a partially-written one.
+    pub fn update<F>(&self, message_id: &str, update: F) -> Result<MessageStatus, StatusError>
+    where
+        F: FnOnce(&mut MessageStatus),
+    {
+        let lock = self.lock(message_id);
+        let result = {
+            let _guard = lock.lock().expect("status lock error");
+            self.write(message_id, update)
+        };
+        self.unlock(message_id, &lock);
+        result
+    }
+
+    fn write<F>(&self, message_id: &str, update: F) -> Result<MessageStatus, StatusError>
+    where
+        F: FnOnce(&mut MessageStatus),
+    {
+        let mut status = self.get(message_id)?.unwrap_or_else(|| MessageStatus {
+            message_id: message_id.to_string(),
+            ..MessageStatus::default()
+        });
+        update(&mut status);
+
+        self.write_file(&self.path(message_id), &serde_json::to_vec(&status)?)?;
+
+        Ok(status)
+    }
+
+    /// Links `id` to the status of `message_id`,
+    /// for ids that were returned before the provider's id was known,
+    /// i.e. by the worker pool or the spool.
+    pub fn link(&self, id: &str, message_id: &str) -> Result<(), StatusError> {
+        self.write_file(&self.link_path(id), message_id.as_bytes())
+    }
+
+    fn write_file(&self, path: &Path, contents: &[u8]) -> Result<(), StatusError> {
+        let mut rng = thread_rng();
+        let temporary_path = path.with_extension(format!(
+            "{}.{:016x}.tmp",
+            process::id(),
+            rng.gen::<u64>()
+        ));
+
+        {
+            let mut file = File::create(&temporary_path)?;
+            file.write_all(contents)?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&temporary_path, path)?;
+
+        Ok(())
+    }
+
+    fn lock(&self, message_id: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.locks.lock().expect("status locks error");
+        locks
+            .entry(message_id.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(())))
+            .clone()
+    }
+
+    // Locks are dropped once nobody else is waiting on them,
+    // so that the map doesn't grow with every message
+    fn unlock(&self, message_id: &str, lock: &Arc<Mutex<()>>) {
+        let mut locks = self.locks.lock().expect("status locks error");
+        if Arc::strong_count(lock) == 2 {
+            locks.remove(message_id);
+        }
+    }
+
+    // Provider message ids can contain characters
+    // that aren't safe in file names, e.g. Mailgun's `<id@domain>`
+    fn path(&self, message_id: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", hex::encode(message_id)))
+    }
+
+    fn link_path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{}.link", hex::encode(id)))
+    }
+}
@@ -0,0 +1,161 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{path::Path, sync::Arc, thread};
+
+use super::*;
+use settings::test::TemporaryDirectory;
+
+#[test]
+fn update_and_get() {
+    let directory = TemporaryDirectory::new("status");
+    let store = create_store(&directory);
+    assert_eq!(store.get("foo").expect("get error"), None);
+
+    let status = store
+        .update("foo", |status| {
+            status.sent_at = Some(String::from("2018-06-01T00:00:00.000Z"))
+        })
+        .expect("update error");
+    assert_eq!(status.message_id, "foo");
+    assert_eq!(status.state(), State::Sent);
+
+    store
+        .update("foo", |status| {
+            status.delivered_at = Some(String::from("2018-06-01T00:00:01.000Z"));
+            status.smtp_response = Some(String::from("250 ok"));
+        })
+        .expect("update error");
+
+    let status = store
+        .get("foo")
+        .expect("get error")
+        .expect("status should be present");
+    assert_eq!(status.state(), State::Delivered);
+    assert_eq!(
+        status.sent_at,
+        Some(String::from("2018-06-01T00:00:00.000Z"))
+    );
+    assert_eq!(
+        status.delivered_at,
+        Some(String::from("2018-06-01T00:00:01.000Z"))
+    );
+    assert_eq!(status.smtp_response, Some(String::from("250 ok")));
+    assert_eq!(store.get("bar").expect("get error"), None);
+}
+
+#[test]
+fn unsafe_message_id() {
+    let directory = TemporaryDirectory::new("status");
+    let store = create_store(&directory);
+    let message_id = "<20180601000000.1.DEADBEEF@example.com>/../foo";
+
+    store
+        .update(message_id, |status| {
+            status.sent_at = Some(String::from("2018-06-01T00:00:00.000Z"))
+        })
+        .expect("update error");
+
+    let status = store
+        .get(message_id)
+        .expect("get error")
+        .expect("status should be present");
+    assert_eq!(status.message_id, message_id);
+    assert_eq!(fs::read_dir(&*directory).unwrap().count(), 1);
+}
+
+#[test]
+fn link() {
+    let directory = TemporaryDirectory::new("status");
+    let store = create_store(&directory);
+    store.link("foo", "ses:bar").expect("link error");
+    assert_eq!(store.get("foo").expect("get error"), None);
+
+    store
+        .update("ses:bar", |status| {
+            status.sent_at = Some(String::from("2018-06-01T00:00:00.000Z"))
+        })
+        .expect("update error");
+    store
+        .update("ses:bar", |status| {
+            status.delivered_at = Some(String::from("2018-06-01T00:00:01.000Z"))
+        })
+        .expect("update error");
+
+    let status = store
+        .get("foo")
+        .expect("get error")
+        .expect("status should be present");
+    assert_eq!(status.message_id, "ses:bar");
+    assert_eq!(status.state(), State::Delivered);
+    assert_eq!(store.get("ses:bar").expect("get error"), Some(status));
+    assert_eq!(fs::read_dir(&*directory).unwrap().count(), 2);
+
+    // Links can be chained
+    store.link("baz", "foo").expect("link error");
+    assert_eq!(
+        store
+            .get("baz")
+            .expect("get error")
+            .map(|status| status.message_id),
+        Some(String::from("ses:bar"))
+    );
+}
+
+#[test]
+fn concurrent_updates() {
+    let directory = TemporaryDirectory::new("status");
+    let store = Arc::new(create_store(&directory));
+
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    store
+                        .update("foo", |status| {
+                            let mut response = status.smtp_response.take().unwrap_or_default();
+                            response.push('.');
+                            status.smtp_response = Some(response);
+                        })
+                        .expect("update error");
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().expect("thread error");
+    }
+
+    let status = store
+        .get("foo")
+        .expect("get error")
+        .expect("status should be present");
+    assert_eq!(status.smtp_response.map(|response| response.len()), Some(80));
+    assert_eq!(fs::read_dir(&*directory).unwrap().count(), 1);
+    assert_eq!(store.locks.lock().unwrap().len(), 0);
+}
+
+#[test]
+fn state() {
+    let mut status = MessageStatus::default();
+    assert_eq!(status.state(), State::Sent);
+    status.delivered_at = Some(String::from("2018-06-01T00:00:01.000Z"));
+    assert_eq!(status.state(), State::Delivered);
+    status.complained_at = Some(String::from("2018-06-01T00:00:03.000Z"));
+    assert_eq!(status.state(), State::Complained);
+
+    let status = MessageStatus {
+        bounced_at: Some(String::from("2018-06-01T00:00:02.000Z")),
+        ..MessageStatus::default()
+    };
+    assert_eq!(status.state(), State::Bounced);
+}
+
+/// A status store in `directory`, for tests that need one.
+pub fn create_store(directory: &Path) -> StatusStore {
+    StatusStore::new(&StatusSettings {
+        directory: directory.to_string_lossy().into_owned(),
+    })
+}
@@ -8,19 +8,61 @@ use rusoto_core::Region;
 #[cfg(test)]
 mod test;
 
+/// The providers that messages can be sent with.
+pub const PROVIDERS: &[&str] = &["mailgun", "mock", "recorder", "sendgrid", "ses", "smtp"];
+
 lazy_static! {
     static ref AWS_ACCESS_FORMAT: Regex = Regex::new("^[A-Z0-9]+$").unwrap();
+    static ref AWS_ENDPOINT_FORMAT: Regex =
+        Regex::new("^https?://[A-Za-z0-9-]+(?:\\.[A-Za-z0-9-]+)*(?::[0-9]+)?$").unwrap();
     static ref AWS_SECRET_FORMAT: Regex = Regex::new("^[A-Za-z0-9+/=]+$").unwrap();
     static ref BASE_URI_FORMAT: Regex = Regex::new(
         "^https?://[A-Za-z0-9-]+(?:\\.[A-Za-z0-9-]+)*(?::[0-9]+)?/(?:[A-Za-z0-9-]+/)*$"
     ).unwrap();
+    static ref CATEGORY_FORMAT: Regex = Regex::new("^[ -~]{1,255}$").unwrap();
+    static ref CONTENT_ID_FORMAT: Regex =
+        Regex::new("^[A-Za-z0-9!#$%&'*+/=?^_`{|}~.-]+(?:@[A-Za-z0-9.-]+)?$").unwrap();
+    static ref CONTENT_TYPE_FORMAT: Regex = Regex::new("^(?i)[a-z]+/[a-z0-9.+-]+$").unwrap();
+    static ref DKIM_ALGORITHM_FORMAT: Regex = Regex::new("^(?:ed25519|rsa)-sha256$").unwrap();
+    static ref DKIM_SELECTOR_FORMAT: Regex =
+        Regex::new("^[A-Za-z0-9_-]+(?:\\.[A-Za-z0-9_-]+)*$").unwrap();
     static ref EMAIL_ADDRESS_FORMAT: Regex =
         Regex::new("^[a-z0-9-]+@[a-z0-9-]+(?:\\.[a-z0-9-]+)+$").unwrap();
+    static ref FILENAME_FORMAT: Regex = Regex::new("^[^\\x00-\\x1f\"/\\\\]+$").unwrap();
+    static ref HEADER_NAME_FORMAT: Regex = Regex::new("^[!-9;-~]+$").unwrap();
+    static ref HEADER_VALUE_FORMAT: Regex =
+        Regex::new("^[^\\x00-\\x08\\x0a-\\x1f\\x7f]*$").unwrap();
     static ref HOST_FORMAT: Regex = Regex::new("^[A-Za-z0-9-]+(?:\\.[A-Za-z0-9-]+)*$").unwrap();
-    static ref PROVIDER_FORMAT: Regex = Regex::new("^(?:mock|sendgrid|ses|smtp)$").unwrap();
+    static ref MAILGUN_API_KEY_FORMAT: Regex =
+        Regex::new("^(?:key-)?[0-9a-f]{32}(?:-[0-9a-f]{8}-[0-9a-f]{8})?$").unwrap();
+    static ref METADATA_KEY_FORMAT: Regex = Regex::new("^[A-Za-z0-9_.-]{1,64}$").unwrap();
+    static ref PROVIDER_ERROR_KIND_FORMAT: Regex =
+        Regex::new("^(?:configuration|permanent|transient)$").unwrap();
+    static ref PROVIDER_FORMAT: Regex =
+        Regex::new(&format!("^(?:{})$", PROVIDERS.join("|"))).unwrap();
+    static ref QUEUE_FORMAT: Regex = Regex::new("^(?:local|sqs)$").unwrap();
+    // Headers that are set by this service or by the providers themselves
+    static ref RESERVED_HEADER_NAME_FORMAT: Regex = Regex::new(
+        "^(?i)(?:bcc|cc|content-[a-z-]*|date|dkim-signature|from|message-id|mime-version|received|reply-to|return-path|sender|subject|to)$"
+    ).unwrap();
     static ref SENDER_NAME_FORMAT: Regex =
         Regex::new("^[A-Za-z0-9-]+(?: [A-Za-z0-9-]+)*$").unwrap();
+    static ref SES_CONFIGURATION_SET_FORMAT: Regex =
+        Regex::new("^[A-Za-z0-9_-]{1,64}$").unwrap();
+    static ref SES_TAG_FORMAT: Regex = Regex::new("^[A-Za-z0-9_-]{1,256}$").unwrap();
     static ref SENDGRID_API_KEY_FORMAT: Regex = Regex::new("^[A-Za-z0-9._]{69}$").unwrap();
+    // A base64-encoded P-256 public key, which is the only kind Sendgrid uses
+    static ref SENDGRID_WEBHOOK_KEY_FORMAT: Regex =
+        Regex::new("^MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE[A-Za-z0-9+/]{86}==$").unwrap();
+    static ref SMTP_SECURITY_FORMAT: Regex =
+        Regex::new("^(?:none|opportunistic|required|tls)$").unwrap();
+    static ref SQS_URL_FORMAT: Regex = Regex::new(
+        "^https?://[A-Za-z0-9-]+(?:\\.[A-Za-z0-9-]+)*(?::[0-9]+)?(?:/[A-Za-z0-9_.-]+)+$"
+    ).unwrap();
+}
+
+pub fn aws_endpoint(value: &str) -> bool {
+    AWS_ENDPOINT_FORMAT.is_match(value)
 }
 
 pub fn aws_region(value: &str) -> bool {
@@ -39,18 +81,70 @@ pub fn base_uri(value: &str) -> bool {
     BASE_URI_FORMAT.is_match(value)
 }
 
+pub fn category(value: &str) -> bool {
+    CATEGORY_FORMAT.is_match(value)
+}
+
+pub fn content_id(value: &str) -> bool {
+    CONTENT_ID_FORMAT.is_match(value)
+}
+
+pub fn content_type(value: &str) -> bool {
+    CONTENT_TYPE_FORMAT.is_match(value)
+}
+
+pub fn dkim_algorithm(value: &str) -> bool {
+    DKIM_ALGORITHM_FORMAT.is_match(value)
+}
+
+pub fn dkim_selector(value: &str) -> bool {
+    DKIM_SELECTOR_FORMAT.is_match(value)
+}
+
 pub fn email_address(value: &str) -> bool {
     EMAIL_ADDRESS_FORMAT.is_match(value)
 }
 
+pub fn filename(value: &str) -> bool {
+    FILENAME_FORMAT.is_match(value)
+}
+
+pub fn header_name(value: &str) -> bool {
+    HEADER_NAME_FORMAT.is_match(value) && !RESERVED_HEADER_NAME_FORMAT.is_match(value)
+}
+
+pub fn header_value(value: &str) -> bool {
+    HEADER_VALUE_FORMAT.is_match(value)
+}
+
 pub fn host(value: &str) -> bool {
     HOST_FORMAT.is_match(value)
 }
 
+pub fn mailgun_api_key(value: &str) -> bool {
+    MAILGUN_API_KEY_FORMAT.is_match(value)
+}
+
+pub fn metadata_key(value: &str) -> bool {
+    METADATA_KEY_FORMAT.is_match(value)
+}
+
 pub fn provider(value: &str) -> bool {
     PROVIDER_FORMAT.is_match(value)
 }
 
+pub fn provider_error_kind(value: &str) -> bool {
+    PROVIDER_ERROR_KIND_FORMAT.is_match(value)
+}
+
+pub fn queue(value: &str) -> bool {
+    QUEUE_FORMAT.is_match(value)
+}
+
+pub fn regex(value: &str) -> bool {
+    Regex::new(value).is_ok()
+}
+
 pub fn sender_name(value: &str) -> bool {
     SENDER_NAME_FORMAT.is_match(value)
 }
@@ -58,3 +152,24 @@ pub fn sender_name(value: &str) -> bool {
 pub fn sendgrid_api_key(value: &str) -> bool {
     SENDGRID_API_KEY_FORMAT.is_match(value)
 }
+
+pub fn sendgrid_webhook_key(value: &str) -> bool {
+    SENDGRID_WEBHOOK_KEY_FORMAT.is_match(value)
+}
+
+pub fn ses_configuration_set(value: &str) -> bool {
+    SES_CONFIGURATION_SET_FORMAT.is_match(value)
+}
+
+/// Applies to both the names and values of message tags.
+pub fn ses_tag(value: &str) -> bool {
+    SES_TAG_FORMAT.is_match(value)
+}
+
+pub fn smtp_security(value: &str) -> bool {
+    SMTP_SECURITY_FORMAT.is_match(value)
+}
+
+pub fn sqs_url(value: &str) -> bool {
+    SQS_URL_FORMAT.is_match(value)
+}
@@ -25,6 +25,21 @@ fn invalid_aws_region() {
     assert_eq!(validate::aws_region("eu-east-1"), false);
 }
 
+#[test]
+fn aws_endpoint() {
+    assert!(validate::aws_endpoint("http://localhost"));
+    assert!(validate::aws_endpoint("http://127.0.0.1:9000"));
+    assert!(validate::aws_endpoint("https://email.us-east-1.amazonaws.com"));
+}
+
+#[test]
+fn invalid_aws_endpoint() {
+    assert!(!validate::aws_endpoint("localhost"));
+    assert!(!validate::aws_endpoint("http://localhost/"));
+    assert!(!validate::aws_endpoint("ftp://localhost"));
+    assert!(!validate::aws_endpoint("http://localhost:9000/foo"));
+}
+
 #[test]
 fn aws_access() {
     assert!(validate::aws_access("A0"));
@@ -82,6 +97,79 @@ fn invalid_base_uri() {
     );
 }
 
+#[test]
+fn category() {
+    assert!(validate::category("verify-email"));
+    assert!(validate::category("Account recovery (v2)"));
+    assert!(validate::category(&"a".repeat(255)));
+}
+
+#[test]
+fn invalid_category() {
+    assert!(!validate::category(""));
+    assert!(!validate::category("foo\nbar"));
+    assert!(!validate::category("caf\u{e9}"));
+    assert!(!validate::category(&"a".repeat(256)));
+}
+
+#[test]
+fn content_id() {
+    assert!(validate::content_id("logo"));
+    assert!(validate::content_id("logo.png@firefox.com"));
+    assert!(validate::content_id("part1.0123abcd"));
+}
+
+#[test]
+fn invalid_content_id() {
+    assert!(!validate::content_id("<logo>"));
+    assert!(!validate::content_id("logo png"));
+    assert!(!validate::content_id("logo\r\n"));
+    assert!(!validate::content_id(""));
+}
+
+#[test]
+fn content_type() {
+    assert!(validate::content_type("image/png"));
+    assert!(validate::content_type("application/pdf"));
+    assert!(validate::content_type("image/svg+xml"));
+    assert!(validate::content_type("Text/Calendar"));
+}
+
+#[test]
+fn invalid_content_type() {
+    assert!(!validate::content_type("image"));
+    assert!(!validate::content_type("image/png; charset=utf-8"));
+    assert!(!validate::content_type(" image/png"));
+    assert!(!validate::content_type("image/png\r\n"));
+}
+
+#[test]
+fn dkim_algorithm() {
+    assert!(validate::dkim_algorithm("rsa-sha256"));
+    assert!(validate::dkim_algorithm("ed25519-sha256"));
+}
+
+#[test]
+fn invalid_dkim_algorithm() {
+    assert!(!validate::dkim_algorithm("rsa-sha1"));
+    assert!(!validate::dkim_algorithm("ed25519"));
+    assert!(!validate::dkim_algorithm(" rsa-sha256"));
+}
+
+#[test]
+fn dkim_selector() {
+    assert!(validate::dkim_selector("fxa"));
+    assert!(validate::dkim_selector("20180601.mail_1"));
+}
+
+#[test]
+fn invalid_dkim_selector() {
+    assert!(!validate::dkim_selector(""));
+    assert!(!validate::dkim_selector("foo bar"));
+    assert!(!validate::dkim_selector("foo;"));
+    assert!(!validate::dkim_selector(".foo"));
+}
+
 #[test]
 fn email_address() {
     assert!(validate::email_address("foo@example.com"));
@@ -96,6 +184,56 @@ fn invalid_email_address() {
     assert!(!validate::email_address("foo@example.com "));
 }
 
+#[test]
+fn filename() {
+    assert!(validate::filename("foo.pdf"));
+    assert!(validate::filename("foo bar.png"));
+}
+
+#[test]
+fn invalid_filename() {
+    assert!(!validate::filename(""));
+    assert!(!validate::filename("foo/bar.pdf"));
+    assert!(!validate::filename("foo\\bar.pdf"));
+    assert!(!validate::filename("foo\".pdf"));
+    assert!(!validate::filename("foo\r\n.pdf"));
+}
+
+#[test]
+fn header_name() {
+    assert!(validate::header_name("In-Reply-To"));
+    assert!(validate::header_name("References"));
+    assert!(validate::header_name("List-Id"));
+    assert!(validate::header_name("X-Foo-Bar"));
+}
+
+#[test]
+fn invalid_header_name() {
+    assert!(!validate::header_name(""));
+    assert!(!validate::header_name("X-Foo Bar"));
+    assert!(!validate::header_name("X-Foo:"));
+    assert!(!validate::header_name("X-Foo\r\n"));
+    assert!(!validate::header_name("From"));
+    assert!(!validate::header_name("reply-to"));
+    assert!(!validate::header_name("Content-Type"));
+    assert!(!validate::header_name("MESSAGE-ID"));
+    assert!(!validate::header_name("DKIM-Signature"));
+}
+
+#[test]
+fn header_value() {
+    assert!(validate::header_value(""));
+    assert!(validate::header_value("<foo@example.com>"));
+    assert!(validate::header_value("foo\tbar"));
+}
+
+#[test]
+fn invalid_header_value() {
+    assert!(!validate::header_value("foo\r\nBcc: bar@example.com"));
+    assert!(!validate::header_value("foo\n"));
+    assert!(!validate::header_value("foo\r"));
+}
+
 #[test]
 fn host() {
     assert!(validate::host("foo"));
@@ -113,9 +251,52 @@ fn invalid_host() {
     assert_eq!(validate::host("127.0.0.1:25"), false);
 }
 
+#[test]
+fn mailgun_api_key() {
+    assert!(validate::mailgun_api_key(
+        "key-0123456789abcdef0123456789abcdef"
+    ));
+    assert!(validate::mailgun_api_key(
+        "0123456789abcdef0123456789abcdef-01234567-89abcdef"
+    ));
+}
+
+#[test]
+fn invalid_mailgun_api_key() {
+    assert!(!validate::mailgun_api_key(
+        "key-0123456789abcdef0123456789abcde"
+    ));
+    assert!(!validate::mailgun_api_key(
+        "key-0123456789ABCDEF0123456789ABCDEF"
+    ));
+    assert!(!validate::mailgun_api_key(
+        " key-0123456789abcdef0123456789abcdef"
+    ));
+    assert!(!validate::mailgun_api_key(
+        "0123456789abcdef0123456789abcdef-01234567"
+    ));
+}
+
+#[test]
+fn metadata_key() {
+    assert!(validate::metadata_key("flow_id"));
+    assert!(validate::metadata_key("flow.id-2"));
+}
+
+#[test]
+fn invalid_metadata_key() {
+    assert!(!validate::metadata_key(""));
+    assert!(!validate::metadata_key("flow id"));
+    assert!(!validate::metadata_key("flow:id"));
+    assert!(!validate::metadata_key(&"a".repeat(65)));
+}
+
 #[test]
 fn provider() {
+    assert!(validate::provider("mailgun"));
     assert!(validate::provider("mock"));
+    assert!(validate::provider("recorder"));
+    assert!(validate::provider("sendgrid"));
     assert!(validate::provider("ses"));
     assert!(validate::provider("smtp"));
 }
@@ -128,6 +309,45 @@ fn invalid_provider() {
     assert_eq!(validate::provider(" smtp"), false);
 }
 
+#[test]
+fn provider_error_kind() {
+    assert!(validate::provider_error_kind("configuration"));
+    assert!(validate::provider_error_kind("permanent"));
+    assert!(validate::provider_error_kind("transient"));
+}
+
+#[test]
+fn invalid_provider_error_kind() {
+    assert!(!validate::provider_error_kind("Transient"));
+    assert!(!validate::provider_error_kind("permanent "));
+    assert!(!validate::provider_error_kind("fatal"));
+}
+
+#[test]
+fn queue() {
+    assert!(validate::queue("local"));
+    assert!(validate::queue("sqs"));
+}
+
+#[test]
+fn invalid_queue() {
+    assert!(!validate::queue("SQS"));
+    assert!(!validate::queue("sqs "));
+    assert!(!validate::queue("redis"));
+}
+
+#[test]
+fn regex() {
+    assert!(validate::regex("^foo@"));
+    assert!(validate::regex("(?i)bar.*@example\\.com$"));
+}
+
+#[test]
+fn invalid_regex() {
+    assert!(!validate::regex("foo("));
+    assert!(!validate::regex("*foo"));
+}
+
 #[test]
 fn sender_name() {
     assert!(validate::sender_name("foo"));
@@ -163,3 +383,90 @@ fn invalid_sendgrid_api_key() {
         "1234567890ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz._123456"
     ));
 }
+
+#[test]
+fn sendgrid_webhook_key() {
+    assert!(validate::sendgrid_webhook_key(
+        "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEfuU+70dabBAP2ZU/jQODTgI9QKe2uV8LRuVnrgAWeOZ8PLqWS/SxczSlNqgn6DSwzmKDjXBg2w1mhAlRQrQngg=="
+    ));
+}
+
+#[test]
+fn invalid_sendgrid_webhook_key() {
+    assert!(!validate::sendgrid_webhook_key(
+        "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEfuU+70dabBAP2ZU/jQODTgI9QKe2uV8LRuVnrgAWeOZ8PLqWS/SxczSlNqgn6DSwzmKDjXBg2w1mhAlRQrQngg"
+    ));
+    assert!(!validate::sendgrid_webhook_key(
+        "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEfuU+70dabBAP2ZU/jQODTgI9QKe2uV8LRuVnrgAWeOZ8PLqWS/SxczSlNqgn6DSwzmKDjXBg2w1mhAlRQrQng=="
+    ));
+    assert!(!validate::sendgrid_webhook_key(
+        "MCowBQYDK2VwAyEAfuU+70dabBAP2ZU/jQODTgI9QKe2uV8LRuVnrgAWeOZ8PLqWS/SxczSlNqgn6DSwzmKDjXBg2w1mhAlRQrQngg=="
+    ));
+    assert!(!validate::sendgrid_webhook_key(
+        "-----BEGIN PUBLIC KEY-----MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEfuU+70dabBAP2ZU/jQODTgI9QKe2uV8LRuVnrgAWeOZ8PLqWS/SxczSlNqgn6DSwzmKDjXBg2w1mhAlRQrQngg=="
+    ));
+}
+
+#[test]
+fn ses_configuration_set() {
+    assert!(validate::ses_configuration_set("foo"));
+    assert!(validate::ses_configuration_set("Foo_Bar-1"));
+    assert!(validate::ses_configuration_set(&"a".repeat(64)));
+}
+
+#[test]
+fn invalid_ses_configuration_set() {
+    assert!(!validate::ses_configuration_set(""));
+    assert!(!validate::ses_configuration_set("foo bar"));
+    assert!(!validate::ses_configuration_set("foo.bar"));
+    assert!(!validate::ses_configuration_set(&"a".repeat(65)));
+}
+
+#[test]
+fn ses_tag() {
+    assert!(validate::ses_tag("foo"));
+    assert!(validate::ses_tag("Foo_Bar-1"));
+    assert!(validate::ses_tag(&"a".repeat(256)));
+}
+
+#[test]
+fn invalid_ses_tag() {
+    assert!(!validate::ses_tag(""));
+    assert!(!validate::ses_tag("foo bar"));
+    assert!(!validate::ses_tag("foo:bar"));
+    assert!(!validate::ses_tag(&"a".repeat(257)));
+}
+
+#[test]
+fn smtp_security() {
+    assert!(validate::smtp_security("none"));
+    assert!(validate::smtp_security("opportunistic"));
+    assert!(validate::smtp_security("required"));
+    assert!(validate::smtp_security("tls"));
+}
+
+#[test]
+fn invalid_smtp_security() {
+    assert!(!validate::smtp_security("Required"));
+    assert!(!validate::smtp_security("tls "));
+    assert!(!validate::smtp_security("starttls"));
+}
+
+#[test]
+fn sqs_url() {
+    assert!(validate::sqs_url(
+        "https://sqs.us-east-1.amazonaws.com/123456789012/fxa-email-bounce"
+    ));
+    assert!(validate::sqs_url(
+        "http://127.0.0.1:4100/100010001000/fxa-email-bounce"
+    ));
+    assert!(validate::sqs_url("http://localhost:9324/queue/foo.fifo"));
+}
+
+#[test]
+fn invalid_sqs_url() {
+    assert!(!validate::sqs_url("sqs.us-east-1.amazonaws.com/123456789012/foo"));
+    assert!(!validate::sqs_url("https://sqs.us-east-1.amazonaws.com"));
+    assert!(!validate::sqs_url("https://sqs.us-east-1.amazonaws.com/"));
+    assert!(!validate::sqs_url("https://sqs.us-east-1.amazonaws.com/123456789012/foo?bar"));
+}
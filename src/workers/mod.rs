@@ -0,0 +1,92 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    error::Error, fmt::{self, Display, Formatter},
+    sync::{
+        mpsc::{self, SyncSender, TrySendError}, Arc, Mutex,
+    },
+    thread,
+};
+
+#[cfg(test)]
+mod test;
+
+#[derive(Debug, PartialEq)]
+pub enum QueueError {
+    /// Every worker is busy and the queue is at capacity.
+    Full,
+    /// All of the workers have died.
+    Disconnected,
+}
+
+impl Error for QueueError {
+    fn description(&self) -> &str {
+        match self {
+            QueueError::Full => "queue is full",
+            QueueError::Disconnected => "queue is disconnected",
+        }
+    }
+}
+
+impl Display for QueueError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description())
+    }
+}
+
+/// A fixed-size pool of worker threads,
+/// fed from a bounded queue of jobs.
+pub struct WorkerPool<T> {
+    sender: Mutex<SyncSender<T>>,
+}
+
+impl<T> WorkerPool<T>
+where
+    T: Send + 'static,
+{
+    pub fn new<F>(size: usize, capacity: usize, handler: F) -> WorkerPool<T>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        for index in 0..size {
+            let receiver = receiver.clone();
+            let handler = handler.clone();
+            thread::Builder::new()
+                .name(format!("worker-{}", index))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for a job,
+                    // so other workers are free to pick up jobs
+                    // while this one is busy.
+                    let job = receiver.lock().expect("receiver lock error").recv();
+                    match job {
+                        Ok(job) => handler(job),
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to spawn worker thread");
+        }
+
+        WorkerPool {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    /// Queue a job without blocking,
+    /// failing if the queue is already full.
+    pub fn submit(&self, job: T) -> Result<(), QueueError> {
+        self.sender
+            .lock()
+            .expect("sender lock error")
+            .try_send(job)
+            .map_err(|error| match error {
+                TrySendError::Full(_) => QueueError::Full,
+                TrySendError::Disconnected(_) => QueueError::Disconnected,
+            })
+    }
+}
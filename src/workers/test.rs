@@ -0,0 +1,68 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{sync::mpsc, time::Duration};
+
+use super::*;
+
+#[test]
+fn processes_jobs() {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    let pool = WorkerPool::new(2, 10, move |job: u32| {
+        sender
+            .lock()
+            .unwrap()
+            .send(job * 2)
+            .expect("channel error");
+    });
+
+    for job in 0..10 {
+        assert_eq!(pool.submit(job), Ok(()));
+    }
+
+    let mut results: Vec<u32> = (0..10)
+        .map(|_| {
+            receiver
+                .recv_timeout(Duration::from_secs(5))
+                .expect("timed out")
+        })
+        .collect();
+    results.sort();
+    assert_eq!(results, (0..10).map(|job| job * 2).collect::<Vec<u32>>());
+}
+
+#[test]
+fn full_queue() {
+    let (started_sender, started_receiver) = mpsc::channel();
+    let (release_sender, release_receiver) = mpsc::channel::<()>();
+    let started_sender = Mutex::new(started_sender);
+    let release_receiver = Mutex::new(release_receiver);
+    let pool = WorkerPool::new(1, 1, move |job: u32| {
+        started_sender
+            .lock()
+            .unwrap()
+            .send(job)
+            .expect("channel error");
+        release_receiver.lock().unwrap().recv().expect("channel error");
+    });
+
+    // Wait until the only worker is busy, then fill the queue
+    assert_eq!(pool.submit(1), Ok(()));
+    assert_eq!(
+        started_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(1)
+    );
+    assert_eq!(pool.submit(2), Ok(()));
+    assert_eq!(pool.submit(3), Err(QueueError::Full));
+
+    release_sender.send(()).expect("channel error");
+    assert_eq!(
+        started_receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(2)
+    );
+    assert_eq!(pool.submit(4), Ok(()));
+    release_sender.send(()).expect("channel error");
+    release_sender.send(()).expect("channel error");
+}